@@ -0,0 +1,33 @@
+#!/usr/bin/env python3
+"""A minimal external Coup bot.
+
+It answers the handshake and then always picks the first option it is
+offered, which is the safe choice for every decision. Useful for checking
+the protocol end to end and as a starting point for real bots:
+
+    cargo run -- --num-local-players 0 --external-cpu "Echo=python3 bots/echo_bot.py"
+"""
+import json
+import sys
+
+PROTOCOL_VERSION = 1
+
+
+def send(message):
+    sys.stdout.write(json.dumps(message) + "\n")
+    sys.stdout.flush()
+
+
+def main():
+    for line in sys.stdin:
+        message = json.loads(line)
+        kind = message["type"]
+        if kind == "hello":
+            send({"type": "hello", "protocol_version": PROTOCOL_VERSION, "name": "echo"})
+        elif kind == "decide":
+            send({"type": "choice", "id": message["id"], "index": 0})
+        # Events need no reply
+
+
+if __name__ == "__main__":
+    main()
@@ -1,8 +1,9 @@
-use crate::PlayerID;
+use crate::{Identity, PlayerID};
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Action {
     Income,
     ForeignAid,
@@ -47,10 +48,30 @@ impl Action {
     }
     // Defines if an action is challengable
     pub fn challengable(&self) -> bool {
-        match self {
-            Action::Income | Action::ForeignAid | Action::Coup(..) => false,
-            _ => true,
-        }
+        !matches!(self, Action::Income | Action::ForeignAid | Action::Coup(..))
+    }
+
+    // The identity you claim to have by taking this action, if any
+    pub fn claimed_identity(&self) -> Option<Identity> {
+	match self {
+	    Action::Income | Action::ForeignAid | Action::Coup(..) => None,
+	    Action::Assassinate(..) => Some(Identity::Assassin),
+	    Action::Tax => Some(Identity::Duke),
+	    Action::Exchange => Some(Identity::Ambassador),
+	    Action::BlockForeignAid => Some(Identity::Duke),
+	    Action::BlockAssassination => Some(Identity::Contessa),
+	    Action::Steal(..) => Some(Identity::Captain),
+	    Action::BlockStealCaptain => Some(Identity::Captain),
+	    Action::BlockStealAmbassador => Some(Identity::Ambassador),
+	}
+    }
+
+    // Whether a hand holds the identity needed for this action
+    pub fn can_be_done_with(&self, hand: &[Identity]) -> bool {
+	match self.claimed_identity() {
+	    Some(identity) => hand.contains(&identity),
+	    None => true,
+	}
     }
 }
 
@@ -69,7 +90,6 @@ impl fmt::Display for Action {
 	    Action::BlockAssassination => "Contess".to_string(),
 	    Action::BlockStealCaptain => "Block as Captain".to_string(),
 	    Action::BlockStealAmbassador => "Block as Ambassador".to_string(),
-	    _ => format!("{:?}", self)
 	};
         write!(f, "{}", to_write)
     }
@@ -0,0 +1,77 @@
+//! Just enough HTTP/1.1 for the API: one request per connection, with JSON bodies.
+
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use std::io::{BufRead, Write};
+
+/// The largest body the API will read
+const MAX_BODY: usize = 1 << 20;
+
+pub struct Request {
+    pub method: String,
+    /// The path, without any query string
+    pub path: String,
+    pub body: Vec<u8>,
+}
+
+pub fn read_request(reader: &mut impl BufRead) -> Result<Request> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target.to_string()),
+        _ => return Err(anyhow!("Malformed request line '{}'", line.trim())),
+    };
+    let path = target.split('?').next().unwrap_or_default().to_string();
+
+    let mut content_length = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(anyhow!("Connection closed before the end of the headers"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| anyhow!("Bad Content-Length '{}'", value.trim()))?;
+            }
+        }
+    }
+    if content_length > MAX_BODY {
+        return Err(anyhow!("Body of {} bytes is too large", content_length));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    Ok(Request { method, path, body })
+}
+
+pub fn write_response(writer: &mut impl Write, status: u16, body: &Value) -> Result<()> {
+    let body = serde_json::to_string_pretty(body)?;
+    write!(
+        writer,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason(status),
+        body.len(),
+        body
+    )?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        404 => "Not Found",
+        409 => "Conflict",
+        _ => "Internal Server Error",
+    }
+}
@@ -0,0 +1,502 @@
+//! An HTTP JSON API for creating and driving games, so other tools can play without linking
+//! the engine.
+//!
+//! `coup api` listens on 127.0.0.1:4041 unless told otherwise. Games are kept between requests
+//! and only move when asked to: each is played on through the built-in bots' decisions until a
+//! seat played through the API has a decision to make, and waits there until the decision is
+//! submitted. Every game is held by a single thread, which answers the requests one at a time.
+//!
+//! | Request | |
+//! |---|---|
+//! | `POST /games` | Creates a game. Takes `players`, a list of `{"name": ..., "bot": ...}` where seats without a bot are played through the API, and optionally `rules` (changes to the default rules, by field name), `seed` and `turn_limit` |
+//! | `GET /games` | Every game, as below |
+//! | `GET /games/ID` | A game's status, its players' public info, the decisions it is waiting on, and the winner and how many times each seat ran out of time once it's over |
+//! | `GET /games/ID/decisions` | The decisions the game is waiting on |
+//! | `GET /games/ID/seats/SEAT` | Everything the seat can see, and its decision if it has one |
+//! | `POST /games/ID/seats/SEAT/decision` | Makes the seat's decision, given as `{"id": ..., "index": ...}` where index picks one of the decision's options. Replies once the game is waiting again |
+//! | `DELETE /games/ID` | Calls off the game where it stands, and forgets it |
+//!
+//! Decisions carry what is being decided (`pending`) and the legal `options`, written the same
+//! way as for external bots (see `external_player`). Errors come back as `{"error": ...}`.
+//!
+//! The API trusts whoever can reach it with every seat, which is why it only listens on
+//! localhost by default.
+
+mod http;
+
+use crate::event::Event;
+use crate::net;
+use crate::rules::Rules;
+use crate::seat::Choice;
+use crate::step;
+use crate::tournament::BotEntry;
+use crate::view::{PendingDecision, PublicPlayerInfo};
+use crate::{Game, Identity, LoggerType, PlayerConfig, PlayerID, PlayerType};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::BufReader;
+use std::net::{TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::Duration;
+use structopt::StructOpt;
+
+/// How long a connection gets to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(StructOpt, Debug)]
+pub struct ApiConfig {
+    /// The address to listen on
+    #[structopt(long, default_value = "127.0.0.1:4041")]
+    bind: String,
+}
+
+pub fn run(config: &ApiConfig) -> Result<()> {
+    let listener = TcpListener::bind(&config.bind)?;
+    println!("Serving the API on http://{}", listener.local_addr()?);
+    serve(listener);
+    Ok(())
+}
+
+/// A request read off a connection, and where to send the reply
+type Job = (http::Request, Sender<(u16, Value)>);
+
+// Each connection is read on its own thread, so a slow client holds nobody else up, and handed
+// to the thread holding the games
+fn serve(listener: TcpListener) {
+    let (jobs, incoming) = mpsc::channel::<Job>();
+    thread::spawn(move || {
+        let mut lobby = Lobby::default();
+        for (request, reply) in incoming {
+            let _ = reply.send(answer(&mut lobby, &request));
+        }
+    });
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let jobs = jobs.clone();
+                thread::spawn(move || {
+                    if let Err(e) = handle(stream, &jobs) {
+                        log::warn!("Unable to answer a request: {}", e);
+                    }
+                });
+            }
+            Err(e) => log::warn!("Unable to accept a connection: {}", e),
+        }
+    }
+}
+
+fn handle(stream: TcpStream, jobs: &Sender<Job>) -> Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    let (status, body) = match http::read_request(&mut reader) {
+        Ok(request) => {
+            let (reply, answered) = mpsc::channel();
+            jobs.send((request, reply))?;
+            answered.recv()?
+        }
+        Err(e) => (400, json!({ "error": e.to_string() })),
+    };
+    http::write_response(&mut writer, status, &body)
+}
+
+fn answer(lobby: &mut Lobby, request: &http::Request) -> (u16, Value) {
+    match route(lobby, &request.method, &request.path, &request.body) {
+        Ok(reply) => reply,
+        Err(failure) => (failure.status, json!({ "error": failure.message })),
+    }
+}
+
+/// A request that couldn't be answered, and the status to say so with
+struct Failure {
+    status: u16,
+    message: String,
+}
+
+impl Failure {
+    fn new(status: u16, message: impl fmt::Display) -> Self {
+        Failure {
+            status,
+            message: message.to_string(),
+        }
+    }
+}
+
+type Reply = std::result::Result<(u16, Value), Failure>;
+
+fn route(lobby: &mut Lobby, method: &str, path: &str, body: &[u8]) -> Reply {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match (method, segments.as_slice()) {
+        ("POST", ["games"]) => create_game(lobby, body),
+        ("GET", ["games"]) => {
+            let games = lobby.games.iter().map(|(id, table)| summary(*id, table)).collect();
+            Ok((200, Value::Array(games)))
+        }
+        ("GET", ["games", game]) => {
+            let (id, table) = find_game(lobby, game)?;
+            Ok((200, summary(id, table)))
+        }
+        ("DELETE", ["games", game]) => {
+            let (id, table) = find_game(lobby, game)?;
+            let reply = summary(id, table);
+            lobby.games.remove(&id);
+            Ok((200, reply))
+        }
+        ("GET", ["games", game, "decisions"]) => {
+            let (_, table) = find_game(lobby, game)?;
+            Ok((200, json!(decisions(table))))
+        }
+        ("GET", ["games", game, "seats", seat]) => {
+            let (_, table) = find_game(lobby, game)?;
+            let seat = find_seat(table, seat)?;
+            Ok((200, json!(seat_view(table, &seat))))
+        }
+        ("POST", ["games", game, "seats", seat, "decision"]) => {
+            let (id, table) = find_game(lobby, game)?;
+            let submitted: Submission = serde_json::from_slice(body).map_err(|e| Failure::new(400, e))?;
+            let seat = find_seat(table, seat)?;
+            let decision = match &table.decision {
+                Some(decision) if decision.seat == seat && decision.id == submitted.id => decision,
+                _ => {
+                    return Err(Failure::new(
+                        409,
+                        format!("Seat {} has no decision {} to make", seat.0, submitted.id),
+                    ))
+                }
+            };
+            let choice = decision.options.get(submitted.index).cloned().ok_or_else(|| {
+                Failure::new(
+                    400,
+                    format!(
+                        "Decision {} has {} options, there is no option {}",
+                        decision.id,
+                        decision.options.len(),
+                        submitted.index
+                    ),
+                )
+            })?;
+            table.play_on(Some(choice));
+            Ok((200, summary(id, table)))
+        }
+        _ => Err(Failure::new(404, format!("Nothing at {} {}", method, path))),
+    }
+}
+
+fn find_game<'a>(lobby: &'a mut Lobby, game: &str) -> std::result::Result<(u64, &'a mut Table), Failure> {
+    let not_found = || Failure::new(404, format!("There is no game {}", game));
+    let id: u64 = game.parse().map_err(|_| not_found())?;
+    let table = lobby.games.get_mut(&id).ok_or_else(not_found)?;
+    Ok((id, table))
+}
+
+fn find_seat(table: &Table, seat: &str) -> std::result::Result<PlayerID, Failure> {
+    let not_found = || Failure::new(404, format!("There is no seat {}", seat));
+    let seat = PlayerID(seat.parse().map_err(|_| not_found())?);
+    if table.game.state.turn_order.contains(&seat) {
+        Ok(seat)
+    } else {
+        Err(not_found())
+    }
+}
+
+/// Every game the API knows about
+#[derive(Default)]
+struct Lobby {
+    next_game: u64,
+    games: BTreeMap<u64, Table>,
+}
+
+/// A game being played through the API. Between requests it is always either waiting on one
+/// of the API's seats, or over.
+struct Table {
+    game: Game,
+    // The seats played through the API. The game stops for their decisions, and the bots in
+    // every other seat decide for themselves.
+    api_seats: Vec<PlayerID>,
+    // The game plays one decision at a time, so at most one is ever waiting
+    decision: Option<Decision>,
+    next_decision: u64,
+    // A bot panicked, so the game can't go on
+    crashed: bool,
+}
+
+/// A decision waiting on a seat played through the API
+struct Decision {
+    id: u64,
+    seat: PlayerID,
+    pending: PendingDecision,
+    options: Vec<Choice>,
+}
+
+impl Table {
+    /// Makes the waiting decision, if given one, then plays the bots' decisions until one of
+    /// the API's seats has to decide or the game ends
+    fn play_on(&mut self, choice: Option<Choice>) {
+        let game = &mut self.game;
+        let api_seats = &self.api_seats;
+        let waiting = panic::catch_unwind(AssertUnwindSafe(|| {
+            if let Some(choice) = choice {
+                game.submit(choice).expect("Only the decision's own options are submitted");
+            }
+            game.play_until(|seat| api_seats.contains(seat))
+        }));
+        self.decision = match waiting {
+            Ok(Some(step::Decision { seat, pending, options })) => {
+                self.next_decision += 1;
+                Some(Decision {
+                    id: self.next_decision,
+                    seat,
+                    pending,
+                    options,
+                })
+            }
+            Ok(None) => None,
+            Err(_) => {
+                self.crashed = true;
+                None
+            }
+        };
+    }
+}
+
+/// What `POST /games` takes
+#[derive(Deserialize)]
+struct NewGame {
+    players: Vec<SeatConfig>,
+    #[serde(default)]
+    rules: Map<String, Value>,
+    seed: Option<u64>,
+    turn_limit: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct SeatConfig {
+    name: String,
+    /// As TYPE or "random WEIGHTS". Seats without one are played through the API
+    bot: Option<String>,
+}
+
+/// What `POST /games/ID/seats/SEAT/decision` takes
+#[derive(Deserialize)]
+struct Submission {
+    id: u64,
+    index: usize,
+}
+
+fn create_game(lobby: &mut Lobby, body: &[u8]) -> Reply {
+    let new_game: NewGame = serde_json::from_slice(body).map_err(|e| Failure::new(400, e))?;
+    // Setting up runs the bots' constructors and the deal, so a panic there is caught as it is
+    // in play, rather than taking down the thread that holds every game
+    let table = panic::catch_unwind(move || start(new_game))
+        .map_err(|_| Failure::new(500, "The game crashed while being set up"))?;
+    let mut table = table.map_err(|e| Failure::new(400, e))?;
+    table.play_on(None);
+    lobby.next_game += 1;
+    let id = lobby.next_game;
+    let reply = summary(id, &table);
+    lobby.games.insert(id, table);
+    Ok((201, reply))
+}
+
+// Sets up the game, without playing any of it. Fails if the game can't be set up.
+fn start(new_game: NewGame) -> Result<Table> {
+    let rules = Rules::default().with_changes(&new_game.rules)?;
+    let mut players = Vec::new();
+    let mut api_seats = Vec::new();
+    for (seat, config) in new_game.players.into_iter().enumerate() {
+        let player_type = match &config.bot {
+            Some(bot) => bot.parse::<BotEntry>()?.player_type,
+            None => {
+                api_seats.push(PlayerID(seat as u8));
+                // Only holds the seat, since the game never asks it anything
+                PlayerType::DumbCPU
+            }
+        };
+        players.push(PlayerConfig::new(player_type, config.name));
+    }
+    let seed = new_game.seed.unwrap_or_else(rand::random);
+    let mut game = Game::new(rules, players, LoggerType::Quiet, seed)?;
+    if let Some(turn_limit) = new_game.turn_limit {
+        game.set_turn_limit(turn_limit);
+    }
+    Ok(Table {
+        game,
+        api_seats,
+        decision: None,
+        next_decision: 0,
+        crashed: false,
+    })
+}
+
+fn summary(id: u64, table: &Table) -> Value {
+    let result = table.game.result();
+    let status = match (result, table.crashed) {
+        (Some(_), _) => "over",
+        (None, true) => "crashed",
+        (None, false) => "waiting",
+    };
+    json!({
+        "id": id,
+        "status": status,
+        "turn": table.game.state.turn,
+        "players": PublicPlayerInfo::for_table(&table.game.state),
+        "decisions": decisions(table),
+        "winner": result.and_then(|result| result.winner.clone()),
+        "timeouts": result.map(|result| &result.timeouts),
+    })
+}
+
+/// A decision as the API shows it
+#[derive(Serialize)]
+struct DecisionInfo<'a> {
+    id: u64,
+    seat: &'a PlayerID,
+    pending: &'a PendingDecision,
+    options: Vec<Value>,
+}
+
+impl<'a> From<&'a Decision> for DecisionInfo<'a> {
+    fn from(decision: &'a Decision) -> Self {
+        DecisionInfo {
+            id: decision.id,
+            seat: &decision.seat,
+            pending: &decision.pending,
+            options: decision
+                .options
+                .iter()
+                .map(|choice| net::encode(choice.clone(), &decision.pending))
+                .collect(),
+        }
+    }
+}
+
+fn decisions(table: &Table) -> Vec<DecisionInfo<'_>> {
+    table.decision.iter().map(DecisionInfo::from).collect()
+}
+
+/// Everything a seat can see, between decisions as well as during them
+#[derive(Serialize)]
+struct SeatView<'a> {
+    me: &'a PlayerID,
+    hand: &'a [Identity],
+    players: Vec<PublicPlayerInfo>,
+    deck_size: usize,
+    turn: u32,
+    history: &'a [Event],
+    rules: Rules,
+    decision: Option<DecisionInfo<'a>>,
+}
+
+fn seat_view<'a>(table: &'a Table, seat: &'a PlayerID) -> Option<SeatView<'a>> {
+    let game = &table.game;
+    Some(SeatView {
+        me: seat,
+        hand: game.driver.hands.get(seat)?,
+        players: PublicPlayerInfo::for_table(&game.state),
+        deck_size: game.driver.field.deck.len(),
+        turn: game.state.turn,
+        history: &game.state.history,
+        rules: game.state.rules,
+        decision: table
+            .decision
+            .as_ref()
+            .filter(|decision| &decision.seat == seat)
+            .map(DecisionInfo::from),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    // Sends a request and returns the status and the JSON reply
+    fn request(address: &str, method: &str, path: &str, body: Value) -> (u16, Value) {
+        let mut stream = TcpStream::connect(address).unwrap();
+        let body = body.to_string();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            address,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response.split_whitespace().nth(1).unwrap().parse().unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    #[test]
+    fn games_are_driven_to_the_end_over_http() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || serve(listener));
+
+        let new_game = json!({
+            "players": [{"name": "Ana"}, {"name": "Bea"}, {"name": "Bot", "bot": "honest"}],
+            "rules": {"starting_coins": 4},
+            "seed": 11,
+        });
+        let (status, game) = request(&address, "POST", "/games", new_game);
+        assert_eq!(status, 201, "{}", game);
+        assert_eq!(game["players"][0]["coins"], 4);
+        let path = format!("/games/{}", game["id"]);
+
+        let mut game = game;
+        let mut decisions = 0;
+        while game["status"] == "waiting" {
+            let decision = &game["decisions"][0];
+            let seat = decision["seat"].as_u64().unwrap();
+            let (status, view) = request(&address, "GET", &format!("{}/seats/{}", path, seat), Value::Null);
+            assert_eq!(status, 200);
+            assert_eq!(&view["decision"], decision);
+
+            // Only the seat that's deciding can answer, and only with one of its options
+            let other = (seat + 1) % 2;
+            let answer = json!({"id": decision["id"], "index": 0});
+            let (status, _) = request(&address, "POST", &format!("{}/seats/{}/decision", path, other), answer);
+            assert_eq!(status, 409);
+            let options = decision["options"].as_array().unwrap().len();
+            let answer = json!({"id": decision["id"], "index": options});
+            let (status, _) = request(&address, "POST", &format!("{}/seats/{}/decision", path, seat), answer);
+            assert_eq!(status, 400);
+
+            let answer = json!({"id": decision["id"], "index": decisions % options});
+            let (status, next) = request(&address, "POST", &format!("{}/seats/{}/decision", path, seat), answer);
+            assert_eq!(status, 200, "{}", next);
+            game = next;
+            decisions += 1;
+        }
+        assert_eq!(game["status"], "over");
+        assert!(decisions > 0);
+        assert_eq!(request(&address, "GET", &path, Value::Null).1, game);
+
+        let (status, _) = request(&address, "DELETE", &path, Value::Null);
+        assert_eq!(status, 200);
+        let (status, _) = request(&address, "GET", &path, Value::Null);
+        assert_eq!(status, 404);
+    }
+
+    #[test]
+    fn games_too_big_to_seat_are_refused_and_the_api_keeps_serving() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || serve(listener));
+
+        let players: Vec<Value> = (0..300).map(|seat| json!({"name": format!("Bot {}", seat), "bot": "dumb"})).collect();
+        let new_game = json!({"players": players, "rules": {"cards_per_identity": 255}});
+        let (status, reply) = request(&address, "POST", "/games", new_game);
+        assert_eq!(status, 400, "{}", reply);
+        assert_eq!(request(&address, "GET", "/games", Value::Null).0, 200);
+    }
+}
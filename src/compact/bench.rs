@@ -0,0 +1,281 @@
+//! Times copying and stepping a CompactState against doing the same with the structures a
+//! Game runs on.
+//!
+//! Random lines of play are generated up front from a real deal. Each is then played out three
+//! ways: by cloning the game's own state, hands and deck and changing them through their hash
+//! maps, by copying a CompactState and applying the moves, and by applying the moves to a single
+//! CompactState and undoing them again. The game's history isn't added to as the moves are
+//! played, so the first of these is, if anything, flattered.
+
+use super::{CompactState, Move, Pending, Phase};
+use crate::rules::Rules;
+use crate::{Game, GameRng, GameState, Identity, LoggerType, PlayerConfig, PlayerID, PlayerType};
+use anyhow::{anyhow, Result};
+use rand::{Rng, SeedableRng};
+use std::cmp::min;
+use std::collections::{HashMap, HashSet};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct BenchConfig {
+    /// Players at the table
+    #[structopt(long, default_value = "4")]
+    seats: usize,
+    /// Lines of play to time
+    #[structopt(long, default_value = "20000")]
+    playouts: usize,
+    /// The most turns in each line of play
+    #[structopt(long, default_value = "30")]
+    depth: u32,
+    /// Seed for the deal and the lines of play. A random one is picked (and printed) if not given
+    #[structopt(long)]
+    seed: Option<u64>,
+}
+
+/// The state a Game keeps, cloned as a whole
+#[derive(Clone)]
+pub(super) struct Legacy {
+    state: GameState,
+    hands: HashMap<PlayerID, Vec<Identity>>,
+    deck: Vec<Identity>,
+    to_move: PlayerID,
+}
+
+impl Legacy {
+    pub(super) fn compact(&self) -> Result<CompactState> {
+        CompactState::from_game(&self.state, &self.hands, &self.deck, &self.to_move)
+    }
+
+    // The same changes as CompactState::apply, made the way Game makes them
+    pub(super) fn step(&mut self, mv: Move) {
+        let rules = self.state.rules;
+        match mv {
+            Move::Collect(coins) => {
+                let player = self.state.player_states.get_mut(&self.to_move).unwrap();
+                player.num_coins = player.num_coins.saturating_add(coins);
+            }
+            Move::Pay(coins) => {
+                let player = self.state.player_states.get_mut(&self.to_move).unwrap();
+                player.num_coins -= min(coins, player.num_coins);
+            }
+            Move::Steal(victim) => {
+                let room = u8::MAX - self.state.player_states[&self.to_move].num_coins;
+                let victim = self.state.player_states.get_mut(&PlayerID(victim)).unwrap();
+                let stolen = min(min(victim.num_coins, rules.steal_amount), room);
+                victim.num_coins -= stolen;
+                self.state.player_states.get_mut(&self.to_move).unwrap().num_coins += stolen;
+            }
+            Move::Reveal(seat, identity) => {
+                let id = PlayerID(seat);
+                let hand = self.hands.get_mut(&id).unwrap();
+                let index = hand.iter().position(|&card| card == identity).unwrap();
+                let card = hand.remove(index);
+                let player = self.state.player_states.get_mut(&id).unwrap();
+                player.lost_lives.push(card);
+                player.num_lives -= 1;
+            }
+            Move::Swap { give, take } => {
+                let hand = self.hands.get_mut(&self.to_move).unwrap();
+                let index = hand.iter().position(|&card| card == give).unwrap();
+                hand[index] = std::mem::replace(&mut self.deck[take as usize], give);
+            }
+            // A Game keeps no record of where a turn is up to
+            Move::Phase(_) => {}
+            Move::Pass => {
+                let order = &self.state.turn_order;
+                let seat = order.iter().position(|id| *id == self.to_move).unwrap();
+                let next = (1..=order.len())
+                    .map(|offset| &order[(seat + offset) % order.len()])
+                    .find(|id| self.state.player_states[*id].is_alive())
+                    .unwrap_or(&self.to_move);
+                self.to_move = next.clone();
+                self.state.turn += 1;
+            }
+        }
+    }
+}
+
+/// Deals a game, times the three ways of playing it out and prints how they compare
+pub fn run(config: &BenchConfig, rules: Rules) -> Result<()> {
+    rules.check(config.seats)?;
+    if config.playouts == 0 {
+        return Err(anyhow!("Nothing to time"));
+    }
+    let seed = config.seed.unwrap_or_else(rand::random);
+    let (legacy, compact) = deal(config.seats, rules, seed)?;
+
+    let mut rng = GameRng::seed_from_u64(seed);
+    let playouts: Vec<Vec<Move>> = (0..config.playouts)
+        .map(|_| playout(compact, config.depth, &mut rng))
+        .collect();
+    let (positions, info_sets) = count_positions(&compact, &playouts);
+    let moves: usize = playouts.iter().map(Vec::len).sum();
+
+    let cloned = time(|| {
+        for moves in &playouts {
+            let mut state = legacy.clone();
+            for &mv in moves {
+                state.step(mv);
+            }
+            black_box(&state);
+        }
+    });
+    let copied = time(|| {
+        for moves in &playouts {
+            let mut state = compact;
+            for &mv in moves {
+                state.apply(mv);
+            }
+            black_box(&state);
+        }
+    });
+    let mut state = compact;
+    let mut undos = Vec::with_capacity(config.depth as usize * 4);
+    let undone = time(|| {
+        for moves in &playouts {
+            for &mv in moves {
+                undos.push(state.apply(mv));
+            }
+            black_box(&state);
+            while let Some(undo) = undos.pop() {
+                state.undo(undo);
+            }
+        }
+    });
+
+    println!("Seed: {}", seed);
+    println!(
+        "{} lines of play, {} moves, from a {} player deal. A compact state is {} bytes",
+        playouts.len(),
+        moves,
+        config.seats,
+        std::mem::size_of::<CompactState>()
+    );
+    println!(
+        "{} different positions, {} different information sets for the first seat",
+        positions, info_sets
+    );
+    println!();
+    println!("{:<28} {:>10} {:>12} {:>10} {:>8}", "Method", "Total ms", "ns per line", "ns per move", "Speedup");
+    for (name, elapsed) in &[
+        ("Clone game state and step", cloned),
+        ("Copy compact state and step", copied),
+        ("Compact apply and undo", undone),
+    ] {
+        let nanos = elapsed.as_nanos() as f64;
+        println!(
+            "{:<28} {:>10.1} {:>12.0} {:>10.1} {:>7.1}x",
+            name,
+            nanos / 1e6,
+            nanos / playouts.len() as f64,
+            nanos / moves as f64,
+            cloned.as_nanos() as f64 / nanos
+        );
+    }
+    Ok(())
+}
+
+/// Deals a game of dumb bots, as both the state a Game keeps and a CompactState
+pub(super) fn deal(seats: usize, rules: Rules, seed: u64) -> Result<(Legacy, CompactState)> {
+    let players = (0..seats)
+        .map(|seat| PlayerConfig::new(PlayerType::DumbCPU, format!("Player {}", seat + 1)))
+        .collect();
+    let mut game = Game::new(rules, players, LoggerType::Quiet, seed)?;
+    game.setup();
+    let first = game.state.turn_order[0].clone();
+    let legacy = Legacy {
+        state: game.state,
+        hands: game.driver.hands,
+        deck: game.driver.field.deck,
+        to_move: first,
+    };
+    let compact = legacy.compact()?;
+    Ok((legacy, compact))
+}
+
+/// A random but legal line of play, a turn at a time
+pub(super) fn playout(mut state: CompactState, depth: u32, rng: &mut GameRng) -> Vec<Move> {
+    let rules = *state.rules();
+    let mut moves = Vec::new();
+    let mut play = |state: &mut CompactState, mv: Move| {
+        state.apply(mv);
+        moves.push(mv);
+    };
+    for _ in 0..depth {
+        if state.is_over() {
+            break;
+        }
+        let actor = state.current_seat();
+        let me = *state.seat(actor);
+        let others: Vec<u8> = (0..state.seats().len() as u8)
+            .filter(|&seat| seat != actor && state.seat(seat).is_alive())
+            .collect();
+        let target = others[rng.gen_range(0, others.len())];
+        let victim = *state.seat(target);
+        let lost = victim.hand.cards().nth(rng.gen_range(0, victim.hand.len() as usize)).unwrap();
+        if me.coins >= rules.required_coup_coins {
+            play(&mut state, Move::Pay(rules.coup_cost));
+            play(&mut state, Move::Phase(Phase::LoseCard(target)));
+            play(&mut state, Move::Reveal(target, lost));
+        } else {
+            match rng.gen_range(0, 5) {
+                0 => play(&mut state, Move::Collect(rules.income)),
+                1 => {
+                    play(&mut state, Move::Phase(Phase::Challenge(Pending::Tax)));
+                    play(&mut state, Move::Collect(rules.tax));
+                }
+                2 => {
+                    play(&mut state, Move::Phase(Phase::Challenge(Pending::Steal(target))));
+                    play(&mut state, Move::Phase(Phase::Block(Pending::Steal(target))));
+                    play(&mut state, Move::Steal(target));
+                }
+                3 if me.coins >= rules.assassinate_cost => {
+                    let pending = Pending::Assassinate(target);
+                    play(&mut state, Move::Phase(Phase::Challenge(pending)));
+                    play(&mut state, Move::Pay(rules.assassinate_cost));
+                    play(&mut state, Move::Phase(Phase::Block(pending)));
+                    play(&mut state, Move::Phase(Phase::LoseCard(target)));
+                    play(&mut state, Move::Reveal(target, lost));
+                }
+                4 if !state.deck().is_empty() => {
+                    let give = me.hand.cards().nth(rng.gen_range(0, me.hand.len() as usize)).unwrap();
+                    let take = rng.gen_range(0, state.deck().len()) as u8;
+                    play(&mut state, Move::Phase(Phase::Challenge(Pending::Exchange)));
+                    play(&mut state, Move::Phase(Phase::Exchange));
+                    play(&mut state, Move::Swap { give, take });
+                }
+                _ => {
+                    play(&mut state, Move::Phase(Phase::Block(Pending::ForeignAid)));
+                    play(&mut state, Move::Phase(Phase::ChallengeBlock(Pending::ForeignAid)));
+                    play(&mut state, Move::Collect(rules.foreign_aid));
+                }
+            }
+        }
+        play(&mut state, Move::Pass);
+    }
+    moves
+}
+
+// How many different positions, and information sets for the first seat, the lines of play
+// pass through
+fn count_positions(compact: &CompactState, playouts: &[Vec<Move>]) -> (usize, usize) {
+    let mut positions = HashSet::new();
+    let mut info_sets = HashSet::new();
+    for moves in playouts {
+        let mut state = *compact;
+        for &mv in moves {
+            state.apply(mv);
+            positions.insert(state.zobrist());
+            info_sets.insert(state.info_set(Some(0)));
+        }
+    }
+    (positions.len(), info_sets.len())
+}
+
+fn time(mut f: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+}
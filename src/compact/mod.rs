@@ -0,0 +1,641 @@
+//! A small, fixed-size copy of a game's state for search and simulation.
+//!
+//! `GameState` is built for running a game: names, a history of events, and hash maps keyed by
+//! PlayerID. Search needs to copy and step a state millions of times, so `CompactState` keeps
+//! only the numbers. Seats live in a fixed array, hands are packed counts in a u16, and the deck
+//! is an inline array. It is `Copy`, so cloning it never allocates.
+//!
+//! A state is changed one `Move` at a time. `apply` hands back an `Undo` that puts the state
+//! back exactly as it was, so a search can walk down a line of play and back up again without
+//! copying at all.
+//!
+//! States carry a Zobrist hash (see `zobrist`) that is kept up to date as moves are applied
+//! and undone, so positions can be cached and repeats spotted cheaply. `info_set` gives what a
+//! single seat can see, which hashes and compares the same for every state that seat can't tell
+//! apart.
+
+pub mod bench;
+mod zobrist;
+
+use crate::rules::Rules;
+use crate::{GameState, Identity, PlayerID};
+use anyhow::{anyhow, Result};
+use std::cmp::min;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+/// The most seats a compact state has room for
+pub const MAX_SEATS: usize = 8;
+/// The most cards a compact deck has room for
+pub const MAX_DECK: usize = 32;
+
+/// Every identity, in the order they are packed into a Hand
+pub const IDENTITIES: [Identity; 5] = [
+    Identity::Ambassador,
+    Identity::Assassin,
+    Identity::Contessa,
+    Identity::Captain,
+    Identity::Duke,
+];
+
+// Bits used for the count of each identity in a Hand
+const COUNT_BITS: u16 = 3;
+const COUNT_MASK: u16 = (1 << COUNT_BITS) - 1;
+
+fn shift(identity: Identity) -> u16 {
+    identity as u16 * COUNT_BITS
+}
+
+/// A handful of cards where only how many of each identity matters, packed three bits per
+/// identity. Holds up to seven of each.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Hand(u16);
+
+impl Hand {
+    pub fn from_cards(cards: &[Identity]) -> Self {
+        let mut hand = Hand::default();
+        for &card in cards {
+            hand.add(card);
+        }
+        hand
+    }
+
+    pub fn count(self, identity: Identity) -> u8 {
+        ((self.0 >> shift(identity)) & COUNT_MASK) as u8
+    }
+
+    pub fn contains(self, identity: Identity) -> bool {
+        self.count(identity) > 0
+    }
+
+    pub fn len(self) -> u8 {
+        IDENTITIES.iter().map(|&identity| self.count(identity)).sum()
+    }
+
+    pub fn add(&mut self, identity: Identity) {
+        debug_assert!(self.count(identity) < COUNT_MASK as u8, "Too many {:?}s in one hand", identity);
+        self.0 += 1 << shift(identity);
+    }
+
+    /// Takes a card out of the hand, returning false if it wasn't there
+    pub fn remove(&mut self, identity: Identity) -> bool {
+        if !self.contains(identity) {
+            return false;
+        }
+        self.0 -= 1 << shift(identity);
+        true
+    }
+
+    /// Every card in the hand, grouped by identity
+    pub fn cards(self) -> impl Iterator<Item = Identity> {
+        IDENTITIES
+            .iter()
+            .flat_map(move |&identity| std::iter::repeat_n(identity, self.count(identity) as usize))
+    }
+}
+
+/// The deck, in order, without any allocation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deck {
+    cards: [Identity; MAX_DECK],
+    len: u8,
+    // How many of each identity are in the deck
+    counts: [u8; IDENTITIES.len()],
+}
+
+impl Deck {
+    pub fn from_cards(cards: &[Identity]) -> Result<Self> {
+        if cards.len() > MAX_DECK {
+            return Err(anyhow!("A compact deck holds {} cards, got {}", MAX_DECK, cards.len()));
+        }
+        let mut deck = Deck {
+            cards: [Identity::Ambassador; MAX_DECK],
+            len: cards.len() as u8,
+            counts: [0; IDENTITIES.len()],
+        };
+        deck.cards[..cards.len()].copy_from_slice(cards);
+        for &card in cards {
+            deck.counts[card as usize] += 1;
+        }
+        Ok(deck)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn count(&self, identity: Identity) -> u8 {
+        self.counts[identity as usize]
+    }
+
+    // Puts a card in the deck in place of another, handing back the one it replaced
+    fn swap(&mut self, index: usize, card: Identity) -> Identity {
+        let taken = std::mem::replace(&mut self.cards[..self.len as usize][index], card);
+        self.counts[taken as usize] -= 1;
+        self.counts[card as usize] += 1;
+        taken
+    }
+}
+
+/// Everything about one seat
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SeatState {
+    pub coins: u8,
+    pub lives: u8,
+    pub hand: Hand,
+    // Cards turned over after losing a life
+    pub revealed: Hand,
+}
+
+impl SeatState {
+    pub fn is_alive(&self) -> bool {
+        self.lives > 0
+    }
+}
+
+/// An action other players can still respond to, and who it targets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pending {
+    ForeignAid,
+    Tax,
+    Exchange,
+    Steal(u8),
+    Assassinate(u8),
+}
+
+/// Where the current turn is up to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// The player to move is choosing an action
+    ChooseAction,
+    /// The others can challenge the player to move's claim
+    Challenge(Pending),
+    /// The action can be blocked
+    Block(Pending),
+    /// The block can be challenged
+    ChallengeBlock(Pending),
+    /// A seat has to turn over a card
+    LoseCard(u8),
+    /// The player to move is swapping cards with the deck
+    Exchange,
+}
+
+/// One change to a compact state. Actions are played out as a few of these, e.g. an
+/// assassination is a `Pay` followed by the target's `Reveal`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Move {
+    /// The player to move takes coins from the bank, as many as they have room for
+    Collect(u8),
+    /// The player to move pays coins to the bank, or as many as they have
+    Pay(u8),
+    /// The player to move steals from a seat, as much as the rules allow and they have room for
+    Steal(u8),
+    /// A seat turns over a card and loses a life
+    Reveal(u8, Identity),
+    /// The player to move puts a card from their hand into the deck at `take`, and takes the
+    /// card that was there
+    Swap { give: Identity, take: u8 },
+    /// The turn moves on to its next phase
+    Phase(Phase),
+    /// Play passes to the next seat still in the game, who starts by choosing an action
+    Pass,
+}
+
+/// What it takes to reverse a move
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Undo {
+    mv: Move,
+    // Coins that actually changed hands
+    coins: u8,
+    // The card taken from the deck by a swap
+    taken: Option<Identity>,
+    to_move: u8,
+    phase: Phase,
+}
+
+/// A whole game's state in a few hundred bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactState {
+    seats: [SeatState; MAX_SEATS],
+    num_seats: u8,
+    deck: Deck,
+    to_move: u8,
+    phase: Phase,
+    turn: u32,
+    rules: Rules,
+    // Zobrist hashes of what everyone can see, and of the hands and deck
+    public_hash: u64,
+    private_hash: u64,
+}
+
+impl CompactState {
+    /// Copies a running game, with `to_move` about to take their turn. Seats are numbered by
+    /// PlayerID, which is also the turn order.
+    pub fn from_game(
+        state: &GameState,
+        hands: &HashMap<PlayerID, Vec<Identity>>,
+        deck: &[Identity],
+        to_move: &PlayerID,
+    ) -> Result<Self> {
+        let num_seats = state.turn_order.len();
+        if num_seats > MAX_SEATS {
+            return Err(anyhow!("A compact state seats {} players, got {}", MAX_SEATS, num_seats));
+        }
+        let mut seats = [SeatState::default(); MAX_SEATS];
+        for id in &state.turn_order {
+            let player = &state.player_states[id];
+            seats[id.0 as usize] = SeatState {
+                coins: player.num_coins,
+                lives: player.num_lives,
+                hand: Hand::from_cards(&hands[id]),
+                revealed: Hand::from_cards(&player.lost_lives),
+            };
+        }
+        let mut compact = CompactState {
+            seats,
+            num_seats: num_seats as u8,
+            deck: Deck::from_cards(deck)?,
+            to_move: to_move.0,
+            phase: Phase::ChooseAction,
+            turn: state.turn,
+            rules: state.rules,
+            public_hash: 0,
+            private_hash: 0,
+        };
+        let (public_hash, private_hash) = compact.hashes();
+        compact.public_hash = public_hash;
+        compact.private_hash = private_hash;
+        Ok(compact)
+    }
+
+    pub fn seats(&self) -> &[SeatState] {
+        &self.seats[..self.num_seats as usize]
+    }
+
+    pub fn seat(&self, seat: u8) -> &SeatState {
+        &self.seats()[seat as usize]
+    }
+
+    pub fn deck(&self) -> &Deck {
+        &self.deck
+    }
+
+    pub fn current_seat(&self) -> u8 {
+        self.to_move
+    }
+
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.seats().iter().filter(|seat| seat.is_alive()).count() <= 1
+    }
+
+    /// The Zobrist hash of the whole position. The turn number is left out, so a position that
+    /// comes round again hashes the same.
+    pub fn zobrist(&self) -> u64 {
+        self.public_hash ^ self.private_hash
+    }
+
+    /// Everything a seat can see. None gives what a spectator can see.
+    pub fn info_set(&self, viewer: Option<u8>) -> InfoSet {
+        let mut seats = [PublicSeat::default(); MAX_SEATS];
+        for (public, seat) in seats.iter_mut().zip(self.seats()) {
+            *public = PublicSeat {
+                coins: seat.coins,
+                lives: seat.lives,
+                revealed: seat.revealed,
+            };
+        }
+        let hand = viewer.map_or_else(Hand::default, |viewer| self.seat(viewer).hand);
+        let mut hash = self.public_hash;
+        if let Some(viewer) = viewer {
+            hash ^= zobrist::viewer(viewer);
+            for &identity in &IDENTITIES {
+                hash ^= zobrist::hand(viewer as usize, identity, hand.count(identity));
+            }
+        }
+        InfoSet {
+            seats,
+            num_seats: self.num_seats,
+            deck_size: self.deck.len,
+            to_move: self.to_move,
+            phase: self.phase,
+            viewer,
+            hand,
+            hash,
+        }
+    }
+
+    /// Makes a move, and returns what it takes to unmake it
+    pub fn apply(&mut self, mv: Move) -> Undo {
+        let mut undo = Undo {
+            mv,
+            coins: 0,
+            taken: None,
+            to_move: self.to_move,
+            phase: self.phase,
+        };
+        let actor = self.to_move as usize;
+        match mv {
+            Move::Collect(coins) => {
+                undo.coins = min(coins, u8::MAX - self.seats[actor].coins);
+                self.set_coins(actor, self.seats[actor].coins + undo.coins);
+            }
+            Move::Pay(coins) => {
+                undo.coins = min(coins, self.seats[actor].coins);
+                self.set_coins(actor, self.seats[actor].coins - undo.coins);
+            }
+            Move::Steal(victim) => {
+                let victim = victim as usize;
+                let room = u8::MAX - self.seats[actor].coins;
+                undo.coins = min(min(self.seats[victim].coins, self.rules.steal_amount), room);
+                self.set_coins(victim, self.seats[victim].coins - undo.coins);
+                self.set_coins(actor, self.seats[actor].coins + undo.coins);
+            }
+            Move::Reveal(seat, identity) => {
+                let seat = seat as usize;
+                let held = self.take_card(seat, identity);
+                debug_assert!(held, "Revealed a {:?} that wasn't in the hand", identity);
+                self.turn_over(seat, identity, true);
+                self.set_lives(seat, self.seats[seat].lives - 1);
+            }
+            Move::Swap { give, take } => {
+                let held = self.take_card(actor, give);
+                debug_assert!(held, "Swapped away a {:?} that wasn't in the hand", give);
+                let taken = self.swap_deck_card(take as usize, give);
+                self.give_card(actor, taken);
+                undo.taken = Some(taken);
+            }
+            Move::Phase(phase) => self.set_phase(phase),
+            Move::Pass => {
+                self.set_to_move(self.next_seat(self.to_move));
+                self.set_phase(Phase::ChooseAction);
+                self.turn += 1;
+            }
+        }
+        undo
+    }
+
+    /// Unmakes the most recent move. Moves have to be undone in the reverse of the order they
+    /// were applied in.
+    pub fn undo(&mut self, undo: Undo) {
+        self.set_to_move(undo.to_move);
+        self.set_phase(undo.phase);
+        let actor = self.to_move as usize;
+        match undo.mv {
+            Move::Collect(_) => self.set_coins(actor, self.seats[actor].coins - undo.coins),
+            Move::Pay(_) => self.set_coins(actor, self.seats[actor].coins + undo.coins),
+            Move::Steal(victim) => {
+                let victim = victim as usize;
+                self.set_coins(actor, self.seats[actor].coins - undo.coins);
+                self.set_coins(victim, self.seats[victim].coins + undo.coins);
+            }
+            Move::Reveal(seat, identity) => {
+                let seat = seat as usize;
+                self.turn_over(seat, identity, false);
+                self.give_card(seat, identity);
+                self.set_lives(seat, self.seats[seat].lives + 1);
+            }
+            Move::Swap { give, take } => {
+                let taken = undo.taken.expect("A swap always takes a card");
+                self.swap_deck_card(take as usize, taken);
+                self.take_card(actor, taken);
+                self.give_card(actor, give);
+            }
+            Move::Phase(_) => {}
+            Move::Pass => self.turn -= 1,
+        }
+    }
+
+    // The next seat after this one that is still in the game
+    fn next_seat(&self, seat: u8) -> u8 {
+        let num_seats = self.num_seats;
+        (1..=num_seats)
+            .map(|offset| (seat + offset) % num_seats)
+            .find(|&next| self.seat(next).is_alive())
+            .unwrap_or(seat)
+    }
+
+    // The changes every move is made of, each keeping the hashes up to date
+
+    fn set_coins(&mut self, seat: usize, coins: u8) {
+        self.public_hash ^= zobrist::coins(seat, self.seats[seat].coins) ^ zobrist::coins(seat, coins);
+        self.seats[seat].coins = coins;
+    }
+
+    fn set_lives(&mut self, seat: usize, lives: u8) {
+        self.public_hash ^= zobrist::lives(seat, self.seats[seat].lives) ^ zobrist::lives(seat, lives);
+        self.seats[seat].lives = lives;
+    }
+
+    fn take_card(&mut self, seat: usize, identity: Identity) -> bool {
+        let before = self.seats[seat].hand.count(identity);
+        if !self.seats[seat].hand.remove(identity) {
+            return false;
+        }
+        self.private_hash ^= zobrist::hand(seat, identity, before) ^ zobrist::hand(seat, identity, before - 1);
+        true
+    }
+
+    fn give_card(&mut self, seat: usize, identity: Identity) {
+        let before = self.seats[seat].hand.count(identity);
+        self.seats[seat].hand.add(identity);
+        self.private_hash ^= zobrist::hand(seat, identity, before) ^ zobrist::hand(seat, identity, before + 1);
+    }
+
+    // Turns a card face up, or back down again
+    fn turn_over(&mut self, seat: usize, identity: Identity, face_up: bool) {
+        let revealed = &mut self.seats[seat].revealed;
+        let before = revealed.count(identity);
+        if face_up {
+            revealed.add(identity);
+        } else {
+            revealed.remove(identity);
+        }
+        let after = revealed.count(identity);
+        self.public_hash ^= zobrist::revealed(seat, identity, before) ^ zobrist::revealed(seat, identity, after);
+    }
+
+    fn swap_deck_card(&mut self, index: usize, card: Identity) -> Identity {
+        let taken = self.deck.swap(index, card);
+        if taken != card {
+            let (put, took) = (self.deck.count(card), self.deck.count(taken));
+            self.private_hash ^= zobrist::deck(card, put - 1) ^ zobrist::deck(card, put);
+            self.private_hash ^= zobrist::deck(taken, took + 1) ^ zobrist::deck(taken, took);
+        }
+        taken
+    }
+
+    fn set_to_move(&mut self, seat: u8) {
+        if seat == self.to_move {
+            return;
+        }
+        self.public_hash ^= zobrist::to_move(self.to_move) ^ zobrist::to_move(seat);
+        self.to_move = seat;
+    }
+
+    fn set_phase(&mut self, phase: Phase) {
+        if phase == self.phase {
+            return;
+        }
+        self.public_hash ^= zobrist::phase(self.phase) ^ zobrist::phase(phase);
+        self.phase = phase;
+    }
+
+    /// Works the public and private hashes out from scratch
+    pub fn hashes(&self) -> (u64, u64) {
+        let mut public = zobrist::to_move(self.to_move) ^ zobrist::phase(self.phase) ^ zobrist::deck_size(self.deck.len());
+        let mut private = 0;
+        for (index, seat) in self.seats().iter().enumerate() {
+            public ^= zobrist::coins(index, seat.coins) ^ zobrist::lives(index, seat.lives);
+            for &identity in &IDENTITIES {
+                public ^= zobrist::revealed(index, identity, seat.revealed.count(identity));
+                private ^= zobrist::hand(index, identity, seat.hand.count(identity));
+            }
+        }
+        // Only what the deck holds, not its order, which nobody at the table can know
+        for &identity in &IDENTITIES {
+            private ^= zobrist::deck(identity, self.deck.count(identity));
+        }
+        (public, private)
+    }
+}
+
+impl Hash for CompactState {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.zobrist());
+    }
+}
+
+/// What everyone can see of a seat
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PublicSeat {
+    pub coins: u8,
+    pub lives: u8,
+    pub revealed: Hand,
+}
+
+/// Everything one seat knows about a position: the public state of the table and their own
+/// hand. Positions that only differ in cards the seat can't see give equal information sets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InfoSet {
+    seats: [PublicSeat; MAX_SEATS],
+    num_seats: u8,
+    deck_size: u8,
+    to_move: u8,
+    phase: Phase,
+    viewer: Option<u8>,
+    hand: Hand,
+    // Zobrist hash of the above, carried over from the state
+    hash: u64,
+}
+
+impl Hash for InfoSet {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::bench::{deal, playout};
+    use super::*;
+    use crate::GameRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn compact_and_game_states_step_alike_and_undo_cleanly() {
+        for seats in 2..=6 {
+            let (legacy, compact) = deal(seats, Rules::default(), seats as u64).unwrap();
+            let mut rng = GameRng::seed_from_u64(seats as u64);
+            for _ in 0..200 {
+                let moves = playout(compact, 30, &mut rng);
+                let mut stepped = legacy.clone();
+                let mut applied = compact;
+                let mut undos = Vec::new();
+                for &mv in &moves {
+                    stepped.step(mv);
+                    undos.push(applied.apply(mv));
+                    let (public, private) = applied.hashes();
+                    assert_eq!(applied.zobrist(), public ^ private, "Hash went out of date after {:?}", mv);
+                }
+                assert_eq!(applied, stepped.compact().unwrap(), "Compact and game states disagree after {:?}", moves);
+                while let Some(undo) = undos.pop() {
+                    applied.undo(undo);
+                }
+                assert_eq!(applied, compact, "Undoing {:?} didn't restore the state", moves);
+            }
+        }
+    }
+
+    #[test]
+    fn kept_up_hashes_match_ones_worked_out_from_scratch() {
+        let (_, start) = deal(4, Rules::default(), 7).unwrap();
+        let mut rng = GameRng::seed_from_u64(7);
+        let mut state = start;
+        let mut undos = Vec::new();
+        // Any move the state allows, not just ones a game would make, so every feature changes
+        for _ in 0..5000 {
+            let actor = state.current_seat();
+            let seat = rng.gen_range(0, state.seats().len() as u8);
+            let mv = match rng.gen_range(0, 6) {
+                0 => Move::Collect(rng.gen_range(0, 4)),
+                1 => Move::Pay(rng.gen_range(0, 4)),
+                2 if seat != actor => Move::Steal(seat),
+                3 if state.seat(seat).hand.len() > 0 => {
+                    let hand = state.seat(seat).hand;
+                    Move::Reveal(seat, hand.cards().nth(rng.gen_range(0, hand.len() as usize)).unwrap())
+                }
+                4 if state.seat(actor).hand.len() > 0 && !state.deck().is_empty() => {
+                    let hand = state.seat(actor).hand;
+                    Move::Swap {
+                        give: hand.cards().nth(rng.gen_range(0, hand.len() as usize)).unwrap(),
+                        take: rng.gen_range(0, state.deck().len() as u8),
+                    }
+                }
+                5 => Move::Phase(Phase::LoseCard(seat)),
+                _ => Move::Pass,
+            };
+            undos.push(state.apply(mv));
+            let (public, private) = state.hashes();
+            assert_eq!(state.zobrist(), public ^ private, "Hash went out of date after {:?}", mv);
+        }
+        while let Some(undo) = undos.pop() {
+            state.undo(undo);
+            let (public, private) = state.hashes();
+            assert_eq!(state.zobrist(), public ^ private, "Hash went out of date undoing {:?}", undo);
+        }
+        assert_eq!(state.zobrist(), start.zobrist());
+    }
+
+    #[test]
+    fn coins_stop_at_the_most_a_seat_holds() {
+        let (mut legacy, before) = deal(2, Rules::default(), 1).unwrap();
+        let mut state = before;
+        let seat = state.current_seat();
+        let victim = state.next_seat(seat);
+        let mut undos = Vec::new();
+        for mv in [Move::Collect(u8::MAX), Move::Collect(1), Move::Steal(victim)] {
+            legacy.step(mv);
+            undos.push(state.apply(mv));
+        }
+        assert_eq!(state.seat(seat).coins, u8::MAX);
+        // Nothing is taken that there's no room for
+        assert_eq!(state.seat(victim).coins, before.seat(victim).coins);
+        assert_eq!(state, legacy.compact().unwrap());
+        let (public, private) = state.hashes();
+        assert_eq!(state.zobrist(), public ^ private);
+
+        // Only the coins that were taken are given back
+        while let Some(undo) = undos.pop() {
+            state.undo(undo);
+        }
+        assert_eq!(state, before);
+    }
+}
@@ -0,0 +1,95 @@
+//! Zobrist keys for hashing a CompactState.
+//!
+//! Every feature of a state (a seat's coins, how many Dukes are in a hand, whose turn it is, ...)
+//! gets a random 64 bit key, and a state hashes to the XOR of the keys of its features. Changing
+//! a feature only takes XORing out its old key and XORing in the new one. The keys are worked
+//! out at compile time from fixed seeds, so a position hashes the same in every run.
+
+use super::{Pending, Phase};
+use crate::Identity;
+
+use super::{MAX_DECK, MAX_SEATS};
+
+const IDENTITIES: usize = 5;
+// Counts of one identity in a hand go up to seven
+const COUNTS: usize = 8;
+// Phases, the actions they can be waiting on, and their targets
+const PHASE_KINDS: usize = 6;
+const PENDING_KINDS: usize = 6;
+
+static COINS: [u64; MAX_SEATS * 256] = keys(1);
+static LIVES: [u64; MAX_SEATS * 256] = keys(2);
+static HAND: [u64; MAX_SEATS * IDENTITIES * COUNTS] = keys(3);
+static REVEALED: [u64; MAX_SEATS * IDENTITIES * COUNTS] = keys(4);
+static DECK: [u64; IDENTITIES * (MAX_DECK + 1)] = keys(5);
+static DECK_SIZE: [u64; MAX_DECK + 1] = keys(6);
+static TO_MOVE: [u64; MAX_SEATS] = keys(7);
+static PHASE: [u64; PHASE_KINDS * PENDING_KINDS * MAX_SEATS] = keys(8);
+static VIEWER: [u64; MAX_SEATS] = keys(9);
+
+// A table of keys for one kind of feature, filled in at compile time (splitmix64)
+const fn keys<const N: usize>(feature: u64) -> [u64; N] {
+    let mut keys = [0; N];
+    let mut i = 0;
+    while i < N {
+        let mut z = (feature << 56 | i as u64).wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        keys[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    keys
+}
+
+pub fn coins(seat: usize, coins: u8) -> u64 {
+    COINS[seat * 256 + coins as usize]
+}
+
+pub fn lives(seat: usize, lives: u8) -> u64 {
+    LIVES[seat * 256 + lives as usize]
+}
+
+pub fn hand(seat: usize, identity: Identity, count: u8) -> u64 {
+    HAND[(seat * IDENTITIES + identity as usize) * COUNTS + count as usize]
+}
+
+pub fn revealed(seat: usize, identity: Identity, count: u8) -> u64 {
+    REVEALED[(seat * IDENTITIES + identity as usize) * COUNTS + count as usize]
+}
+
+pub fn deck(identity: Identity, count: u8) -> u64 {
+    DECK[identity as usize * (MAX_DECK + 1) + count as usize]
+}
+
+pub fn deck_size(size: usize) -> u64 {
+    DECK_SIZE[size]
+}
+
+pub fn to_move(seat: u8) -> u64 {
+    TO_MOVE[seat as usize]
+}
+
+// Whose information set a hash is of
+pub fn viewer(seat: u8) -> u64 {
+    VIEWER[seat as usize]
+}
+
+pub fn phase(phase: Phase) -> u64 {
+    let (kind, pending) = match phase {
+        Phase::ChooseAction => (0, None),
+        Phase::Challenge(pending) => (1, Some(pending)),
+        Phase::Block(pending) => (2, Some(pending)),
+        Phase::ChallengeBlock(pending) => (3, Some(pending)),
+        Phase::LoseCard(seat) => return PHASE[(4 * PENDING_KINDS) * MAX_SEATS + seat as usize],
+        Phase::Exchange => (5, None),
+    };
+    let (action, target) = match pending {
+        None => (0, 0),
+        Some(Pending::ForeignAid) => (1, 0),
+        Some(Pending::Tax) => (2, 0),
+        Some(Pending::Exchange) => (3, 0),
+        Some(Pending::Steal(target)) => (4, target),
+        Some(Pending::Assassinate(target)) => (5, target),
+    };
+    PHASE[(kind * PENDING_KINDS + action) * MAX_SEATS + target as usize]
+}
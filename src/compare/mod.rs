@@ -0,0 +1,341 @@
+//! Heads-up A/B testing of two bots with a sequential probability ratio test (SPRT).
+//!
+//! The test weighs "A is no stronger than B" (H0, an Elo difference of --elo0) against "A is
+//! stronger by --elo1" (H1). After every decisive game the log likelihood ratio moves towards
+//! one of them, and the run stops as soon as it crosses a bound set by --alpha (the chance of
+//! wrongly calling A stronger) and --beta (the chance of missing a real improvement). Games
+//! called off without a winner don't count towards the test.
+//!
+//! Games are played in parallel batches but the test is run over them in order, so a seed gives
+//! the same result whatever the number of threads.
+
+use crate::simulate::{game_seed, play_game, wilson_interval, Limits};
+use crate::tournament::BotEntry;
+use crate::rules::Rules;
+use crate::{GameResult, PlayerConfig, PlayerID};
+use anyhow::{anyhow, Result};
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use structopt::StructOpt;
+
+// Games handed to the thread pool at a time. Fixed, so the stopping point doesn't depend on
+// the number of threads
+const BATCH_SIZE: u64 = 256;
+
+#[derive(StructOpt, Debug)]
+pub struct CompareConfig {
+    /// The bot being tested, as TYPE or "random WEIGHTS"
+    #[structopt(long)]
+    a: BotEntry,
+    /// The bot it is measured against
+    #[structopt(long)]
+    b: BotEntry,
+    /// The most games to play before giving up on a result
+    #[structopt(long, default_value = "20000")]
+    games: u64,
+    /// Elo difference that counts as "no stronger"
+    #[structopt(long, default_value = "0")]
+    elo0: f64,
+    /// Elo difference that counts as "stronger"
+    #[structopt(long, default_value = "20")]
+    elo1: f64,
+    /// Chance of calling A stronger when it isn't
+    #[structopt(long, default_value = "0.05")]
+    alpha: f64,
+    /// Chance of missing A being stronger when it is
+    #[structopt(long, default_value = "0.05")]
+    beta: f64,
+    /// Games still going after this many turns are called off without a winner
+    #[structopt(long, default_value = "1000")]
+    max_turns: u32,
+    /// Games that come back to the same position this many times are called off without a
+    /// winner. Positions aren't tracked if not given
+    #[structopt(long)]
+    max_repeats: Option<u32>,
+    /// Seed for the whole run. A random one is picked (and printed) if not given
+    #[structopt(long)]
+    seed: Option<u64>,
+    /// Threads to play on. Defaults to one per core
+    #[structopt(long)]
+    threads: Option<usize>,
+}
+
+impl CompareConfig {
+    fn limits(&self) -> Limits {
+        Limits {
+            max_turns: self.max_turns,
+            max_repeats: self.max_repeats,
+            decision_clock: None,
+        }
+    }
+}
+
+/// Where the test ended up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Verdict {
+    Stronger,
+    NotStronger,
+    Inconclusive,
+}
+
+/// A running sequential probability ratio test on A's chance of winning a decisive game
+struct Sprt {
+    win: f64,
+    loss: f64,
+    lower: f64,
+    upper: f64,
+    llr: f64,
+}
+
+impl Sprt {
+    fn new(config: &CompareConfig) -> Self {
+        let p0 = win_probability(config.elo0);
+        let p1 = win_probability(config.elo1);
+        Sprt {
+            win: (p1 / p0).ln(),
+            loss: ((1.0 - p1) / (1.0 - p0)).ln(),
+            lower: (config.beta / (1.0 - config.alpha)).ln(),
+            upper: ((1.0 - config.beta) / config.alpha).ln(),
+            llr: 0.0,
+        }
+    }
+
+    fn record(&mut self, a_won: bool) -> Verdict {
+        self.llr += if a_won { self.win } else { self.loss };
+        if self.llr >= self.upper {
+            Verdict::Stronger
+        } else if self.llr <= self.lower {
+            Verdict::NotStronger
+        } else {
+            Verdict::Inconclusive
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Score {
+    games: u64,
+    a_wins: u64,
+    b_wins: u64,
+    draws: u64,
+}
+
+/// Plays A against B until the test reaches a verdict or the games run out, then reports
+pub fn run(config: &CompareConfig, rules: Rules) -> Result<()> {
+    rules.check(2)?;
+    if config.elo1 <= config.elo0 {
+        return Err(anyhow!("--elo1 must be greater than --elo0"));
+    }
+    for (name, chance) in &[("alpha", config.alpha), ("beta", config.beta)] {
+        if *chance <= 0.0 || *chance >= 0.5 {
+            return Err(anyhow!("--{} must be between 0 and 0.5, got {}", name, chance));
+        }
+    }
+    let seed = config.seed.unwrap_or_else(rand::random);
+    let mut pool = ThreadPoolBuilder::new();
+    if let Some(threads) = config.threads {
+        pool = pool.num_threads(threads);
+    }
+    let pool = pool.build()?;
+
+    let mut sprt = Sprt::new(config);
+    let mut score = Score::default();
+    let mut verdict = Verdict::Inconclusive;
+    'batches: while score.games < config.games {
+        let batch = score.games..(score.games + BATCH_SIZE).min(config.games);
+        let results: Vec<Result<GameResult>> = pool.install(|| {
+            batch
+                .clone()
+                .into_par_iter()
+                .map(|game| play_game(seating(config, game), rules, config.limits(), game_seed(seed, game), None))
+                .collect()
+        });
+        for (game, result) in batch.zip(results) {
+            score.games += 1;
+            let a_won = match result?.winner {
+                // A sits first in even games and second in odd ones
+                Some(PlayerID(seat)) => u64::from(seat) == game % 2,
+                None => {
+                    score.draws += 1;
+                    continue;
+                }
+            };
+            if a_won {
+                score.a_wins += 1;
+            } else {
+                score.b_wins += 1;
+            }
+            verdict = sprt.record(a_won);
+            if verdict != Verdict::Inconclusive {
+                break 'batches;
+            }
+        }
+    }
+
+    println!("Seed: {}", seed);
+    present(config, &score, &sprt, verdict);
+    Ok(())
+}
+
+// A and B swap seats every game, so neither gets to go first more often
+fn seating(config: &CompareConfig, game: u64) -> Vec<PlayerConfig> {
+    let a = PlayerConfig::new(config.a.player_type.clone(), format!("A ({})", config.a.name));
+    let b = PlayerConfig::new(config.b.player_type.clone(), format!("B ({})", config.b.name));
+    if game.is_multiple_of(2) {
+        vec![a, b]
+    } else {
+        vec![b, a]
+    }
+}
+
+fn present(config: &CompareConfig, score: &Score, sprt: &Sprt, verdict: Verdict) {
+    let decisive = score.a_wins + score.b_wins;
+    println!("A: {}", config.a.name);
+    println!("B: {}", config.b.name);
+    println!(
+        "{} games: A won {}, B won {}, {} without a winner",
+        score.games, score.a_wins, score.b_wins, score.draws
+    );
+    if decisive == 0 {
+        println!("No decisive games, nothing to compare");
+        return;
+    }
+
+    let a_rate = score.a_wins as f64 / decisive as f64;
+    let (low, high) = wilson_interval(score.a_wins, decisive);
+    println!(
+        "A's win rate: {:.1}% [{:.1}%, {:.1}%], {:+.1} points over B",
+        100.0 * a_rate,
+        100.0 * low,
+        100.0 * high,
+        100.0 * (2.0 * a_rate - 1.0)
+    );
+    println!(
+        "Elo difference: {:+.1} [{:+.1}, {:+.1}]",
+        elo_difference(a_rate),
+        elo_difference(low),
+        elo_difference(high)
+    );
+    // How sure we can be that A is better at all, from the normal approximation
+    let margin = score.a_wins as f64 - score.b_wins as f64;
+    let superiority = normal_cdf(margin / (decisive as f64).sqrt());
+    println!("Likelihood A is stronger: {:.1}%", 100.0 * superiority);
+    println!(
+        "SPRT: LLR {:.2}, bounds [{:.2}, {:.2}], for H0 elo {} vs H1 elo {}",
+        sprt.llr, sprt.lower, sprt.upper, config.elo0, config.elo1
+    );
+    match verdict {
+        Verdict::Stronger => println!(
+            "A is stronger (H1 accepted with {:.0}% confidence)",
+            100.0 * (1.0 - config.alpha)
+        ),
+        Verdict::NotStronger => println!(
+            "A is not stronger (H0 accepted with {:.0}% confidence)",
+            100.0 * (1.0 - config.beta)
+        ),
+        Verdict::Inconclusive => println!("Inconclusive after {} games", score.games),
+    }
+}
+
+// The chance the stronger of two players wins a game, for a given Elo difference
+fn win_probability(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+// The Elo difference that gives a win probability, the inverse of win_probability
+fn elo_difference(win_probability: f64) -> f64 {
+    let p = win_probability.clamp(1e-6, 1.0 - 1e-6);
+    -400.0 * (1.0 / p - 1.0).log10()
+}
+
+// Standard normal CDF, using the Abramowitz and Stegun approximation of erf
+fn normal_cdf(x: f64) -> f64 {
+    let z = x.abs() / std::f64::consts::SQRT_2;
+    let t = 1.0 / (1.0 + 0.327_591_1 * z);
+    let poly = t
+        * (0.254_829_592
+            + t * (-0.284_496_736 + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    let erf = 1.0 - poly * (-z * z).exp();
+    if x >= 0.0 {
+        0.5 * (1.0 + erf)
+    } else {
+        0.5 * (1.0 - erf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GameRng;
+    use rand::{Rng, SeedableRng};
+
+    fn default_sprt() -> (CompareConfig, Sprt) {
+        let config = CompareConfig::from_iter_safe(["compare", "--a", "random", "--b", "honest"]).unwrap();
+        let sprt = Sprt::new(&config);
+        (config, sprt)
+    }
+
+    // Games until the test reaches a verdict, or None if it never does
+    fn run_stream(sprt: &mut Sprt, stream: impl Iterator<Item = bool>) -> Option<(u64, Verdict)> {
+        for (games, a_won) in (1..).zip(stream.take(100_000)) {
+            match sprt.record(a_won) {
+                Verdict::Inconclusive => continue,
+                verdict => return Some((games, verdict)),
+            }
+        }
+        None
+    }
+
+    // Wald's estimate of how many games the test takes when A wins with probability p, and
+    // the test calls A stronger with the given probability
+    fn expected_games(sprt: &Sprt, p: f64, accepts: f64) -> f64 {
+        let drift = p * sprt.win + (1.0 - p) * sprt.loss;
+        (accepts * sprt.upper + (1.0 - accepts) * sprt.lower) / drift
+    }
+
+    #[test]
+    fn a_bot_that_always_wins_is_called_stronger_quickly() {
+        let (_, mut sprt) = default_sprt();
+        let (games, verdict) = run_stream(&mut sprt, std::iter::repeat(true)).unwrap();
+        assert_eq!(verdict, Verdict::Stronger);
+        assert_eq!(games, (sprt.upper / sprt.win).ceil() as u64);
+        assert!(games < 100, "{} games", games);
+    }
+
+    #[test]
+    fn an_even_match_is_called_no_stronger_in_about_the_expected_games() {
+        let (config, mut sprt) = default_sprt();
+        let expected = expected_games(&sprt, 0.5, config.alpha);
+        let (games, verdict) = run_stream(&mut sprt, [true, false].iter().cloned().cycle()).unwrap();
+        assert_eq!(verdict, Verdict::NotStronger);
+        assert!((games as f64) < 2.0 * expected, "{} games, expected about {}", games, expected);
+
+        let mut rng = GameRng::seed_from_u64(7);
+        let mut total = 0;
+        let mut called_stronger = 0;
+        for _ in 0..20 {
+            let (_, mut sprt) = default_sprt();
+            let stream = std::iter::repeat_with(|| rng.gen_bool(0.5));
+            let (games, verdict) = run_stream(&mut sprt, stream).unwrap();
+            // Wrongly calling A stronger should only happen about alpha of the time
+            if verdict == Verdict::Stronger {
+                called_stronger += 1;
+            }
+            total += games;
+        }
+        assert!(called_stronger <= 4, "Called stronger {} times in 20", called_stronger);
+        let mean = total as f64 / 20.0;
+        assert!(mean > expected / 3.0 && mean < 3.0 * expected, "{} games on average, expected about {}", mean, expected);
+    }
+
+    #[test]
+    fn elo_and_win_probability_are_inverses() {
+        assert!((win_probability(0.0) - 0.5).abs() < 1e-12);
+        for elo in [-300.0, -20.0, 0.0, 20.0, 300.0] {
+            assert!((elo_difference(win_probability(elo)) - elo).abs() < 1e-6);
+        }
+        assert!((normal_cdf(0.0) - 0.5).abs() < 1e-6);
+        assert!((normal_cdf(1.96) - 0.975).abs() < 1e-3);
+        assert!((normal_cdf(-1.96) - 0.025).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,161 @@
+use crate::action::Action;
+use crate::prompter::{LocalPrompter, Prompter};
+use crate::seat::TimeoutPolicy;
+use crate::view::PlayerNames;
+use crate::{GameState, Identity, PlayerID};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Something that happened during a game. Public events are sent to every
+/// player, private events only to the player they concern.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Event {
+    GameStarted {
+        turn_order: Vec<PlayerID>,
+    },
+    TurnStarted {
+        player: PlayerID,
+    },
+    ActionChosen {
+        actor: PlayerID,
+        action: Action,
+    },
+    BlockDeclared {
+        blocker: PlayerID,
+        actor: PlayerID,
+        action: Action,
+        block: Action,
+    },
+    ChallengeDeclared {
+        challenger: PlayerID,
+        challenged: PlayerID,
+        action: Action,
+    },
+    ChallengeResolved {
+        challenger: PlayerID,
+        challenged: PlayerID,
+        action: Action,
+        challenger_won: bool,
+    },
+    ActionResolved {
+        actor: PlayerID,
+        action: Action,
+    },
+    CardLost {
+        player: PlayerID,
+        identity: Identity,
+    },
+    PlayerEliminated {
+        player: PlayerID,
+    },
+    DecisionTimedOut {
+        player: PlayerID,
+        policy: TimeoutPolicy,
+    },
+    GameOver {
+        winner: Option<PlayerID>,
+    },
+    Chat {
+        player: PlayerID,
+        message: String,
+    },
+
+    // Private events
+    CardDealt {
+        player: PlayerID,
+        identity: Identity,
+    },
+    CardsExchanged {
+        player: PlayerID,
+        drawn: Vec<Identity>,
+        returned: Vec<Identity>,
+    },
+}
+
+impl Event {
+    /// Returns the only player allowed to see this event, or None if it is public
+    pub fn private_to(&self) -> Option<&PlayerID> {
+        match self {
+            Event::CardDealt { player, .. } | Event::CardsExchanged { player, .. } => Some(player),
+            _ => None,
+        }
+    }
+
+    /// Human readable description of the event
+    pub fn describe(&self, names: &impl PlayerNames) -> String {
+        let name = |id: &PlayerID| names.get_player_name(id);
+        let display = |action: &Action| LocalPrompter::display_action(names, action.clone());
+        match self {
+            Event::GameStarted { turn_order } => {
+                let names: Vec<String> = turn_order.iter().map(name).collect();
+                format!("The turn order is as follows: {}", names.join(" "))
+            }
+            Event::TurnStarted { player } => format!("{}'s turn!", name(player)),
+            Event::ActionChosen { actor, action } => {
+                format!("{} chose action {}", name(actor), display(action))
+            }
+            Event::BlockDeclared { blocker, actor, action, block } => format!(
+                "{} is blocking {}'s {} with {}",
+                name(blocker),
+                name(actor),
+                display(action),
+                display(block)
+            ),
+            Event::ChallengeDeclared { challenger, challenged, action } => format!(
+                "{} is challenging {}'s {}",
+                name(challenger),
+                name(challenged),
+                display(action)
+            ),
+            Event::ChallengeResolved { challenger, challenged, action, challenger_won } => {
+                let loser = if *challenger_won { challenged } else { challenger };
+                format!(
+                    "{} lost the challenge over {}'s {}",
+                    name(loser),
+                    name(challenged),
+                    display(action)
+                )
+            }
+            Event::ActionResolved { actor, action } => {
+                format!("{}'s {} went through", name(actor), display(action))
+            }
+            Event::CardLost { player, identity } => format!("{} discarded {:?}", name(player), identity),
+            Event::PlayerEliminated { player } => format!("{} is out of the game", name(player)),
+            Event::DecisionTimedOut { player, policy } => {
+                let consequence = match policy {
+                    TimeoutPolicy::SafeDefault => "playing it safe for them",
+                    TimeoutPolicy::Forfeit => "they forfeit",
+                    TimeoutPolicy::Random => "a random player takes over",
+                };
+                format!("{} ran out of time, {}", name(player), consequence)
+            }
+            Event::GameOver { winner: Some(winner) } => format!("{} won!", name(winner)),
+            Event::GameOver { winner: None } => "Uh oh... a lot of people won?".to_string(),
+            Event::Chat { player, message } => format!("{} says: {}", name(player), message),
+            Event::CardDealt { player, identity } => format!("{} was dealt {:?}", name(player), identity),
+            Event::CardsExchanged { player, drawn, returned } => format!(
+                "{} drew {:?} and returned {:?}",
+                name(player),
+                drawn,
+                returned
+            ),
+        }
+    }
+}
+
+/// Watches a game from outside the table: it sees every event, public or private, along with
+/// the public state, the real hands and the deck as they were when the event happened. Used for
+/// stats and checks, never by players.
+pub trait Subscriber {
+    fn on_event(&mut self, event: &Event, state: &GameState, hands: &HashMap<PlayerID, Vec<Identity>>, deck: &[Identity]);
+}
+
+/// Lets the caller keep hold of a subscriber it hands to a game, to read it afterwards
+impl<S: Subscriber> Subscriber for Rc<RefCell<S>> {
+    fn on_event(&mut self, event: &Event, state: &GameState, hands: &HashMap<PlayerID, Vec<Identity>>, deck: &[Identity]) {
+        self.borrow_mut().on_event(event, state, hands, deck);
+    }
+}
@@ -0,0 +1,617 @@
+//! Randomized games that check the engine's invariants after every event.
+//!
+//! Each game gets a random table of bots and a random (but valid) set of rules, all worked out
+//! from the game's seed. A Checker watches every event and makes sure cards are neither made
+//! nor lost, coins move exactly as the action says, only players still in the game act, blocked
+//! and successfully challenged actions don't go through, and a finished game has exactly one
+//! winner. Panics count as failures too.
+//!
+//! A failing game is shrunk, by dropping players, swapping bots for dumb ones, putting rules
+//! back to their defaults and cutting the game short, for as long as it keeps failing. The
+//! smallest case is saved as a line of JSON, by default to `regressions.jsonl` in the working
+//! directory. Cases copied into `regressions.jsonl` next to this file are replayed by the tests.
+
+use crate::action::Action;
+use crate::event::{Event, Subscriber};
+use crate::rules::Rules;
+use crate::simulate::game_seed;
+use crate::tournament::BotEntry;
+use crate::{Game, GameRng, GameState, Identity, LoggerType, PlayerConfig, PlayerID};
+use anyhow::{anyhow, Result};
+use enumset::EnumSet;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::rc::Rc;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct FuzzConfig {
+    /// The number of games to play
+    #[structopt(long, default_value = "2000")]
+    games: u64,
+    /// Games still going after this many turns are called off without a winner
+    #[structopt(long, default_value = "300")]
+    max_turns: u32,
+    /// Seed for the whole run. A random one is picked (and printed) if not given
+    #[structopt(long)]
+    seed: Option<u64>,
+    /// Where shrunk failing cases are added, one JSON object per line
+    #[structopt(long, parse(from_os_str), default_value = "regressions.jsonl")]
+    save: PathBuf,
+}
+
+/// Everything needed to play a game again exactly
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Case {
+    seed: u64,
+    // Bots as TYPE or "random WEIGHTS", one per seat
+    players: Vec<String>,
+    rules: Rules,
+    max_turns: u32,
+}
+
+impl Case {
+    /// Sets the game up, ready to play
+    pub fn game(&self) -> Result<Game> {
+        let mut players = Vec::new();
+        for (seat, spec) in self.players.iter().enumerate() {
+            let bot: BotEntry = spec.parse()?;
+            players.push(PlayerConfig::new(bot.player_type, format!("{} {}", bot.name, seat + 1)));
+        }
+        let mut game = Game::new(self.rules, players, LoggerType::Quiet, self.seed)?;
+        game.set_turn_limit(self.max_turns);
+        Ok(game)
+    }
+}
+
+/// Why a case failed, and on which turn
+#[derive(Debug, Clone)]
+pub struct Failure {
+    message: String,
+    turn: u32,
+}
+
+/// A shrunk failing case, as saved
+#[derive(Serialize, Deserialize)]
+struct Saved {
+    case: Case,
+    failure: String,
+}
+
+/// Plays the configured number of random games, shrinking and saving any that fail
+pub fn run(config: &FuzzConfig) -> Result<()> {
+    let seed = config.seed.unwrap_or_else(rand::random);
+    println!("Seed: {}", seed);
+    // Panics are reported with the case that caused them, not as they happen
+    let quiet = QuietPanics::new();
+    let mut failures = 0;
+    for game in 0..config.games {
+        let case = random_case(game_seed(seed, game), config.max_turns);
+        let failure = match run_case(&case) {
+            Ok(()) => continue,
+            Err(failure) => failure,
+        };
+        failures += 1;
+        println!("Game {} failed on turn {}: {}", game, failure.turn, failure.message);
+        let (case, failure) = shrink(case, failure);
+        println!("  Shrunk to turn {}: {}", failure.turn, failure.message);
+        let saved = serde_json::to_string(&Saved {
+            case,
+            failure: failure.message,
+        })?;
+        println!("  {}", saved);
+        let mut file = OpenOptions::new().create(true).append(true).open(&config.save)?;
+        writeln!(file, "{}", saved)?;
+    }
+    drop(quiet);
+
+    println!("{} games, {} failed", config.games, failures);
+    if failures > 0 {
+        return Err(anyhow!("{} failing case(s) saved to {}", failures, config.save.display()));
+    }
+    Ok(())
+}
+
+type PanicHook = Box<dyn Fn(&panic::PanicHookInfo<'_>) + Sync + Send>;
+
+/// Silences panic messages until dropped, then puts back whatever reported them before
+struct QuietPanics(Option<PanicHook>);
+
+impl QuietPanics {
+    fn new() -> Self {
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(|_| {}));
+        QuietPanics(Some(hook))
+    }
+}
+
+impl Drop for QuietPanics {
+    fn drop(&mut self) {
+        if let Some(hook) = self.0.take() {
+            panic::set_hook(hook);
+        }
+    }
+}
+
+/// A table of random bots and a random set of rules they can play under
+pub fn random_case(seed: u64, max_turns: u32) -> Case {
+    let mut rng = GameRng::seed_from_u64(seed);
+    loop {
+        let seats = rng.gen_range(2, 7);
+        let rules = random_rules(&mut rng);
+        if rules.check(seats).is_err() {
+            continue;
+        }
+        let players = (0..seats).map(|_| random_bot(&mut rng)).collect();
+        return Case {
+            seed,
+            players,
+            rules,
+            max_turns,
+        };
+    }
+}
+
+fn random_rules(rng: &mut GameRng) -> Rules {
+    let identities: EnumSet<Identity> = EnumSet::all().iter().filter(|_| rng.gen_bool(0.7)).collect();
+    let starting_cards = rng.gen_range(1, 4);
+    let required_coup_coins = rng.gen_range(1, 16);
+    Rules {
+        identities,
+        cards_per_identity: if rng.gen_bool(0.5) { None } else { Some(rng.gen_range(1, 6)) },
+        starting_cards,
+        starting_lives: rng.gen_range(1, starting_cards + 1),
+        starting_coins: rng.gen_range(0, 13),
+        required_coup_coins,
+        coup_cost: rng.gen_range(0, required_coup_coins + 1),
+        assassinate_cost: rng.gen_range(0, 9),
+        steal_amount: rng.gen_range(0, 5),
+        income: rng.gen_range(0, 4),
+        foreign_aid: rng.gen_range(0, 5),
+        tax: rng.gen_range(0, 6),
+        exchange_cards: rng.gen_range(0, 4),
+    }
+}
+
+fn random_bot(rng: &mut GameRng) -> String {
+    match rng.gen_range(0, 4) {
+        0 => "dumb".to_string(),
+        1 => "honest".to_string(),
+        2 => "random".to_string(),
+        _ => {
+            let names = [
+                "income",
+                "foreign_aid",
+                "tax",
+                "exchange",
+                "steal",
+                "assassinate",
+                "coup",
+                "challenge",
+                "no_challenge",
+                "block",
+                "no_block",
+            ];
+            let weights: Vec<String> = names
+                .iter()
+                .map(|name| format!("{}={}", name, rng.gen_range(0, 5)))
+                .collect();
+            format!("random {}", weights.join(","))
+        }
+    }
+}
+
+/// Plays a case to the end with a Checker watching
+pub fn run_case(case: &Case) -> Result<(), Failure> {
+    let checker = Rc::new(RefCell::new(Checker::new(case.rules, case.max_turns)));
+    let outcome = panic::catch_unwind(AssertUnwindSafe(|| -> Result<()> {
+        let mut game = case.game()?;
+        game.subscribe(Box::new(checker.clone()));
+        game.play();
+        Ok(())
+    }));
+    let checker = checker.borrow();
+    if let Some(failure) = &checker.violation {
+        return Err(failure.clone());
+    }
+    let message = match outcome {
+        Ok(Ok(())) => return Ok(()),
+        Ok(Err(e)) => format!("Couldn't set up the game: {}", e),
+        Err(panic) => format!("Panicked: {}", panic_message(&*panic)),
+    };
+    Err(Failure {
+        message,
+        turn: checker.turn,
+    })
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    match (panic.downcast_ref::<&str>(), panic.downcast_ref::<String>()) {
+        (Some(message), _) => message.to_string(),
+        (_, Some(message)) => message.clone(),
+        _ => "unknown panic".to_string(),
+    }
+}
+
+/// Makes a failing case as small as it will go while it still fails
+pub fn shrink(mut case: Case, mut failure: Failure) -> (Case, Failure) {
+    'shrinking: loop {
+        for candidate in smaller(&case, &failure) {
+            if candidate.rules.check(candidate.players.len()).is_err() {
+                continue;
+            }
+            if let Err(smaller_failure) = run_case(&candidate) {
+                case = candidate;
+                failure = smaller_failure;
+                continue 'shrinking;
+            }
+        }
+        return (case, failure);
+    }
+}
+
+// Every case one step smaller than this one
+fn smaller(case: &Case, failure: &Failure) -> Vec<Case> {
+    let mut candidates = Vec::new();
+    if failure.turn < case.max_turns {
+        candidates.push(Case {
+            max_turns: failure.turn,
+            ..case.clone()
+        });
+    }
+    if case.players.len() > 2 {
+        for seat in 0..case.players.len() {
+            let mut candidate = case.clone();
+            candidate.players.remove(seat);
+            candidates.push(candidate);
+        }
+    }
+    for seat in 0..case.players.len() {
+        let simpler = match case.players[seat].as_str() {
+            "dumb" => continue,
+            spec if spec.starts_with("random ") => "random",
+            _ => "dumb",
+        };
+        let mut candidate = case.clone();
+        candidate.players[seat] = simpler.to_string();
+        candidates.push(candidate);
+    }
+    let defaults: [fn(&mut Rules, &Rules); 13] = [
+        |rules, default| rules.identities = default.identities,
+        |rules, default| rules.cards_per_identity = default.cards_per_identity,
+        |rules, default| rules.starting_cards = default.starting_cards,
+        |rules, default| rules.starting_lives = default.starting_lives,
+        |rules, default| rules.starting_coins = default.starting_coins,
+        |rules, default| rules.required_coup_coins = default.required_coup_coins,
+        |rules, default| rules.coup_cost = default.coup_cost,
+        |rules, default| rules.assassinate_cost = default.assassinate_cost,
+        |rules, default| rules.steal_amount = default.steal_amount,
+        |rules, default| rules.income = default.income,
+        |rules, default| rules.foreign_aid = default.foreign_aid,
+        |rules, default| rules.tax = default.tax,
+        |rules, default| rules.exchange_cards = default.exchange_cards,
+    ];
+    for reset in &defaults {
+        let mut candidate = case.clone();
+        reset(&mut candidate.rules, &Rules::default());
+        if candidate.rules != case.rules {
+            candidates.push(candidate);
+        }
+    }
+    candidates
+}
+
+/// Watches a game and notes the first event after which an invariant doesn't hold
+struct Checker {
+    rules: Rules,
+    max_turns: u32,
+    // How many of each identity there are between the deck, the hands and the revealed cards
+    cards: Option<[usize; 5]>,
+    started: bool,
+    // Everyone's coins when the action being played out was chosen
+    coins_before: HashMap<PlayerID, u8>,
+    // Who blocked the action being played out, if anyone
+    blocker: Option<PlayerID>,
+    // Why the action being played out shouldn't go through, if it shouldn't
+    called_off: Option<&'static str>,
+    // The turn of the last event seen
+    turn: u32,
+    violation: Option<Failure>,
+}
+
+impl Checker {
+    fn new(rules: Rules, max_turns: u32) -> Self {
+        Checker {
+            rules,
+            max_turns,
+            cards: None,
+            started: false,
+            coins_before: HashMap::new(),
+            blocker: None,
+            called_off: None,
+            turn: 0,
+            violation: None,
+        }
+    }
+
+    fn check(
+        &mut self,
+        event: &Event,
+        state: &GameState,
+        hands: &HashMap<PlayerID, Vec<Identity>>,
+        deck: &[Identity],
+    ) -> Result<(), String> {
+        self.check_cards(state, hands, deck)?;
+        let alive = |id: &PlayerID| state.player_states[id].is_alive();
+        let coins = |id: &PlayerID| state.player_states[id].num_coins;
+        match event {
+            Event::GameStarted { .. } => self.started = true,
+            Event::TurnStarted { player } if !alive(player) => {
+                return Err(format!("{:?} started a turn while out of the game", player))
+            }
+            Event::ActionChosen { actor, action } => {
+                if !alive(actor) {
+                    return Err(format!("{:?} chose {:?} while out of the game", actor, action));
+                }
+                self.check_action(actor, action, state)?;
+                self.coins_before = state.player_states.iter().map(|(id, player)| (id.clone(), player.num_coins)).collect();
+                self.blocker = None;
+                self.called_off = None;
+            }
+            Event::BlockDeclared { blocker, .. } => {
+                if !alive(blocker) {
+                    return Err(format!("{:?} blocked while out of the game", blocker));
+                }
+                self.blocker = Some(blocker.clone());
+                self.called_off = Some("blocked");
+            }
+            Event::ChallengeDeclared { challenger, challenged, .. } => {
+                if !alive(challenger) {
+                    return Err(format!("{:?} challenged while out of the game", challenger));
+                }
+                if challenger == challenged {
+                    return Err(format!("{:?} challenged themselves", challenger));
+                }
+            }
+            // A caught bluff calls the action off, and a caught block lets it through
+            Event::ChallengeResolved { challenged, challenger_won: true, .. } => {
+                self.called_off = match &self.blocker {
+                    Some(blocker) if blocker == challenged => None,
+                    _ => Some("successfully challenged"),
+                };
+            }
+            Event::ActionResolved { actor, action } => {
+                if let Some(reason) = self.called_off {
+                    return Err(format!("{:?}'s {:?} went through after it was {}", actor, action, reason));
+                }
+                if !alive(actor) {
+                    return Err(format!("{:?}'s {:?} went through after they were out of the game", actor, action));
+                }
+                for id in &state.turn_order {
+                    let before = self.coins_before[id];
+                    let expected = self.coins_after(actor, action, id, before);
+                    if coins(id) != expected {
+                        return Err(format!(
+                            "{:?} has {} coins after {:?}'s {:?}, expected {}",
+                            id,
+                            coins(id),
+                            actor,
+                            action,
+                            expected
+                        ));
+                    }
+                }
+            }
+            Event::GameOver { winner } => {
+                let survivors: Vec<&PlayerID> = state.turn_order.iter().filter(|id| alive(id)).collect();
+                match winner {
+                    Some(winner) if survivors != [winner] => {
+                        return Err(format!("{:?} won, but {:?} are still in", winner, survivors))
+                    }
+                    None if survivors.is_empty() => return Err("Everyone is out of the game".to_string()),
+                    None if survivors.len() == 1 => {
+                        return Err(format!("No winner, but only {:?} is still in", survivors[0]))
+                    }
+                    None if state.turn < self.max_turns => {
+                        return Err(format!("No winner after {} turns, short of the limit", state.turn))
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // No card is ever made or lost, and everyone holds one card per life plus the cards they
+    // were dealt beyond their lives, which stay hidden even once they're out
+    fn check_cards(
+        &mut self,
+        state: &GameState,
+        hands: &HashMap<PlayerID, Vec<Identity>>,
+        deck: &[Identity],
+    ) -> Result<(), String> {
+        let mut cards = [0; 5];
+        let revealed = state.player_states.values().flat_map(|player| player.lost_lives.iter());
+        for &card in deck.iter().chain(hands.values().flatten()).chain(revealed) {
+            cards[card as usize] += 1;
+        }
+        match &self.cards {
+            None => self.cards = Some(cards),
+            Some(expected) if *expected != cards => {
+                return Err(format!("Cards were made or lost: {:?} became {:?}", expected, cards))
+            }
+            Some(_) => {}
+        }
+        if !self.started {
+            return Ok(());
+        }
+        let spare = self.rules.starting_cards - self.rules.starting_lives;
+        for (id, player) in &state.player_states {
+            let held = hands[id].len();
+            if held != (player.num_lives + spare) as usize {
+                return Err(format!("{:?} holds {} cards with {} lives", id, held, player.num_lives));
+            }
+        }
+        Ok(())
+    }
+
+    // Actions have to be affordable, aimed at someone else still in the game, and a coup once
+    // the player has enough coins that one is forced
+    fn check_action(&self, actor: &PlayerID, action: &Action, state: &GameState) -> Result<(), String> {
+        let coins = state.player_states[actor].num_coins;
+        if coins >= self.rules.required_coup_coins && !matches!(action, Action::Coup(..)) {
+            return Err(format!("{:?} chose {:?} with {} coins, instead of a coup", actor, action, coins));
+        }
+        let (cost, target) = match action {
+            Action::Assassinate(target) => (self.rules.assassinate_cost, Some(target)),
+            Action::Coup(target) => (self.rules.coup_cost, Some(target)),
+            Action::Steal(target) => (0, Some(target)),
+            _ => (0, None),
+        };
+        if coins < cost {
+            return Err(format!("{:?} chose {:?} with only {} coins", actor, action, coins));
+        }
+        if let Some(target) = target {
+            if target == actor || !state.player_states.get(target).is_some_and(|player| player.is_alive()) {
+                return Err(format!("{:?} aimed {:?} at someone who can't be targeted", actor, action));
+            }
+        }
+        Ok(())
+    }
+
+    // How many coins a player should have once an action has been played out
+    fn coins_after(&self, actor: &PlayerID, action: &Action, id: &PlayerID, before: u8) -> u8 {
+        let rules = &self.rules;
+        match action {
+            Action::Income if id == actor => before + rules.income,
+            Action::ForeignAid if id == actor => before + rules.foreign_aid,
+            Action::Tax if id == actor => before + rules.tax,
+            Action::Assassinate(..) if id == actor => before - rules.assassinate_cost,
+            Action::Coup(..) if id == actor => before - rules.coup_cost,
+            Action::Steal(target) if id == actor => before + self.coins_before[target].min(rules.steal_amount),
+            Action::Steal(target) if id == target => before - before.min(rules.steal_amount),
+            _ => before,
+        }
+    }
+}
+
+impl Subscriber for Checker {
+    fn on_event(&mut self, event: &Event, state: &GameState, hands: &HashMap<PlayerID, Vec<Identity>>, deck: &[Identity]) {
+        self.turn = state.turn;
+        if self.violation.is_some() {
+            return;
+        }
+        if let Err(message) = self.check(event, state, hands, deck) {
+            self.violation = Some(Failure {
+                message: format!("{} (at {:?})", message, event),
+                turn: state.turn,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::player::traits::Player;
+    use crate::view::PlayerView;
+
+    /// Answers every decision with something the rules don't allow
+    struct Cheat {
+        id: PlayerID,
+        hand: Vec<Identity>,
+    }
+
+    impl Player for Cheat {
+        fn choose_action(&mut self, _view: &PlayerView) -> Action {
+            Action::Assassinate(self.id.clone())
+        }
+
+        fn will_challenge(&mut self, _view: &PlayerView, _player_id: &PlayerID, _action: &Action) -> bool {
+            false
+        }
+
+        fn will_block(&mut self, _view: &PlayerView, _player_id: &PlayerID, _action: &Action) -> Option<Action> {
+            Some(Action::Tax)
+        }
+
+        fn choose_card_to_replace(&mut self, _view: &PlayerView, _card: &Identity) -> Option<usize> {
+            Some(9)
+        }
+
+        fn choose_card_to_lose(&mut self, _view: &PlayerView) -> usize {
+            9
+        }
+
+        fn choose_forced_coup(&mut self, _view: &PlayerView) -> PlayerID {
+            self.id.clone()
+        }
+
+        fn get_hand(&self) -> Vec<Identity> {
+            self.hand.to_vec()
+        }
+
+        fn set_hand(&mut self, hand: Vec<Identity>) {
+            self.hand = hand;
+        }
+
+        fn who_am_i(&self) -> &PlayerID {
+            &self.id
+        }
+    }
+
+    #[test]
+    fn random_games_keep_the_invariants() {
+        for game in 0..300 {
+            let case = random_case(game_seed(39, game), 300);
+            if let Err(failure) = run_case(&case) {
+                let (case, failure) = shrink(case, failure);
+                panic!("{}\n{}", failure.message, serde_json::to_string(&case).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn rule_breaking_choices_are_swapped_for_the_safe_one() {
+        for seed in 0..50 {
+            let case = Case {
+                seed,
+                players: vec!["random".to_string(), "honest".to_string(), "dumb".to_string()],
+                rules: Rules::default(),
+                max_turns: 300,
+            };
+            let mut game = case.game().unwrap();
+            let cheat = PlayerID(0);
+            let player = Cheat {
+                id: cheat.clone(),
+                hand: Vec::new(),
+            };
+            game.take_seat(&cheat, "Cheat".to_string(), Box::new(player));
+            let checker = Rc::new(RefCell::new(Checker::new(case.rules, case.max_turns)));
+            game.subscribe(Box::new(checker.clone()));
+            game.play();
+            let violation = checker.borrow_mut().violation.take();
+            if let Some(failure) = violation {
+                panic!("Seed {}: {}", seed, failure.message);
+            }
+        }
+    }
+
+    #[test]
+    fn saved_failures_stay_fixed() {
+        for line in include_str!("regressions.jsonl").lines().filter(|line| !line.trim().is_empty()) {
+            let saved: Saved = serde_json::from_str(line).unwrap();
+            if let Err(failure) = run_case(&saved.case) {
+                panic!("{}\nwas: {}\n{}", failure.message, saved.failure, line);
+            }
+        }
+    }
+}
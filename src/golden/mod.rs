@@ -0,0 +1,92 @@
+//! Golden games: known seeds and tables played through the engine, with every event compared
+//! against a recorded transcript.
+//!
+//! Each file in `games/` holds a game on its first line, in the same form the fuzzer saves cases
+//! in, followed by the events it produced, one JSON object per line, private events included.
+//! A change to the rules engine that plays any of them out differently shows up as a diff
+//! against the transcript. If the change is meant to, rerun the tests with `UPDATE_GOLDEN=1` to
+//! record the new transcripts and review them in the git diff. A new golden game only needs its
+//! first line writing before doing the same.
+
+use crate::event::{Event, Subscriber};
+use crate::fuzz::Case;
+use crate::{GameState, Identity, PlayerID};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+// Lines either side of the first difference shown in a diff
+const CONTEXT: usize = 3;
+
+/// Writes down every event as JSON
+#[derive(Default)]
+struct Recorder {
+    events: Vec<String>,
+}
+
+impl Subscriber for Recorder {
+    fn on_event(&mut self, event: &Event, _state: &GameState, _hands: &HashMap<PlayerID, Vec<Identity>>, _deck: &[Identity]) {
+        self.events.push(serde_json::to_string(event).unwrap());
+    }
+}
+
+fn games() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/golden/games");
+    let mut games: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "jsonl"))
+        .collect();
+    games.sort();
+    games
+}
+
+fn transcript(case: &Case) -> Vec<String> {
+    let recorder = Rc::new(RefCell::new(Recorder::default()));
+    let mut game = case.game().unwrap();
+    game.subscribe(Box::new(recorder.clone()));
+    game.play();
+    let events = recorder.borrow().events.clone();
+    events
+}
+
+// The first place two transcripts part ways, with a little of what came before
+fn diff(expected: &[String], actual: &[String]) -> Option<String> {
+    let first = (0..expected.len().max(actual.len())).find(|&i| expected.get(i) != actual.get(i))?;
+    let start = first.saturating_sub(CONTEXT);
+    let mut lines = vec![format!("@@ event {} of {} (expected {})", first + 1, actual.len(), expected.len())];
+    lines.extend(expected[start..first].iter().map(|line| format!("  {}", line)));
+    lines.extend(expected.iter().skip(first).take(CONTEXT).map(|line| format!("- {}", line)));
+    lines.extend(actual.iter().skip(first).take(CONTEXT).map(|line| format!("+ {}", line)));
+    Some(lines.join("\n"))
+}
+
+#[test]
+fn golden_games_replay_the_same() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let mut failures = Vec::new();
+    let games = games();
+    assert!(!games.is_empty(), "No golden games found");
+    for path in games {
+        let contents = fs::read_to_string(&path).unwrap();
+        let mut lines = contents.lines();
+        let header = lines.next().unwrap_or_default();
+        let case: Case = serde_json::from_str(header).unwrap();
+        let expected: Vec<String> = lines.map(str::to_string).collect();
+        let actual = transcript(&case);
+        if update {
+            let mut updated = vec![header.to_string()];
+            updated.extend(actual);
+            fs::write(&path, updated.join("\n") + "\n").unwrap();
+        } else if let Some(diff) = diff(&expected, &actual) {
+            failures.push(format!("{}\n{}", path.display(), diff));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "Golden games played out differently. Rerun with UPDATE_GOLDEN=1 if that's intended\n\n{}",
+        failures.join("\n\n")
+    );
+}
@@ -1,15 +1,16 @@
 use crate::logger::traits::Logger;
-use log::{info};
 
+/// Prints straight to stdout. Each game owns its logger, so games running side by side
+/// don't share a sink.
 pub struct LocalLogger {}
 
 
 impl Logger for LocalLogger {
     fn log(&self, message: String) {
-	info!("{}", message);
+	println!("{}", message);
     }
 
     fn present(&self, message: String) {
-	info!("{}", message);
+	println!("{}", message);
     }
 }
@@ -1,2 +1,3 @@
 pub mod local_logger;
+pub mod quiet_logger;
 pub mod traits;
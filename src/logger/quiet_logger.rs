@@ -0,0 +1,10 @@
+use crate::logger::traits::Logger;
+
+/// Drops everything, for games nobody is watching
+pub struct QuietLogger {}
+
+impl Logger for QuietLogger {
+    fn log(&self, _message: String) {}
+
+    fn present(&self, _message: String) {}
+}
@@ -1,26 +1,66 @@
 mod action;
+mod api;
+mod compact;
+mod compare;
+mod event;
+mod fuzz;
+#[cfg(test)]
+mod golden;
 mod logger;
+mod net;
 mod player;
 mod prompter;
+mod rules;
+mod seat;
+mod simulate;
+mod spectate;
+mod step;
+mod stats;
+mod sweep;
+mod tournament;
+mod view;
 
 use action::Action;
-use anyhow::Result;
+use api::ApiConfig;
+use compact::bench::BenchConfig;
+use compact::CompactState;
+use compare::CompareConfig;
+use anyhow::{anyhow, Result};
+use event::{Event, Subscriber};
+use fuzz::FuzzConfig;
 use structopt::clap::arg_enum;
 use enumset::{EnumSet, EnumSetType};
 use logger::local_logger::LocalLogger;
+use logger::quiet_logger::QuietLogger;
 use logger::traits::Logger;
+use net::lobby::LobbyConfig;
+use net::{JoinConfig, ServeConfig};
+use spectate::{SpectateConfig, WatchConfig};
 use player::dumb_player::DumbPlayer;
+use player::external_player::ExternalPlayer;
+use player::honest_player::HonestPlayer;
 use player::human_player::HumanPlayer;
-use player::random_player::RandomPlayer;
+use player::random_player::{RandomPlayer, RandomWeights};
+use player::table_talk::{TableTalk, Talk};
 use player::traits::Player;
-use prompter::{LocalPrompter, Prompter};
+use prompter::tui::TuiPrompter;
+use prompter::{HotSeat, LocalPrompter, Prompter};
+use rules::Rules;
 use rand::seq::SliceRandom;
-use std::cmp::min;
-use std::collections::HashMap;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use seat::{Choice, Seat, TimeoutPolicy};
+use simulate::SimulateConfig;
+use step::{Decision, Progress};
+use sweep::SweepConfig;
+use tournament::TournamentConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
-use std::fmt::{Display};
 use structopt::StructOpt;
+use view::{PlayerNames, PlayerView};
 
+use std::time::Duration;
 use std::{thread, time};
 
 
@@ -28,25 +68,30 @@ use std::{thread, time};
 
 // Game change turns
 // Every Player
-#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
 pub struct PlayerID(u8);
 
-#[derive(Debug)]
-struct Challenge {
-    actor_id: PlayerID,
-    challenger_id: PlayerID,
-    action: Action,
-}
-
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
 pub enum PlayerType {
     DumbCPU,
-    RandomCPU,
+    // Never bluffs
+    HonestCPU,
+    // Picks among its legal choices with the given weights
+    RandomCPU(RandomWeights),
+    // Runs the given command and talks to it over stdin/stdout
+    ExternalCPU(String),
     Local,
+    // A local player taking turns at the terminal with the other local players, who is handed it
+    // before each of their decisions
+    HotSeat,
+    // A local player on the full-screen terminal UI
+    Tui,
 }
 
 pub enum LoggerType {
     Local,
+    // Nothing is logged, for headless games
+    Quiet,
 }
 
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
@@ -65,14 +110,16 @@ impl PlayerConfig {
 }
 
 // Holds internal state about the current game
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct GameState {
     // TODO = Convenience Cache consider removing
     active_players: Vec<PlayerID>,
-    num_cards: u8,
     player_states: HashMap<PlayerID, PlayerState>,
     turn_order: Vec<PlayerID>,
-    // history -> Vec of Turns?
+    // Every public event so far
+    history: Vec<Event>,
+    turn: u32,
+    rules: Rules,
 }
 
 pub struct GameDriver {
@@ -80,7 +127,34 @@ pub struct GameDriver {
     // Need to be stored?
     // Do I need to make static / store playerID to player map
     // Holds the autonomous players
-    players: HashMap<PlayerID, Box<dyn Player>>,
+    players: HashMap<PlayerID, Seat>,
+    // The real hands. Players are only ever given a copy
+    hands: HashMap<PlayerID, Vec<Identity>>,
+}
+
+/// The longest chat message a player can send, in characters. Anything more is cut off.
+pub const MAX_CHAT: usize = 200;
+
+/// The random number generator behind a game and its bots. Seeded, so games can be replayed.
+pub type GameRng = ChaCha8Rng;
+
+/// How a finished game turned out
+#[derive(Debug, Clone)]
+pub struct GameResult {
+    // None if the game was called off, or somehow ended without a single survivor
+    pub winner: Option<PlayerID>,
+    // Everyone who was knocked out, in the order they went
+    pub eliminated: Vec<PlayerID>,
+    pub turns: u32,
+    // How many times each player ran out of time on the decision clock
+    pub timeouts: BTreeMap<PlayerID, u32>,
+}
+
+// How long cpu players get to make each decision, and what happens when they run out
+#[derive(Debug, Clone, Copy)]
+pub struct DecisionClock {
+    budget: Duration,
+    policy: TimeoutPolicy,
 }
 
 /// Main struct for the game
@@ -88,42 +162,62 @@ pub struct Game {
     driver: GameDriver,
     state: GameState,
     logger: Box<dyn Logger>,
-    interactive: bool
+    interactive: bool,
+    clock: Option<DecisionClock>,
+    // The game is called off without a winner after this many turns
+    turn_limit: Option<u32>,
+    // Or once the same position has come round this many times
+    repetition_limit: Option<u32>,
+    // How often each position has been seen at the start of a turn, by Zobrist hash
+    positions: HashMap<u64, u32>,
+    repeated: bool,
+    // Every random decision the game makes comes from here, so a seed replays the same game
+    rng: GameRng,
+    subscribers: Vec<Box<dyn Subscriber>>,
+    // Where the game has got to, between decisions
+    progress: Progress,
 }
 
 impl Game {
     // Will need to decide on how to assign players / who is playing
     pub fn new(
-        identities: EnumSet<Identity>,
+        rules: Rules,
         players: Vec<PlayerConfig>,
         logger_type: LoggerType,
-    ) -> Self {
+        seed: u64,
+    ) -> Result<Self> {
+        let mut rng = GameRng::seed_from_u64(seed);
         let logger = match logger_type {
             LoggerType::Local => Box::new(LocalLogger {}) as Box<dyn Logger>,
+            LoggerType::Quiet => Box::new(QuietLogger {}) as Box<dyn Logger>,
         };
 
-        let num_players = players.len();
-        let num_cards = match num_players {
-            1..=4 => 3,
-            _ => 4,
-        };
+        rules.check(players.len())?;
+        let num_cards = rules.cards_per_identity(players.len());
 
-        let mut driver = GameDriver::new(identities, num_cards);
+        let mut driver = GameDriver::new(rules.identities, num_cards);
 
         // TODO --> This is bad. Not populated yet?
         let turn_order = driver.players.keys().cloned().collect();
-        let mut state = GameState::new(num_cards, turn_order);
+        let mut state = GameState::new(turn_order, rules);
 
-        let mut player_id = 0;
 	let mut interactive = false;
-        for entry in players {
-	    let id = PlayerID(player_id.clone());
-            player_id = player_id + 1;
+        // Shared by the players taking turns at the terminal
+        let hot_seat = HotSeat::default();
+        for (player_id, entry) in (0..).zip(players) {
+	    let id = PlayerID(player_id);
 	    let mut name = entry.player_name;
             // Create Player
+            let cpu = !matches!(entry.player_type, PlayerType::Local | PlayerType::HotSeat | PlayerType::Tui);
             let player = match entry.player_type {
                 PlayerType::DumbCPU => Box::new(DumbPlayer::new(id.clone())) as Box<dyn Player>,
-                PlayerType::RandomCPU => Box::new(RandomPlayer::new(id.clone())) as Box<dyn Player>,
+                PlayerType::HonestCPU => Box::new(HonestPlayer::new(id.clone())) as Box<dyn Player>,
+                PlayerType::RandomCPU(weights) => {
+                    Box::new(RandomPlayer::with_weights(id.clone(), weights, rng.gen())) as Box<dyn Player>
+                }
+                PlayerType::ExternalCPU(command) => {
+                    Box::new(ExternalPlayer::new(id.clone(), &name, &command)?) as Box<dyn Player>
+                }
                 PlayerType::Local => {
 		    // Existence of local player makes game interactive
 		    interactive = true;
@@ -134,53 +228,242 @@ impl Game {
 		    player_prompter.set_name(name.clone());
 		    Box::new(HumanPlayer::new(id.clone(), player_prompter)) as Box<dyn Player>
 		}
+                PlayerType::HotSeat => {
+                    interactive = true;
+                    let mut player_prompter = LocalPrompter::new();
+                    println!("Creating a new local player!\nPlease enter your name:");
+                    name = player_prompter.prompt_player(None)?;
+                    player_prompter.set_name(name.clone());
+                    player_prompter.set_hot_seat(hot_seat.clone());
+                    Box::new(HumanPlayer::new(id.clone(), player_prompter)) as Box<dyn Player>
+                }
+                PlayerType::Tui => {
+                    // The log is on screen, so there's no need to wait between events
+                    let mut player_prompter = TuiPrompter::new()?;
+                    name = player_prompter.prompt_text("Please enter your name", None)?;
+                    player_prompter.set_name(name.clone());
+                    Box::new(HumanPlayer::new(id.clone(), player_prompter)) as Box<dyn Player>
+                }
             };
             state.player_states.insert(
                 id.clone(),
-                PlayerState::new(name, STARTING_LIVES),
+                PlayerState::new(name, rules.starting_lives, rules.starting_coins),
             );
 
-            driver.players.insert(id.clone(), player);
+            driver.players.insert(id.clone(), Seat::new(player, cpu));
+            driver.hands.insert(id.clone(), Vec::new());
         }
-	state.update_turn_order(driver.players.keys().cloned().collect());
+	// TODO - Establish turn order -> Roll for it? Then clockwise?
+	// For now players go in the order they were configured
+	let mut turn_order: Vec<PlayerID> = driver.players.keys().cloned().collect();
+	turn_order.sort();
+	state.update_turn_order(turn_order);
 
-        Self {
+        Ok(Self {
             driver,
             state,
             logger,
 	    interactive,
+            clock: None,
+            turn_limit: None,
+            repetition_limit: None,
+            positions: HashMap::new(),
+            repeated: false,
+            rng,
+            subscribers: Vec::new(),
+            progress: Progress::default(),
+        })
+    }
+
+    /// Puts a player who has joined from elsewhere in a seat, in place of whoever was configured
+    /// there. They are never put on the clock.
+    pub fn take_seat(&mut self, id: &PlayerID, name: String, player: Box<dyn Player>) {
+        self.driver.players.insert(id.clone(), Seat::new(player, false));
+        self.state.player_states.get_mut(id).unwrap().player_name = name;
+    }
+
+    /// Gives every cpu player a time budget for each decision
+    pub fn set_decision_clock(&mut self, budget: Duration, policy: TimeoutPolicy) {
+        self.driver.players = self
+            .driver
+            .players
+            .drain()
+            .map(|(id, seat)| (id, seat.start_clock()))
+            .collect();
+        self.clock = Some(DecisionClock { budget, policy });
+    }
+
+    /// Has every cpu player talk the way given. Call before putting them on the clock.
+    pub fn set_bot_talk(&mut self, talk: Talk) {
+        self.driver.players = self
+            .driver
+            .players
+            .drain()
+            .map(|(id, seat)| (id, seat.wrap(|player| Box::new(TableTalk::new(player, talk)))))
+            .collect();
+    }
+
+    /// Ends the game without a winner once this many turns have been played, so bots that
+    /// can't finish each other off don't play forever
+    pub fn set_turn_limit(&mut self, turn_limit: u32) {
+        self.turn_limit = Some(turn_limit);
+    }
+
+    /// Ends the game without a winner once the same position, down to the cards in every hand
+    /// and the deck, has come round this many times at the start of a turn
+    pub fn set_repetition_limit(&mut self, repetition_limit: u32) {
+        self.repetition_limit = Some(repetition_limit);
+    }
+
+    /// Adds someone to watch the game from outside the table
+    pub fn subscribe(&mut self, subscriber: Box<dyn Subscriber>) {
+        self.subscribers.push(subscriber);
+    }
+
+    fn publish(&mut self, event: &Event) {
+        for subscriber in &mut self.subscribers {
+            subscriber.on_event(event, &self.state, &self.driver.hands, &self.driver.field.deck);
+        }
+    }
+
+    fn out_of_turns(&self) -> bool {
+        self.repeated || self.turn_limit.is_some_and(|limit| self.state.turn >= limit)
+    }
+
+    // Counts the position as the given player starts their turn, and notes when it has come
+    // round too often. Tables too big for a compact state aren't checked.
+    fn record_position(&mut self, player_id: &PlayerID) {
+        let limit = match self.repetition_limit {
+            Some(limit) => limit,
+            None => return,
+        };
+        let position = CompactState::from_game(&self.state, &self.driver.hands, &self.driver.field.deck, player_id);
+        if let Ok(position) = position {
+            let seen = self.positions.entry(position.zobrist()).or_insert(0);
+            *seen += 1;
+            if *seen >= limit {
+                self.repeated = true;
+            }
         }
     }
 
     fn shuffle(&mut self) {
-        let mut rng = rand::thread_rng();
-        let deck_length = self.driver.field.deck.len().clone();
-        let mut deck = &mut self.driver.field.deck;
-        &deck[0..deck_length].shuffle(&mut rng);
+        self.driver.field.deck.shuffle(&mut self.rng);
     }
 
     // Should this be in driver? Should driver be flattened to game?
-    fn deal(&mut self, player_order: &Vec<PlayerID>) {
-        for _ in 0..STARTING_CARDS {
+    fn deal(&mut self, player_order: &[PlayerID]) {
+        for _ in 0..self.state.rules.starting_cards {
             for id in player_order {
-                let mut deck = &mut self.driver.field.deck;
-                let card = deck.remove(0);
-                self.driver
-                    .players
-                    .get_mut(&id)
-                    .unwrap()
-                    .take_card(&self.state, card);
+                let card = self.driver.field.deck.remove(0);
+                self.driver.hands.get_mut(id).unwrap().push(card);
+                self.emit(Event::CardDealt { player: id.clone(), identity: card });
+            }
+        }
+    }
+
+    /// Logs a public event and tells every player about it. Private events only go to the
+    /// player they concern.
+    fn emit(&mut self, event: Event) {
+        self.publish(&event);
+        match event.private_to() {
+            Some(player_id) => {
+                if let Some(player) = self.driver.players.get_mut(player_id) {
+                    player.observe(&event);
+                }
+            }
+            None => {
+                self.logger.log(event.describe(&self.state));
+                self.notify_all(event.clone());
+                self.state.history.push(event);
             }
         }
     }
 
-    fn update_active_players(&mut self, player_order: &Vec<PlayerID>) {
+    /// Puts a decision to the player in the seat, handing them the view of the game their seat
+    /// is allowed to see. Cpu players that run out of time get the clock's fallback.
+    fn ask(&mut self, decision: &Decision) -> Choice {
+        let player_id = &decision.seat;
+        let view = PlayerView::new(
+            &self.state,
+            player_id,
+            self.driver.hands.get(player_id).unwrap().clone(),
+            self.driver.field.deck.len(),
+            decision.pending.clone(),
+        );
+        let budget = self.clock.map(|clock| clock.budget);
+        let seat = self.driver.players.get_mut(player_id).unwrap();
+        let choice = match seat.decide(&view, budget) {
+            // Choices the rules don't allow are swapped for the safe default
+            Ok(choice) if decision.options.contains(&choice) => return choice,
+            Ok(choice) => {
+                self.logger.log(format!(
+                    "{} can't choose {:?}, using the default instead",
+                    self.get_player_name(player_id),
+                    choice
+                ));
+                return seat::safe_default(&view);
+            }
+            Err(_) => seat::safe_default(&view),
+        };
+        let policy = self.clock.unwrap().policy;
+        let choice = match policy {
+            TimeoutPolicy::SafeDefault | TimeoutPolicy::Forfeit => choice,
+            TimeoutPolicy::Random => {
+                let mut replacement = RandomPlayer::new(player_id.clone(), self.rng.gen());
+                let replacement_choice = seat::decide(&mut replacement, &view);
+                seat.replace(Box::new(replacement));
+                if decision.options.contains(&replacement_choice) {
+                    replacement_choice
+                } else {
+                    choice
+                }
+            }
+        };
+        self.emit(Event::DecisionTimedOut {
+            player: player_id.clone(),
+            policy,
+        });
+        if policy == TimeoutPolicy::Forfeit {
+            self.forfeit(player_id);
+        }
+        choice
+    }
+
+    /// Puts whatever the players have said since they were last heard into the game as chat,
+    /// going round the table in turn order
+    fn hear(&mut self) {
+        for id in self.state.turn_order.clone() {
+            let said = match self.driver.players.get_mut(&id) {
+                Some(seat) => seat.talk(),
+                None => continue,
+            };
+            for message in said {
+                // Players only get to say a line of plain text
+                let message: String = message.chars().filter(|c| !c.is_control()).take(MAX_CHAT).collect();
+                let message = message.trim();
+                if !message.is_empty() {
+                    self.emit(Event::Chat {
+                        player: id.clone(),
+                        message: message.to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    fn notify_all(&mut self, event: Event) {
+        for player in self.driver.players.values_mut() {
+            player.observe(&event);
+        }
+    }
+
+    fn update_active_players(&mut self, player_order: &[PlayerID]) {
         self.state.active_players = self.active_players(player_order);
     }
 
     fn wait(&self) {
 	let second = time::Duration::from_millis(1000);
-	let now = time::Instant::now();
 	thread::sleep(second);
     }
 
@@ -190,277 +473,126 @@ impl Game {
 	}
     }
 
-    fn check_for_challenges(
-        &self,
-        actor_id: &PlayerID,
-        turn_order: &Vec<PlayerID>,
-        action: &Action,
-    ) -> Option<Challenge> {
-        for challenger_id in &self.active_players(turn_order) {
-            // Can't challenge yourself
-            if challenger_id == actor_id {
-                continue;
-            }
-            let challenger = self.driver.players.get(challenger_id).unwrap();
-            if challenger.will_challenge(&self.state, actor_id, action) {
-                return Some(Challenge {
-                    actor_id: actor_id.clone(),
-                    challenger_id: challenger_id.clone(),
-                    action: action.clone(),
-                });
-            }
-        }
-        None
-    }
-
     pub fn setup(&mut self) {
-	// TODO - Establish turn order -> Roll for it? Then clockwise?
-        // Find a way not to do this twice
-        let turn_order = self.driver.players.keys().cloned().collect();
+        let turn_order = self.state.turn_order.clone();
         self.shuffle();
         self.deal(&turn_order);
         self.update_active_players(&turn_order);
 
-	// TODO --> Remove / detect if interactive mode.
-	// Could check for local players?
-	println!("Let the game begin!");
-	print!("The turn order is as follows: ");
-	for player in &turn_order {
-	    print!("{} ", self.state.get_player_name(player));
-	}
-	println!("");
-    }
-
-    pub fn play(&mut self) {
-	self.setup();
-	let turn_order = self.driver.players.keys().cloned().collect();
-        // Start Game Loop
-        while !self.game_over(&turn_order) {
-            // Need to check game over everytime state changes. --> Sad
-            let active_players = &self.active_players(&turn_order);
-            for active_id in active_players {
-		self.logger.log(format!(
-		    "{}'s turn!", self.state.get_player_name(active_id))
-		);
-		self.wait_if_interactive();
-                let player = self.driver.players.get(active_id).unwrap();
-
-                // Enforce Required Coup
-                let action = if player.count_coins(&self.state) < REQUIRE_COUP_COINS {
-                    player.choose_action(&self.state)
-                } else {
-                    Action::Coup(player.choose_forced_coup(&self.state))
-                };
-
-                self.logger.log(
-                    format!(
-                        "{} chose action {}",
-                        self.get_player_name(active_id),
-                        LocalPrompter::display_action(&self.state, action.clone())
-                    )
-                    .to_string(),
-                );
-
-                // TODO -> This is overly complex, and does not allow things to be challenged if someone wants
-                // to block. Would like to be able to choose these at the same time
-                let mut block_was_challenged = false;
-
-                // Allow for actions to be blocked
-                for blocker_id in active_players {
-		    // Don't block yourself
-		    if blocker_id == active_id {
-			continue;
-		    }
-		    
-                    if action.blockable(blocker_id).is_some() {
-                        let blocker = self.driver.players.get(blocker_id).unwrap();
-                        // actor steal from blocker
-                        if let Some(blocking_action) =
-                            blocker.will_block(&self.state, &active_id, &action)
-                        {
-			    self.wait_if_interactive();
-                            // blocker block
-                            self.logger.log(format!(
-                                "{} is blocking {}'s {} with {}",
-                                self.get_player_name(blocker_id),
-                                self.get_player_name(active_id),
-                                LocalPrompter::display_action(&self.state, action.clone()),
-                                LocalPrompter::display_action(&self.state, blocking_action.clone()),
-                            ));
-                            if let Some(challenge) =
-                                self.check_for_challenges(blocker_id, &turn_order, &blocking_action)
-                            {
-                                // actor challenge your block
-                                block_was_challenged = true;
-                                if !self.process_challenge(&challenge) {
-                                    //Challenge was unsuccessful, action is blocked, turn is over
-                                    continue;
-                                } // Challenge was successful, block is invalidated, action goes through
-                            } else {
-                                // Block was unchallenged, do not allow anyone to challenge
-                                continue;
-                            }
-                        }
-                    }
-                }
+        self.logger.present("Let the game begin!".to_string());
+        self.emit(Event::GameStarted { turn_order });
+    }
 
-                // TODO --> Do not allow for challenging of action if already blocked
-                if action.challengable() && !block_was_challenged {
-                    if let Some(challenge) =
-                        self.check_for_challenges(active_id, &turn_order, &action)
-                    {
-                        self.process_challenge(&challenge);
-                    }
-                }
-                self.process_action(&action, active_id);
-                self.update_active_players(&turn_order);
+    /// Plays the game to the end, putting each decision to the player in the seat, and
+    /// reports who won
+    pub fn play(&mut self) -> GameResult {
+        self.play_until(|_| false);
+        self.result().unwrap().clone()
+    }
 
-                // TODO --> This makes me very sad
-                if self.game_over(&turn_order) {
-                    break;
-                }
+    /// Plays on, putting each decision to the player in the seat, until one comes up for a seat
+    /// the caller holds, and returns it for the caller to `submit`. None once the game is over.
+    pub fn play_until(&mut self, holds: impl Fn(&PlayerID) -> bool) -> Option<Decision> {
+        while let Some(decision) = self.next_decision() {
+            self.hear();
+            if holds(&decision.seat) {
+                return Some(decision);
             }
+            let choice = self.ask(&decision);
+            // What was said while deciding comes before what the decision leads to
+            self.hear();
+            self.submit(choice).expect("Players are only ever given legal choices");
         }
-	self.wait_if_interactive();
-        self.present_game_results();
-    }
-
-    fn process_challenge(&mut self, challenge: &Challenge) -> bool {
-        let actor_id = &challenge.actor_id;
-        let challenger_id = &challenge.challenger_id;
-        let action = &challenge.action;
-	
-	self.wait_if_interactive();
-        self.logger.log(format!(
-            "{} is challenging {}'s {}",
-            self.get_player_name(challenger_id),
-            self.get_player_name(actor_id),
-            LocalPrompter::display_action(&self.state, action.clone())
-        ));
-
-        let mut winner_id = actor_id;
-        let mut loser_id = actor_id;
-
-        let actor = self.driver.players.get_mut(actor_id).unwrap();
-        if actor.can_do_action(action) {
-            loser_id = challenger_id;
-        } else {
-            winner_id = challenger_id;
+        None
+    }
+
+    fn present_game_results(&mut self) -> GameResult {
+        let winner = match self.state.active_players.as_slice() {
+            [winner] => Some(winner.clone()),
+            _ => None,
+        };
+        let event = Event::GameOver { winner: winner.clone() };
+        self.logger.present(event.describe(&self.state));
+        self.publish(&event);
+        self.notify_all(event);
+
+        let mut timeouts: BTreeMap<PlayerID, u32> =
+            self.state.turn_order.iter().map(|player| (player.clone(), 0)).collect();
+        for event in &self.state.history {
+            if let Event::DecisionTimedOut { player, .. } = event {
+                *timeouts.entry(player.clone()).or_insert(0) += 1;
+            }
         }
-	
-	self.wait_if_interactive();
-        self.logger.log(format!(
-            "{} lost the challenge",
-            self.get_player_name(loser_id)
-        ));
-	self.wait_if_interactive();
-        self.kill_player(loser_id);
-        // let winner = self.driver.players.get_mut(winner_id).unwrap();
-        // TODO - Give winner a card from the deck
-        return challenger_id == winner_id;
-    }
-
-    fn present_game_results(&self) {
-        if self.state.active_players.len() != 1 {
-            self.logger
-                .log(format!("Uh oh... a lot of people won?").to_string());
-        } else {
-            self.logger.log(
-                format!(
-                    "{} won!",
-                    self.get_player_name(&self.state.active_players[0])
-                )
-                .to_string(),
-            );
+        for player in &self.state.turn_order {
+            let count = timeouts[player];
+            if count > 0 {
+                self.logger.present(format!(
+                    "{} ran out of time {} time(s)",
+                    self.get_player_name(player),
+                    count
+                ));
+            }
+        }
+        let eliminated = self
+            .state
+            .history
+            .iter()
+            .filter_map(|event| match event {
+                Event::PlayerEliminated { player } => Some(player.clone()),
+                _ => None,
+            })
+            .collect();
+        GameResult {
+            winner,
+            eliminated,
+            turns: self.state.turn,
+            timeouts,
         }
     }
 
-    fn kill_player(&mut self, player_id: &PlayerID) {
-        let mut victim = self.driver.players.get_mut(player_id).unwrap();
-	let num_lives_left = self.state.player_states.get(player_id).unwrap().num_lives;
-	if num_lives_left == 0 {
-	    self.logger.log(
-		format!("Tried to kill {} but they have no lives left!",
-			self.get_player_name(player_id)
-		)
-	    );
-	    return;
-	}
-        let to_discard = victim.choose_card_to_lose(&self.state);
-        let discarded = victim.discard(to_discard).unwrap();
-        self.logger.log(format!(
-            "{} discarded {:#?}",
-	    self.get_player_name(player_id),
-            discarded
-        ));
-        let mut victim_state = self.state.player_states.get_mut(player_id).unwrap();
+    // Turns over one of a player's cards, falling back to the first if the index is bad
+    fn reveal_card(&mut self, player_id: &PlayerID, index: usize) {
+        let hand = self.driver.hands.get_mut(player_id).unwrap();
+        let index = if index < hand.len() { index } else { 0 };
+        let discarded = hand.remove(index);
+        let victim_state = self.state.player_states.get_mut(player_id).unwrap();
         victim_state.lost_lives.push(discarded);
         victim_state.num_lives -= 1;
+        let eliminated = !victim_state.is_alive();
+        self.emit(Event::CardLost { player: player_id.clone(), identity: discarded });
+        if eliminated {
+            self.emit(Event::PlayerEliminated { player: player_id.clone() });
+        }
     }
 
-    fn process_action(&mut self, action: &Action, actor: &PlayerID) {
-        match action {
-            // TODO All constants should be defined
-            Action::Income => {
-                let mut player = self.state.player_states.get_mut(&actor).unwrap();
-                player.num_coins += 1;
-            }
-            Action::ForeignAid => {
-                let mut player = self.state.player_states.get_mut(&actor).unwrap();
-                player.num_coins += 2;
-            }
-            Action::Tax => {
-                let mut player = self.state.player_states.get_mut(&actor).unwrap();
-                player.num_coins += 3;
-            }
-            Action::Steal(target) => {
-                let mut target = self.state.player_states.get_mut(&target).unwrap();
-                let coins_to_steal = min(target.num_coins, 2);
-                target.num_coins -= coins_to_steal;
-                let mut player = self.state.player_states.get_mut(&actor).unwrap();
-                player.num_coins += coins_to_steal;
-            }
-            // TODO Trying a blocked assassination should still result in side effect
-            Action::Assassinate(target) => {
-                let mut player = self.state.player_states.get_mut(&actor).unwrap();
-                player.num_coins -= 3;
-                self.kill_player(target);
-            }
-            Action::Coup(target) => {
-                let mut player = self.state.player_states.get_mut(&actor).unwrap();
-                player.num_coins -= 7;
-                self.kill_player(target);
-            }
-            _ => {
-                self.logger
-                    .log(format!("Unknown action... Moving on {:?}", action).to_string());
-            }
+    // Knocks a player out of the game by turning over all of their cards
+    fn forfeit(&mut self, player_id: &PlayerID) {
+        while self.is_player_alive(player_id) {
+            self.reveal_card(player_id, 0);
         }
     }
 
-    fn game_over(&self, players: &Vec<PlayerID>) -> bool {
-        let mut num_alive = 0;
-        for id in players {
-            if self.state.player_states.get(&id).unwrap().num_lives > 0 {
-                num_alive += 1;
-            }
-        }
+    fn game_over(&self, players: &[PlayerID]) -> bool {
+        let num_alive = players
+            .iter()
+            .filter(|id| self.is_player_alive(id))
+            .count();
         num_alive <= 1
     }
-    fn active_players(&self, players: &Vec<PlayerID>) -> Vec<PlayerID> {
+    fn active_players(&self, players: &[PlayerID]) -> Vec<PlayerID> {
         players
-            .into_iter()
-            .cloned()
+            .iter()
             .filter(|id| self.is_player_alive(id))
+            .cloned()
             .collect()
     }
 
     fn is_player_alive(&self, player_id: &PlayerID) -> bool {
-        self.state.player_states.get(&player_id).unwrap().is_alive()
+        self.state.player_states.get(player_id).unwrap().is_alive()
     }
 
     fn get_player_name(&self, player_id: &PlayerID) -> String {
-        self.state.get_player_name(&player_id)
+        self.state.get_player_name(player_id)
     }
 }
 
@@ -471,7 +603,7 @@ impl fmt::Display for GameState {
 	    let player_state = self.player_states.get(player);
 	    match player_state {
 		Some(player_state) => {
-		    println!("{}", player_state);
+		    writeln!(f, "{}", player_state)?;
 		},
 		None => {
 		    panic!("Turn order and player states out of sync")
@@ -484,33 +616,39 @@ impl fmt::Display for GameState {
 
 
 impl GameState {
-    fn new(num_cards: u8, turn_order: Vec<PlayerID>) -> Self {
+    fn new(turn_order: Vec<PlayerID>, rules: Rules) -> Self {
         let player_states = HashMap::new();
         Self {
-            num_cards,
             player_states,
-            active_players: turn_order.iter().cloned().collect(),
+            active_players: turn_order.clone(),
             turn_order,
+            history: Vec::new(),
+            turn: 0,
+            rules,
         }
     }
-    fn get_player_name(&self, player_id: &PlayerID) -> String {
-        self.player_states.get(&player_id).unwrap().get_name()
-    }
     fn update_turn_order(&mut self, turn_order: Vec<PlayerID>) {
 	self.turn_order = turn_order;
     }
 }
 
+impl PlayerNames for GameState {
+    fn get_player_name(&self, player_id: &PlayerID) -> String {
+        self.player_states.get(player_id).unwrap().get_name()
+    }
+}
+
 impl GameDriver {
     fn new(identities: EnumSet<Identity>, num_cards: u8) -> Self {
         let field = GameField::new(identities, num_cards);
         let players = HashMap::new();
-        Self { field, players }
+        let hands = HashMap::new();
+        Self { field, players, hands }
     }
 }
 
 // This is public information about a player
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct PlayerState {
     lost_lives: Vec<Identity>,
     player_name: String,
@@ -521,15 +659,15 @@ pub struct PlayerState {
 
 impl fmt::Display for PlayerState {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-	write!(f, "{}:\n", self.player_name);
-	write!(f, "\tLives: {}\n", self.num_lives);
-	write!(f, "\tCoins: {}\n", self.num_coins);
-	if self.lost_lives.len() > 0 {
-	    write!(f, "\tLost Identities: ");
+	writeln!(f, "{}:", self.player_name)?;
+	writeln!(f, "\tLives: {}", self.num_lives)?;
+	writeln!(f, "\tCoins: {}", self.num_coins)?;
+	if !self.lost_lives.is_empty() {
+	    write!(f, "\tLost Identities: ")?;
 	    for life in &self.lost_lives {
-		write!(f, "{:?} ", life);
+		write!(f, "{:?} ", life)?;
 	    }
-	    write!(f, "\n");
+	    writeln!(f)?;
 	}
 	Ok(())
     }
@@ -538,11 +676,11 @@ impl fmt::Display for PlayerState {
 
 
 impl PlayerState {
-    pub fn new(player_name: String, num_lives: u8) -> Self {
+    pub fn new(player_name: String, num_lives: u8, num_coins: u8) -> Self {
         let lost_lives = Vec::new();
         Self {
             player_name,
-            num_coins: STARTING_COINS,
+            num_coins,
             num_lives,
             lost_lives,
         }
@@ -558,7 +696,7 @@ impl PlayerState {
 
 // Can be used for cards as well?
 arg_enum! {
-#[derive(Debug,  EnumSetType)]
+#[derive(Debug,  EnumSetType, Serialize, Deserialize)]
 pub enum Identity {
     Ambassador,
     Assassin,
@@ -578,14 +716,14 @@ impl GameField {
         let mut deck = Vec::new();
         for identity in identities {
             for _ in 0..num_cards {
-                deck.push(identity.clone())
+                deck.push(identity)
             }
         }
         Self { deck }
     }
 }
 
-use log::{Level, LevelFilter, Metadata, Record, SetLoggerError};
+use log::{Level, LevelFilter, Metadata, Record};
 
 struct SimpleLogger;
 
@@ -605,12 +743,6 @@ impl log::Log for SimpleLogger {
 
 static LOGGER: SimpleLogger = SimpleLogger;
 
-// TODO Max cards needs to sit with this
-const STARTING_CARDS: u8 = 2;
-const STARTING_COINS: u8 = 2;
-const STARTING_LIVES: u8 = 2;
-const REQUIRE_COUP_COINS: u8 = 10;
-
 #[derive(StructOpt, Debug)]
 #[structopt(name = "Coup Simulator CLI", setting = structopt::clap::AppSettings::ColoredHelp)]
 struct GameConfig {
@@ -626,43 +758,184 @@ struct GameConfig {
     /// The number of lives each player begins the game with
     #[structopt(long, default_value = "2")]
     starting_lives: u8,
-    /// The limit on the amount of coins each player starts with
+    /// Players with this many coins must coup
     #[structopt(long, default_value = "10")]
     required_coup_coins: u8,
+    /// The cost of a coup
+    #[structopt(long, default_value = "7")]
+    coup_cost: u8,
+    /// The cost of an assassination
+    #[structopt(long, default_value = "3")]
+    assassinate_cost: u8,
+    /// The most coins a steal can take
+    #[structopt(long, default_value = "2")]
+    steal_amount: u8,
+    /// Copies of each identity in the deck. Defaults to 3, or 4 for games of five or more
+    #[structopt(long)]
+    cards_per_identity: Option<u8>,
     /// The number of local players in this simulation
     #[structopt(long, default_value = "1")]
     num_local_players: u8,
+    /// Play local players on a full-screen terminal UI instead of typed menus
+    #[structopt(long)]
+    tui: bool,
+    /// Local players take turns at the terminal: it's cleared and handed to each player before
+    /// their decisions, so nobody sees anyone else's hand or questions
+    #[structopt(long, conflicts_with = "tui")]
+    hot_seat: bool,
     /// The names of the Random CPUS in this simulation
     #[structopt(long, value_delimiter = ",", default_value = "Porter,Miela")]
     random_cpus: Vec<String>,
+    /// How the Random CPUS weigh their choices, e.g. "tax=3,challenge=1,no_challenge=9".
+    /// Choices not listed have a weight of 1
+    #[structopt(long, default_value = "")]
+    random_weights: RandomWeights,
     /// The names of the Dumb CPUS in this simulation
     #[structopt(long, value_delimiter = ",", default_value = "Don")]
-    dumb_cpus: Vec<String>
-    
+    dumb_cpus: Vec<String>,
+    /// External bots in this simulation, as NAME=COMMAND. Can be given more than once
+    #[structopt(long = "external-cpu", number_of_values = 1)]
+    external_cpus: Vec<String>,
+    /// How long cpu players get for each decision, in milliseconds. Unlimited if not given
+    #[structopt(long)]
+    decision_timeout_ms: Option<u64>,
+    /// What happens to a cpu player that runs out of time
+    #[structopt(long, possible_values = &TimeoutPolicy::variants(), case_insensitive = true, default_value = "SafeDefault")]
+    timeout_policy: TimeoutPolicy,
+    /// Have the cpu players talk at the table: trash talk, or their reasoning out loud
+    #[structopt(long, possible_values = &Talk::variants(), case_insensitive = true)]
+    bot_talk: Option<Talk>,
+    #[structopt(flatten)]
+    spectate: SpectateConfig,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
 
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Plays many bot-only games without any output, then prints how each bot did
+    Simulate(SimulateConfig),
+    /// Ranks bots against each other over many games, and prints a leaderboard
+    Tournament(TournamentConfig),
+    /// Tests whether bot A is stronger than bot B, stopping as soon as the answer is clear
+    Compare(CompareConfig),
+    /// Plays a grid of rule changes and prints how each one shifts the balance of the game
+    Sweep(SweepConfig),
+    /// Times stepping the compact game state against the structures a game runs on
+    Bench(BenchConfig),
+    /// Plays random games under random rules, checking the engine's invariants as they go
+    Fuzz(FuzzConfig),
+    /// Hosts a game for players joining from other machines, filling the other seats with bots
+    Serve(ServeConfig),
+    /// Joins a game hosted with serve, or a table at a lobby
+    Join(JoinConfig),
+    /// Hosts many tables at once, each set up and joined from a lobby
+    Lobby(LobbyConfig),
+    /// Serves an HTTP JSON API for creating games and making their decisions
+    Api(ApiConfig),
+    /// Follows a game as a spectator, where it lets spectators in with --spectators
+    Watch(WatchConfig),
+}
+
+impl GameConfig {
+    fn rules(&self) -> Rules {
+        Rules {
+            identities: self.game_identities.iter().cloned().collect(),
+            cards_per_identity: self.cards_per_identity,
+            starting_cards: self.starting_cards,
+            starting_lives: self.starting_lives,
+            starting_coins: self.starting_coins,
+            required_coup_coins: self.required_coup_coins,
+            coup_cost: self.coup_cost,
+            assassinate_cost: self.assassinate_cost,
+            steal_amount: self.steal_amount,
+            ..Rules::default()
+        }
+    }
+}
+
+fn parse_external_cpu(spec: &str) -> Result<(String, String)> {
+    let mut parts = spec.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+        (Some(name), Some(command)) if !name.is_empty() && !command.is_empty() => {
+            Ok((name.to_string(), command.to_string()))
+        }
+        _ => Err(anyhow!("Expected NAME=COMMAND for an external cpu, got '{}'", spec)),
+    }
 }
 
 fn main() -> Result<()> {
-    log::set_logger(&LOGGER).map(|()| log::set_max_level(LevelFilter::Info));
+    log::set_logger(&LOGGER).map_err(|e| anyhow!("Unable to set logger: {:?}", e))?;
+    log::set_max_level(LevelFilter::Info);
 
     let config = GameConfig::from_args();
     
-    let game_identities : EnumSet<Identity> = config.game_identities.into_iter().collect();
+    let rules = config.rules();
+    match &config.command {
+        Some(Command::Simulate(simulate_config)) => {
+            return simulate::run(simulate_config, rules, &config.random_weights)
+        }
+        Some(Command::Tournament(tournament_config)) => return tournament::run(tournament_config, rules),
+        Some(Command::Compare(compare_config)) => return compare::run(compare_config, rules),
+        Some(Command::Sweep(sweep_config)) => return sweep::run(sweep_config, rules, &config.random_weights),
+        Some(Command::Bench(bench_config)) => return compact::bench::run(bench_config, rules),
+        Some(Command::Fuzz(fuzz_config)) => return fuzz::run(fuzz_config),
+        Some(Command::Serve(serve_config)) => return net::serve(serve_config, rules),
+        Some(Command::Join(join_config)) => return net::join(join_config),
+        Some(Command::Lobby(lobby_config)) => return net::lobby::run(lobby_config),
+        Some(Command::Api(api_config)) => return api::run(api_config),
+        Some(Command::Watch(watch_config)) => return spectate::watch(watch_config),
+        None => {}
+    }
+
     let mut players = Vec::new();
     for cpu in &config.dumb_cpus {
 	players.push(PlayerConfig::new(PlayerType::DumbCPU, cpu.clone()));
     }
 
     for cpu in &config.random_cpus {
-	players.push(PlayerConfig::new(PlayerType::RandomCPU, cpu.clone()));
+	players.push(PlayerConfig::new(PlayerType::RandomCPU(config.random_weights.clone()), cpu.clone()));
     }
 
-    for player in 0..config.num_local_players {
+    for cpu in &config.external_cpus {
+	let (name, command) = parse_external_cpu(cpu)?;
+	players.push(PlayerConfig::new(PlayerType::ExternalCPU(command), name));
+    }
+
+    if config.spectate.watching() && config.num_local_players > 0 {
+        return Err(anyhow!("Watching shows the game to everyone at this terminal, so it needs --num-local-players 0"));
+    }
+    let (local_type, logger_type) = if config.tui {
+        (PlayerType::Tui, LoggerType::Quiet)
+    } else if config.spectate.watching() {
+        (PlayerType::Local, LoggerType::Quiet)
+    } else if config.hot_seat {
+        (PlayerType::HotSeat, LoggerType::Local)
+    } else {
+        (PlayerType::Local, LoggerType::Local)
+    };
+    for _ in 0..config.num_local_players {
 	// TODO make name optional / not needed for local player config
-	players.push(PlayerConfig::new(PlayerType::Local, "".to_string()));
+	players.push(PlayerConfig::new(local_type.clone(), "".to_string()));
+    }
+    let mut game = Game::new(rules, players, logger_type, rand::random())?;
+    if let Some(talk) = config.bot_talk {
+        game.set_bot_talk(talk);
+    }
+    if let Some(timeout) = config.decision_timeout_ms {
+	game.set_decision_clock(Duration::from_millis(timeout), config.timeout_policy);
+    }
+    let spectators = config.spectate.open(&mut game)?;
+    let result = game.play();
+    if let Some(spectators) = spectators {
+        // Held back spectators still have the end of the game to see
+        let _ = spectators.join();
+    }
+    if config.tui {
+        // The terminal UI is gone by now, so say how it ended on the way out
+        drop(game.driver);
+        println!("{}", Event::GameOver { winner: result.winner }.describe(&game.state));
     }
-    let mut game = Game::new(game_identities, players, LoggerType::Local);
-    game.play();
     // game.setup();
     // human_player.choose_action(&game.state);
     Ok(())
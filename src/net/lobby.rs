@@ -0,0 +1,869 @@
+//! Many tables on one server.
+//!
+//! `coup lobby` takes connections the way `serve` does, but starts each one off in a lobby,
+//! where it can list the tables, set one up, sit down at one, change its rules and say it's
+//! ready. A table's game starts once it has all the players it was set up for and they're all
+//! ready, with bots in the remaining seats. Every table plays its own game on its own thread,
+//! and once it's over its players are back in the lobby and the table shows who won.
+//!
+//! Lobby requests and replies are JSON lines like the game's:
+//!
+//! ```text
+//! {"type":"list_tables"}
+//! {"type":"create_table","seats":4,"players":2,"bot":"honest","rules":{"starting_coins":3}}
+//! {"type":"join_table","table":1,"name":"Ana"}
+//! {"type":"set_rules","rules":{"identities":["Duke","Captain","Contessa"]}}
+//! {"type":"ready","ready":true}
+//! {"type":"leave_table"}
+//! {"type":"rejoin","token":"..."}
+//! ```
+//!
+//! The server greets every connection with `lobby`, answers `list_tables` with `tables`, tells
+//! everyone at a table about every change to it with `table`, and says what went wrong with
+//! `error`. When a table starts, its players are sent `hello` and the game goes on as with
+//! `serve`, session tokens included: a player whose connection drops can `rejoin` from a new
+//! one.
+
+use crate::player::link::PROTOCOL_VERSION;
+use crate::net::bot_player;
+use crate::player::remote_player::{self, RemotePlayer, Session};
+use crate::rules::Rules;
+use crate::tournament::BotEntry;
+use crate::{Game, GameResult, LoggerType, PlayerConfig, PlayerID};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+use structopt::StructOpt;
+
+// How long the lobby waits on a connection that isn't reading what it's sent. Every connection
+// is written to from the one lobby thread, so one that stops reading mustn't hold up the rest.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(StructOpt, Debug)]
+pub struct LobbyConfig {
+    /// The address to listen on
+    #[structopt(long, default_value = "0.0.0.0:4040")]
+    bind: String,
+    /// Seed for every table's deal and bots, mixed with the table's number. Random if not given
+    #[structopt(long)]
+    seed: Option<u64>,
+    /// Seconds a player's decisions wait for them to rejoin after their connection drops
+    #[structopt(long, default_value = "60")]
+    grace: u64,
+    /// The bot making a dropped player's decisions once the grace period is up, as TYPE or
+    /// "random WEIGHTS"
+    #[structopt(long, default_value = "honest")]
+    stand_in: BotEntry,
+    /// Seconds a player gets to answer before they're taken to have dropped, so a client that
+    /// goes quiet or a connection that dies without closing doesn't hold up the table
+    #[structopt(long, default_value = "300")]
+    answer_timeout: u64,
+}
+
+/// What a client can ask of the lobby
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LobbyRequest {
+    ListTables,
+    CreateTable {
+        seats: usize,
+        /// Players the table waits for. Bots take the other seats
+        players: usize,
+        /// The bot in the other seats, as TYPE or "random WEIGHTS". Random if not given
+        #[serde(default)]
+        bot: Option<String>,
+        /// Changes to the default rules
+        #[serde(default)]
+        rules: Map<String, Value>,
+    },
+    JoinTable {
+        table: u64,
+        name: String,
+    },
+    LeaveTable,
+    SetRules {
+        rules: Map<String, Value>,
+    },
+    Ready {
+        ready: bool,
+    },
+    /// Gets back to a seat in a game that's being played, after a dropped connection
+    Rejoin {
+        token: String,
+    },
+}
+
+/// What the lobby sends
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LobbyMessage {
+    Lobby {
+        protocol_version: u32,
+        tables: Vec<TableInfo>,
+    },
+    Tables {
+        tables: Vec<TableInfo>,
+    },
+    Table {
+        table: TableInfo,
+    },
+    Error {
+        message: String,
+    },
+}
+
+impl LobbyMessage {
+    /// The types of message only a lobby sends
+    pub const TYPES: [&'static str; 4] = ["lobby", "tables", "table", "error"];
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableInfo {
+    pub id: u64,
+    pub seats: usize,
+    pub players: usize,
+    pub bot: String,
+    pub rules: Rules,
+    /// The players sitting at the table, in seat order
+    pub seated: Vec<SeatInfo>,
+    pub status: TableStatus,
+    /// Once the game is over
+    pub winner: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeatInfo {
+    pub name: String,
+    pub ready: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TableStatus {
+    Open,
+    Playing,
+    Over,
+}
+
+impl fmt::Display for TableInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<String> = self
+            .seated
+            .iter()
+            .map(|seat| match (self.status, seat.ready) {
+                (TableStatus::Open, true) => format!("{} (ready)", seat.name),
+                _ => seat.name.clone(),
+            })
+            .collect();
+        write!(f, "Table {}: ", self.id)?;
+        match (self.status, &self.winner) {
+            (TableStatus::Open, _) => write!(
+                f,
+                "{} of {} players [{}], {} seats with {} bots. Rules: {}",
+                self.seated.len(),
+                self.players,
+                names.join(", "),
+                self.seats,
+                self.bot,
+                self.rules
+            ),
+            (TableStatus::Playing, _) => write!(f, "playing [{}]", names.join(", ")),
+            (TableStatus::Over, Some(winner)) => write!(f, "won by {}", winner),
+            (TableStatus::Over, None) => write!(f, "ended without a winner"),
+        }
+    }
+}
+
+/// Opens the lobby, and keeps it open
+pub fn run(config: &LobbyConfig) -> Result<()> {
+    // Found out now rather than when the first table starts
+    bot_player(&config.stand_in.player_type, PlayerID(0), 0)?;
+    let listener = TcpListener::bind(&config.bind)?;
+    println!("Lobby open on {}", listener.local_addr()?);
+    host(listener, config);
+    Ok(())
+}
+
+enum Incoming {
+    Connected(u64, TcpStream),
+    Line(u64, String),
+    Disconnected(u64),
+    Finished(u64, Result<GameResult>),
+}
+
+fn host(listener: TcpListener, config: &LobbyConfig) {
+    let (events, incoming) = mpsc::channel();
+    let accepting = events.clone();
+    thread::spawn(move || accept(listener, accepting));
+    let lobby = Lobby {
+        seed: config.seed,
+        grace: Duration::from_secs(config.grace),
+        stand_in: config.stand_in.clone(),
+        answer_timeout: Duration::from_secs(config.answer_timeout),
+        sessions: HashMap::new(),
+        events,
+        connections: HashMap::new(),
+        tables: BTreeMap::new(),
+        next_table: 0,
+    };
+    lobby.run(incoming);
+}
+
+// Reads every connection on a thread of its own, and passes what it says on to the lobby
+fn accept(listener: TcpListener, events: Sender<Incoming>) {
+    for (conn, stream) in (0..).zip(listener.incoming()) {
+        let (stream, reader) = match stream.and_then(|stream| Ok((stream.try_clone()?, stream))) {
+            Ok(streams) => streams,
+            Err(e) => {
+                log::warn!("Unable to accept a connection: {}", e);
+                continue;
+            }
+        };
+        if events.send(Incoming::Connected(conn, stream)).is_err() {
+            return;
+        }
+        let events = events.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(reader).lines() {
+                match line {
+                    Ok(line) => {
+                        if events.send(Incoming::Line(conn, line)).is_err() {
+                            return;
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+            let _ = events.send(Incoming::Disconnected(conn));
+        });
+    }
+}
+
+struct Lobby {
+    seed: Option<u64>,
+    grace: Duration,
+    stand_in: BotEntry,
+    answer_timeout: Duration,
+    // The table and seat of every session in a game being played, and where to hand the
+    // session's new connections
+    sessions: HashMap<String, (u64, usize, Sender<remote_player::Connection>)>,
+    // Handed to games, so they can say when they're over
+    events: Sender<Incoming>,
+    connections: HashMap<u64, Connection>,
+    tables: BTreeMap<u64, Table>,
+    next_table: u64,
+}
+
+struct Connection {
+    writer: TcpStream,
+    table: Option<u64>,
+    // Where the connection's lines go while it's in a game
+    game: Option<Sender<String>>,
+}
+
+struct Table {
+    info: TableInfo,
+    bot: BotEntry,
+    // The connection in each player's seat
+    sitters: Vec<u64>,
+    // Everyone's name once the game has started, bots included
+    names: Vec<String>,
+}
+
+impl Lobby {
+    fn run(mut self, incoming: Receiver<Incoming>) {
+        for event in incoming {
+            match event {
+                Incoming::Connected(conn, writer) => {
+                    let _ = writer.set_nodelay(true);
+                    if let Err(e) = writer.set_write_timeout(Some(WRITE_TIMEOUT)) {
+                        log::warn!("Unable to set up connection {}: {}", conn, e);
+                        let _ = writer.shutdown(Shutdown::Both);
+                        continue;
+                    }
+                    let connection = Connection {
+                        writer,
+                        table: None,
+                        game: None,
+                    };
+                    self.connections.insert(conn, connection);
+                    let greeting = LobbyMessage::Lobby {
+                        protocol_version: PROTOCOL_VERSION,
+                        tables: self.table_list(),
+                    };
+                    self.send(conn, &greeting);
+                }
+                Incoming::Line(conn, line) => self.receive(conn, line),
+                Incoming::Disconnected(conn) => self.disconnect(conn),
+                Incoming::Finished(table, result) => self.finish(table, result),
+            }
+        }
+    }
+
+    fn receive(&mut self, conn: u64, line: String) {
+        let connection = match self.connections.get(&conn) {
+            Some(connection) => connection,
+            None => return,
+        };
+        if let Some(game) = &connection.game {
+            let _ = game.send(line);
+            return;
+        }
+        let handled = match serde_json::from_str(&line) {
+            Ok(request) => self.handle(conn, request),
+            Err(e) => Err(anyhow!("Unable to understand '{}': {}", line.trim(), e)),
+        };
+        if let Err(e) = handled {
+            self.send(conn, &LobbyMessage::Error { message: e.to_string() });
+        }
+    }
+
+    fn handle(&mut self, conn: u64, request: LobbyRequest) -> Result<()> {
+        match request {
+            LobbyRequest::ListTables => {
+                let tables = self.table_list();
+                self.send(conn, &LobbyMessage::Tables { tables });
+            }
+            LobbyRequest::CreateTable {
+                seats,
+                players,
+                bot,
+                rules,
+            } => {
+                let rules = Rules::default().with_changes(&rules)?;
+                rules.check(seats)?;
+                if players == 0 || players > seats {
+                    return Err(anyhow!("A table of {} can seat 1 to {} players, not {}", seats, seats, players));
+                }
+                let bot: BotEntry = bot.as_deref().unwrap_or("random").parse()?;
+                self.next_table += 1;
+                let info = TableInfo {
+                    id: self.next_table,
+                    seats,
+                    players,
+                    bot: bot.name.clone(),
+                    rules,
+                    seated: Vec::new(),
+                    status: TableStatus::Open,
+                    winner: None,
+                };
+                self.send(conn, &LobbyMessage::Table { table: info.clone() });
+                let table = Table {
+                    info,
+                    bot,
+                    sitters: Vec::new(),
+                    names: Vec::new(),
+                };
+                self.tables.insert(self.next_table, table);
+            }
+            LobbyRequest::JoinTable { table: id, name } => {
+                if let Some(current) = self.connections[&conn].table {
+                    return Err(anyhow!("You're already at table {}", current));
+                }
+                let table = self
+                    .tables
+                    .get_mut(&id)
+                    .ok_or_else(|| anyhow!("There is no table {}", id))?;
+                if table.info.status != TableStatus::Open {
+                    return Err(anyhow!("Table {} has already started", id));
+                }
+                if table.sitters.len() >= table.info.players {
+                    return Err(anyhow!("Table {} is full", id));
+                }
+                table.sitters.push(conn);
+                table.info.seated.push(SeatInfo { name, ready: false });
+                self.connections.get_mut(&conn).unwrap().table = Some(id);
+                self.broadcast(id);
+            }
+            LobbyRequest::LeaveTable => {
+                let id = self.open_table(conn)?;
+                let table = self.tables.get_mut(&id).unwrap();
+                let seat = table.sitters.iter().position(|sitter| *sitter == conn).unwrap();
+                table.sitters.remove(seat);
+                table.info.seated.remove(seat);
+                let info = table.info.clone();
+                self.connections.get_mut(&conn).unwrap().table = None;
+                self.send(conn, &LobbyMessage::Table { table: info });
+                self.broadcast(id);
+            }
+            LobbyRequest::SetRules { rules } => {
+                let id = self.open_table(conn)?;
+                let table = self.tables.get_mut(&id).unwrap();
+                let rules = table.info.rules.with_changes(&rules)?;
+                rules.check(table.info.seats)?;
+                table.info.rules = rules;
+                // Everyone has to agree to the new rules
+                for seat in &mut table.info.seated {
+                    seat.ready = false;
+                }
+                self.broadcast(id);
+            }
+            LobbyRequest::Ready { ready } => {
+                let id = self.open_table(conn)?;
+                let table = self.tables.get_mut(&id).unwrap();
+                let seat = table.sitters.iter().position(|sitter| *sitter == conn).unwrap();
+                table.info.seated[seat].ready = ready;
+                let full = table.sitters.len() == table.info.players;
+                if full && table.info.seated.iter().all(|seat| seat.ready) {
+                    self.start(id);
+                } else {
+                    self.broadcast(id);
+                }
+            }
+            LobbyRequest::Rejoin { token } => {
+                if let Some(current) = self.connections[&conn].table {
+                    return Err(anyhow!("You're already at table {}", current));
+                }
+                let (id, seat, handover) = self
+                    .sessions
+                    .get(&token)
+                    .cloned()
+                    .ok_or_else(|| anyhow!("No seat has that session token"))?;
+                let connection = self.connections.get_mut(&conn).unwrap();
+                let writer = connection.writer.try_clone()?;
+                writer.set_write_timeout(Some(self.answer_timeout))?;
+                connection.table = Some(id);
+                let table = self.tables.get_mut(&id).unwrap();
+                table.sitters[seat] = conn;
+                let info = table.info.clone();
+                // Told before the game starts writing to the connection
+                self.send(conn, &LobbyMessage::Table { table: info });
+                let (lines, inbox) = mpsc::channel();
+                handover
+                    .send((Box::new(Inbox::new(inbox, self.answer_timeout)), Box::new(writer)))
+                    .map_err(|_| anyhow!("Table {} is over", id))?;
+                self.connections.get_mut(&conn).unwrap().game = Some(lines);
+            }
+        }
+        Ok(())
+    }
+
+    // The table the connection is sitting at, as long as its game hasn't started
+    fn open_table(&self, conn: u64) -> Result<u64> {
+        let id = self.connections[&conn]
+            .table
+            .ok_or_else(|| anyhow!("You're not at a table"))?;
+        match self.tables[&id].info.status {
+            TableStatus::Open => Ok(id),
+            _ => Err(anyhow!("Table {} has already started", id)),
+        }
+    }
+
+    fn start(&mut self, id: u64) {
+        let table = self.tables.get_mut(&id).unwrap();
+        table.info.status = TableStatus::Playing;
+        table.names = (0..table.info.seats)
+            .map(|seat| match table.info.seated.get(seat) {
+                Some(info) => info.name.clone(),
+                None => format!("{} {}", table.bot.name, seat + 1),
+            })
+            .collect();
+        let players: Vec<PlayerConfig> = table
+            .names
+            .iter()
+            .map(|name| PlayerConfig::new(table.bot.player_type.clone(), name.clone()))
+            .collect();
+        let seed = self.seed.map(|seed| seed ^ id).unwrap_or_else(rand::random);
+        let mut seats = Vec::new();
+        for (seat, conn) in table.sitters.iter().enumerate() {
+            let player = PlayerID(seat as u8);
+            let stand_in = bot_player(&self.stand_in.player_type, player.clone(), seed ^ seat as u64);
+            let session = match stand_in {
+                Ok(stand_in) => {
+                    let (session, handover) = Session::new(self.grace, stand_in);
+                    self.sessions.insert(session.token().to_string(), (id, seat, handover));
+                    Ok(session)
+                }
+                Err(e) => Err(e),
+            };
+            let connection = self.connections.get_mut(conn).unwrap();
+            let (lines, inbox) = mpsc::channel();
+            connection.game = Some(lines);
+            let timeout = self.answer_timeout;
+            let writer = connection
+                .writer
+                .try_clone()
+                .and_then(|writer| writer.set_write_timeout(Some(timeout)).map(|_| writer));
+            seats.push((player, table.names[seat].clone(), Inbox::new(inbox, timeout), writer, session));
+        }
+        let rules = table.info.rules;
+        // Told before the game starts writing to the same connections
+        self.broadcast(id);
+        let events = self.events.clone();
+        thread::spawn(move || report(id, &events, || play_table(rules, players, seats, seed)));
+    }
+
+    fn finish(&mut self, id: u64, result: Result<GameResult>) {
+        self.sessions.retain(|_, (table, _, _)| *table != id);
+        let table = self.tables.get_mut(&id).unwrap();
+        table.info.status = TableStatus::Over;
+        match result {
+            Ok(result) => table.info.winner = result.winner.map(|winner| table.names[winner.0 as usize].clone()),
+            Err(e) => log::warn!("Table {} couldn't be played: {}", id, e),
+        }
+        println!("{}", table.info);
+        // Everyone still connected is back in the lobby, where writes give up sooner than in a game
+        for conn in &table.sitters {
+            if let Some(connection) = self.connections.get_mut(conn) {
+                connection.table = None;
+                connection.game = None;
+                let _ = connection.writer.set_write_timeout(Some(WRITE_TIMEOUT));
+            }
+        }
+        self.broadcast(id);
+    }
+
+    fn disconnect(&mut self, conn: u64) {
+        // Anyone in a game gets safe choices from here on, once their lines stop coming
+        let connection = match self.connections.remove(&conn) {
+            Some(connection) => connection,
+            None => return,
+        };
+        if let Some(id) = connection.table {
+            let table = self.tables.get_mut(&id).unwrap();
+            if table.info.status == TableStatus::Open {
+                let seat = table.sitters.iter().position(|sitter| *sitter == conn).unwrap();
+                table.sitters.remove(seat);
+                table.info.seated.remove(seat);
+                self.broadcast(id);
+            }
+        }
+    }
+
+    fn table_list(&self) -> Vec<TableInfo> {
+        self.tables.values().map(|table| table.info.clone()).collect()
+    }
+
+    // Tells everyone at the table how it stands
+    fn broadcast(&mut self, id: u64) {
+        let table = &self.tables[&id];
+        let message = LobbyMessage::Table {
+            table: table.info.clone(),
+        };
+        for conn in table.sitters.clone() {
+            self.send(conn, &message);
+        }
+    }
+
+    // Drops any connection that can't be written to, or has stopped reading
+    fn send(&mut self, conn: u64, message: &LobbyMessage) {
+        let connection = match self.connections.get_mut(&conn) {
+            Some(connection) => connection,
+            None => return,
+        };
+        let line = serde_json::to_string(message).unwrap();
+        if let Err(e) = writeln!(connection.writer, "{}", line) {
+            log::warn!("Dropping connection {}, unable to write to it: {}", conn, e);
+            let _ = connection.writer.shutdown(Shutdown::Both);
+            self.disconnect(conn);
+        }
+    }
+}
+
+// Plays a table's game and tells the lobby when it's over. A game that panics is reported as
+// failed, so its table doesn't stay at Playing for good.
+fn report(id: u64, events: &Sender<Incoming>, play: impl FnOnce() -> Result<GameResult>) {
+    let result = panic::catch_unwind(AssertUnwindSafe(play)).unwrap_or_else(|_| Err(anyhow!("The game crashed")));
+    let _ = events.send(Incoming::Finished(id, result));
+}
+
+type SeatLink = (PlayerID, String, Inbox, io::Result<TcpStream>, Result<Session>);
+
+fn play_table(rules: Rules, players: Vec<PlayerConfig>, seats: Vec<SeatLink>, seed: u64) -> Result<GameResult> {
+    let mut game = Game::new(rules, players, LoggerType::Quiet, seed)?;
+    for (id, name, inbox, writer, session) in seats {
+        let connection: remote_player::Connection = (Box::new(inbox), Box::new(writer?));
+        let (player, name) = RemotePlayer::with_session(id.clone(), &name, connection, session?)?;
+        game.take_seat(&id, name, Box::new(player));
+    }
+    Ok(game.play())
+}
+
+/// The lines a connection sends while it's in a game, passed on by the lobby. Waiting longer
+/// than the timeout for one is an error, as a read from the connection itself would be.
+struct Inbox {
+    lines: Receiver<String>,
+    timeout: Duration,
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl Inbox {
+    fn new(lines: Receiver<String>, timeout: Duration) -> Self {
+        Inbox {
+            lines,
+            timeout,
+            buffer: Vec::new(),
+            position: 0,
+        }
+    }
+}
+
+impl Read for Inbox {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let read = available.len().min(buf.len());
+        buf[..read].copy_from_slice(&available[..read]);
+        self.consume(read);
+        Ok(read)
+    }
+}
+
+impl BufRead for Inbox {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.position >= self.buffer.len() {
+            match self.lines.recv_timeout(self.timeout) {
+                Ok(line) => {
+                    self.buffer = line.into_bytes();
+                    self.buffer.push(b'\n');
+                    self.position = 0;
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, "No answer in time"))
+                }
+                // The connection is gone
+                Err(RecvTimeoutError::Disconnected) => return Ok(&[]),
+            }
+        }
+        Ok(&self.buffer[self.position..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.position += amount;
+    }
+}
+
+/// What a client wants from a lobby
+#[derive(Debug, Clone)]
+pub enum Plan {
+    Join(u64),
+    /// Get back to a seat with its session token
+    Rejoin(String),
+    Create {
+        seats: usize,
+        players: usize,
+        bot: Option<String>,
+        rules: Map<String, Value>,
+    },
+}
+
+/// A client's way through the lobby to a game: setting up or finding its table, sitting down
+/// and saying it's ready
+pub struct Visit {
+    plan: Plan,
+    name: String,
+    echo: bool,
+    table: Option<u64>,
+    ready: bool,
+}
+
+/// What a client does about a lobby message
+pub enum Next {
+    Send(LobbyRequest),
+    Wait,
+    // The client's game is over
+    Leave,
+}
+
+impl Visit {
+    pub fn new(plan: Plan, name: &str, echo: bool) -> Self {
+        Visit {
+            plan,
+            name: name.to_string(),
+            echo,
+            table: None,
+            ready: false,
+        }
+    }
+
+    /// The session token to rejoin with, sent before anything else
+    pub fn rejoining(&self) -> Option<&str> {
+        match &self.plan {
+            Plan::Rejoin(token) => Some(token),
+            _ => None,
+        }
+    }
+
+    pub fn answer(&mut self, message: LobbyMessage) -> Result<Next> {
+        let name = self.name.clone();
+        let join = |table| LobbyRequest::JoinTable { table, name };
+        match message {
+            LobbyMessage::Lobby { .. } => match &self.plan {
+                Plan::Join(table) => {
+                    self.table = Some(*table);
+                    Ok(Next::Send(join(*table)))
+                }
+                // Already asked for
+                Plan::Rejoin(_) => Ok(Next::Wait),
+                Plan::Create {
+                    seats,
+                    players,
+                    bot,
+                    rules,
+                } => Ok(Next::Send(LobbyRequest::CreateTable {
+                    seats: *seats,
+                    players: *players,
+                    bot: bot.clone(),
+                    rules: rules.clone(),
+                })),
+            },
+            LobbyMessage::Tables { .. } => Ok(Next::Wait),
+            // The table just set up, or the one rejoined
+            LobbyMessage::Table { table } if self.table.is_none() => {
+                self.table = Some(table.id);
+                if self.rejoining().is_some() {
+                    self.ready = true;
+                    return Ok(Next::Wait);
+                }
+                Ok(Next::Send(join(table.id)))
+            }
+            LobbyMessage::Table { table } if Some(table.id) == self.table => {
+                if self.echo && table.status != TableStatus::Over {
+                    println!("{}", table);
+                }
+                match table.status {
+                    TableStatus::Open if !self.ready => {
+                        self.ready = true;
+                        Ok(Next::Send(LobbyRequest::Ready { ready: true }))
+                    }
+                    TableStatus::Open | TableStatus::Playing => Ok(Next::Wait),
+                    TableStatus::Over => Ok(Next::Leave),
+                }
+            }
+            LobbyMessage::Table { .. } => Ok(Next::Wait),
+            LobbyMessage::Error { message } => Err(anyhow!(message)),
+        }
+    }
+}
+
+/// The tables at a lobby
+pub fn list_tables(address: &str) -> Result<Vec<TableInfo>> {
+    let stream = TcpStream::connect(address)?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    match serde_json::from_str(&line) {
+        Ok(LobbyMessage::Lobby { tables, .. }) => Ok(tables),
+        _ => Err(anyhow!("{} isn't a lobby", address)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::Event;
+    use crate::net::{bot_player, play_seat};
+    use crate::player::random_player::RandomWeights;
+    use crate::PlayerType;
+
+    #[test]
+    fn tables_play_side_by_side() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let config = LobbyConfig {
+            bind: address.clone(),
+            seed: Some(3),
+            grace: 60,
+            stand_in: "honest".parse().unwrap(),
+            answer_timeout: 300,
+        };
+        thread::spawn(move || host(listener, &config));
+
+        // Two tables of two players each, one with house rules
+        let mut rules = Map::new();
+        rules.insert("starting_coins".to_string(), Value::from(5));
+        let plans = vec![
+            Plan::Create {
+                seats: 3,
+                players: 2,
+                bot: Some("honest".to_string()),
+                rules,
+            },
+            Plan::Create {
+                seats: 2,
+                players: 2,
+                bot: None,
+                rules: Map::new(),
+            },
+        ];
+        let mut clients = Vec::new();
+        for (table, plan) in (1..).zip(plans) {
+            for (client, plan) in vec![plan, Plan::Join(table)].into_iter().enumerate() {
+                let address = address.clone();
+                clients.push(thread::spawn(move || {
+                    // Wait for the table to be set up before joining it
+                    if let Plan::Join(_) = plan {
+                        while list_tables(&address).unwrap().len() < table as usize {
+                            thread::yield_now();
+                        }
+                    }
+                    let stream = TcpStream::connect(&address).unwrap();
+                    let name = format!("Client {}.{}", table, client);
+                    let visit = Visit::new(plan, &name, false);
+                    let bot = PlayerType::RandomCPU(RandomWeights::default());
+                    play_seat(stream, &name, false, Some(visit), |id| bot_player(&bot, id, table)).unwrap()
+                }));
+            }
+        }
+        for client in clients {
+            let transcript = client.join().unwrap();
+            assert!(matches!(transcript.events.last(), Some(Event::GameOver { .. })));
+        }
+
+        let tables = list_tables(&address).unwrap();
+        assert_eq!(tables.len(), 2);
+        // Both tables are set up at once, so either could have been set up first
+        let house = tables.iter().find(|table| table.seats == 3).unwrap();
+        assert_eq!(house.rules.starting_coins, 5);
+        assert_eq!(house.bot, "honest");
+        for table in &tables {
+            assert_eq!(table.status, TableStatus::Over);
+            assert_eq!(table.seated.len(), 2);
+        }
+    }
+
+    #[test]
+    fn a_game_that_panics_still_hands_its_table_back() {
+        let (events, incoming) = mpsc::channel();
+        report(3, &events, || panic!("A bot fell over"));
+        match incoming.try_recv() {
+            Ok(Incoming::Finished(3, Err(_))) => {}
+            _ => panic!("The table was never finished"),
+        }
+    }
+
+    #[test]
+    fn connections_that_cant_be_written_to_are_dropped() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (writer, _) = listener.accept().unwrap();
+        let (events, _incoming) = mpsc::channel();
+        let mut lobby = Lobby {
+            seed: None,
+            grace: Duration::from_secs(1),
+            stand_in: "honest".parse().unwrap(),
+            answer_timeout: Duration::from_secs(1),
+            sessions: HashMap::new(),
+            events,
+            connections: HashMap::new(),
+            tables: BTreeMap::new(),
+            next_table: 0,
+        };
+        writer.shutdown(Shutdown::Write).unwrap();
+        let connection = Connection {
+            writer,
+            table: None,
+            game: None,
+        };
+        lobby.connections.insert(7, connection);
+        lobby.send(7, &LobbyMessage::Tables { tables: Vec::new() });
+        assert!(lobby.connections.is_empty());
+    }
+}
@@ -0,0 +1,710 @@
+//! Playing across machines.
+//!
+//! `coup serve` listens for players, seats each one that connects, fills the rest of the table
+//! with bots and then runs the game. Connected players are RemotePlayers to the game, so each
+//! is only sent its own cards and the events anyone at the table could see. `coup join
+//! HOST:PORT` sits down at such a table, and plays with the usual prompts, the terminal UI, or
+//! a bot. `coup serve --web` takes players from browsers instead (see `web`), and `coup lobby`
+//! hosts many tables at once (see `lobby`).
+//!
+//! Players are given a session token when they sit down. If their connection drops, `coup join
+//! HOST:PORT --rejoin TOKEN` gets them back to their seat, and brings them up to date. Browsers
+//! do the same by themselves. Their decisions wait for them for a while, and then a stand-in
+//! bot makes them until they're back.
+
+pub mod lobby;
+pub mod web;
+
+use crate::action::Action;
+use crate::event::Event;
+use crate::player::dumb_player::DumbPlayer;
+use crate::player::honest_player::HonestPlayer;
+use crate::player::human_player::HumanPlayer;
+use crate::player::link::{Message, Response, PROTOCOL_VERSION};
+use crate::player::random_player::RandomPlayer;
+use crate::player::remote_player::{Connection, RemotePlayer, Session};
+use crate::player::table_talk::{TableTalk, Talk};
+use crate::player::traits::Player;
+use crate::prompter::tui::TuiPrompter;
+use crate::prompter::{LocalPrompter, Prompter};
+use crate::rules::Rules;
+use crate::seat::{self, Choice};
+use crate::spectate::SpectateConfig;
+use crate::tournament::BotEntry;
+use crate::view::{PendingDecision, PlayerNames};
+use crate::{Game, GameResult, LoggerType, PlayerConfig, PlayerID, PlayerType};
+use anyhow::{anyhow, Result};
+use lobby::{LobbyMessage, Next, Plan, Visit};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct ServeConfig {
+    /// The address to listen on
+    #[structopt(long, default_value = "0.0.0.0:4040")]
+    bind: String,
+    /// Seats at the table
+    #[structopt(long, default_value = "4")]
+    seats: usize,
+    /// Seats for players joining over the network. The game starts once they are all taken
+    #[structopt(long, default_value = "1")]
+    clients: usize,
+    /// The bot playing the other seats, as TYPE or "random WEIGHTS"
+    #[structopt(long, default_value = "random")]
+    bot: BotEntry,
+    /// Seed for the deal and the bots. A random one is picked if not given
+    #[structopt(long)]
+    seed: Option<u64>,
+    /// Serve the browser client, and seat players from their browsers
+    #[structopt(long)]
+    web: bool,
+    /// Seconds a player's decisions wait for them to rejoin after their connection drops
+    #[structopt(long, default_value = "60")]
+    grace: u64,
+    /// The bot making a dropped player's decisions once the grace period is up, as TYPE or
+    /// "random WEIGHTS"
+    #[structopt(long, default_value = "honest")]
+    stand_in: BotEntry,
+    /// Seconds a player gets to answer before they're taken to have dropped, so a client that
+    /// goes quiet or a connection that dies without closing doesn't hold up the table
+    #[structopt(long, default_value = "300")]
+    answer_timeout: u64,
+    /// Have the bots talk at the table: trash talk, or their reasoning out loud
+    #[structopt(long, possible_values = &Talk::variants(), case_insensitive = true)]
+    bot_talk: Option<Talk>,
+    #[structopt(flatten)]
+    spectate: SpectateConfig,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct JoinConfig {
+    /// The server, as HOST:PORT
+    address: String,
+    /// Your name at the table. You're asked for one if not given
+    #[structopt(long)]
+    name: Option<String>,
+    /// Play on the full-screen terminal UI
+    #[structopt(long)]
+    tui: bool,
+    /// Have a bot play the seat instead, as TYPE or "random WEIGHTS"
+    #[structopt(long)]
+    bot: Option<BotEntry>,
+    /// Have the bot talk at the table: trash talk, or its reasoning out loud
+    #[structopt(long, requires = "bot", possible_values = &Talk::variants(), case_insensitive = true)]
+    bot_talk: Option<Talk>,
+    /// Get back to the seat given this session token, after a dropped connection
+    #[structopt(long)]
+    rejoin: Option<String>,
+    /// At a lobby, list its tables and exit
+    #[structopt(long)]
+    list: bool,
+    /// At a lobby, sit down at this table
+    #[structopt(long, conflicts_with = "new-table")]
+    table: Option<u64>,
+    /// At a lobby, set up a new table and sit down at it
+    #[structopt(long)]
+    new_table: bool,
+    /// Seats at the new table
+    #[structopt(long, default_value = "4")]
+    seats: usize,
+    /// Players the new table waits for. Bots take the other seats
+    #[structopt(long, default_value = "1")]
+    players: usize,
+    /// The bot in the new table's other seats, as TYPE or "random WEIGHTS"
+    #[structopt(long)]
+    table_bot: Option<String>,
+    /// A rule for the new table, as NAME=VALUE, e.g. starting_coins=3 or
+    /// identities=Duke,Captain,Contessa. Can be given more than once
+    #[structopt(long = "rule", number_of_values = 1)]
+    rules: Vec<String>,
+}
+
+impl JoinConfig {
+    // What to do at a lobby, if anything
+    fn plan(&self) -> Result<Option<Plan>> {
+        if let Some(token) = &self.rejoin {
+            return Ok(Some(Plan::Rejoin(token.clone())));
+        }
+        if let Some(table) = self.table {
+            return Ok(Some(Plan::Join(table)));
+        }
+        if !self.new_table {
+            return Ok(None);
+        }
+        let mut rules = Map::new();
+        for rule in &self.rules {
+            let (name, value) = parse_rule(rule)?;
+            rules.insert(name, value);
+        }
+        Ok(Some(Plan::Create {
+            seats: self.seats,
+            players: self.players,
+            bot: self.table_bot.clone(),
+            rules,
+        }))
+    }
+}
+
+// NAME=VALUE, with the value as JSON, or a comma separated list of names
+fn parse_rule(rule: &str) -> Result<(String, Value)> {
+    let (name, value) = rule
+        .split_once('=')
+        .ok_or_else(|| anyhow!("Rules are given as NAME=VALUE, not '{}'", rule))?;
+    let value = match serde_json::from_str(value) {
+        Ok(value) => value,
+        Err(_) if name == "identities" || value.contains(',') => {
+            Value::from(value.split(',').map(|item| item.trim().to_string()).collect::<Vec<_>>())
+        }
+        Err(_) => Value::from(value),
+    };
+    Ok((name.to_string(), value))
+}
+
+/// Waits for the players, then runs the game
+pub fn serve(config: &ServeConfig, rules: Rules) -> Result<()> {
+    let listener = TcpListener::bind(&config.bind)?;
+    // Watching on the terminal replaces the log
+    let logger_type = if config.spectate.watching() {
+        LoggerType::Quiet
+    } else {
+        LoggerType::Local
+    };
+    serve_on(listener, config, rules, logger_type)?;
+    Ok(())
+}
+
+// Hosts the game for the clients that connect to the listener, over WebSockets or plain TCP
+fn serve_on(listener: TcpListener, config: &ServeConfig, rules: Rules, logger_type: LoggerType) -> Result<GameResult> {
+    let address = listener.local_addr()?;
+    let rejoins = Rejoins::default();
+    let timeout = Duration::from_secs(config.answer_timeout);
+    if config.web {
+        println!("Waiting for {} player(s) at http://{}", config.clients, address);
+        let sockets = web::listen(listener, config.clients, rejoins.clone(), timeout);
+        host(config, rules, logger_type, |id, name| {
+            web::connect(&sockets, config, &rejoins, id, name)
+        })
+    } else {
+        println!("Waiting for {} player(s) on {}", config.clients, address);
+        let clients = listen(listener, config.clients, rejoins.clone(), timeout);
+        host(config, rules, logger_type, |id, name| {
+            accept(&clients, config, &rejoins, id, name)
+        })
+    }
+}
+
+/// Seats waiting for their players to get back, by session token
+type Rejoins = Arc<Mutex<HashMap<String, (PlayerID, Sender<Connection>)>>>;
+
+/// How long a connection gets to say which seat it's rejoining
+const REJOIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Accepts connections in the background, for as long as the program runs. The first `clients`
+// are handed over to be seated, and later ones can only rejoin a seat.
+fn listen(
+    listener: TcpListener,
+    clients: usize,
+    rejoins: Rejoins,
+    timeout: Duration,
+) -> Receiver<(TcpStream, SocketAddr)> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut accepted = 0;
+        for stream in listener.incoming() {
+            let (stream, address) = match stream.and_then(|stream| Ok((stream.peer_addr()?, stream))) {
+                Ok((address, stream)) => (stream, address),
+                Err(e) => {
+                    log::warn!("Unable to accept a connection: {}", e);
+                    continue;
+                }
+            };
+            if accepted < clients {
+                accepted += 1;
+                let _ = sender.send((stream, address));
+                continue;
+            }
+            let rejoins = rejoins.clone();
+            thread::spawn(move || match rejoin(stream, &rejoins, timeout) {
+                Ok(seat) => println!("Seat {} rejoined from {}", seat.0, address),
+                Err(e) => log::warn!("Turned away {}: {}", address, e),
+            });
+        }
+    });
+    receiver
+}
+
+// Seats the next client, with a session for getting back to the seat
+fn accept(
+    clients: &Receiver<(TcpStream, SocketAddr)>,
+    config: &ServeConfig,
+    rejoins: &Rejoins,
+    id: PlayerID,
+    name: &str,
+) -> Result<(RemotePlayer, String)> {
+    let (stream, address) = clients.recv()?;
+    let session = open_session(config, rejoins, &id)?;
+    let timeout = Duration::from_secs(config.answer_timeout);
+    let (player, name) = RemotePlayer::connect(id, name, stream, timeout, Some(session))?;
+    println!("{} joined from {}", name, address);
+    Ok((player, name))
+}
+
+// A session for the seat, which connections giving its token are handed to
+fn open_session(config: &ServeConfig, rejoins: &Rejoins, id: &PlayerID) -> Result<Session> {
+    let stand_in = bot_player(&config.stand_in.player_type, id.clone(), rand::random())?;
+    let (session, handover) = Session::new(Duration::from_secs(config.grace), stand_in);
+    rejoins.lock().unwrap().insert(session.token().to_string(), (id.clone(), handover));
+    Ok(session)
+}
+
+// Hands a connection over to the seat whose token it gives, with the same timeout as the
+// connection it replaces. Returns the seat.
+fn rejoin(stream: TcpStream, rejoins: &Rejoins, timeout: Duration) -> Result<PlayerID> {
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(REJOIN_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let token = match serde_json::from_str(&line) {
+        Ok(Response::Rejoin {
+            protocol_version,
+            token,
+        }) if protocol_version == PROTOCOL_VERSION => token,
+        Ok(Response::Rejoin { protocol_version, .. }) => {
+            return Err(anyhow!("Protocol version {} isn't supported", protocol_version))
+        }
+        _ => return Err(anyhow!("The table is full")),
+    };
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let (seat, handover) = rejoins
+        .lock()
+        .unwrap()
+        .get(&token)
+        .cloned()
+        .ok_or_else(|| anyhow!("No seat has that session token"))?;
+    handover
+        .send((Box::new(reader), Box::new(stream)))
+        .map_err(|_| anyhow!("The game is over"))?;
+    Ok(seat)
+}
+
+// Sets the table, seats the clients as they connect and plays the game
+fn host(
+    config: &ServeConfig,
+    rules: Rules,
+    logger_type: LoggerType,
+    mut connect: impl FnMut(PlayerID, &str) -> Result<(RemotePlayer, String)>,
+) -> Result<GameResult> {
+    if config.clients > config.seats {
+        return Err(anyhow!("Can't seat {} players at a table of {}", config.clients, config.seats));
+    }
+    rules.check(config.seats)?;
+    let players = (0..config.seats)
+        .map(|seat| PlayerConfig::new(config.bot.player_type.clone(), format!("{} {}", config.bot.name, seat + 1)))
+        .collect();
+    let seed = config.seed.unwrap_or_else(rand::random);
+    let mut game = Game::new(rules, players, logger_type, seed)?;
+    if let Some(talk) = config.bot_talk {
+        game.set_bot_talk(talk);
+    }
+    for seat in 0..config.clients {
+        let id = PlayerID(seat as u8);
+        let (player, name) = connect(id.clone(), &format!("Player {}", seat + 1))?;
+        game.take_seat(&id, name, Box::new(player));
+    }
+    let spectators = config.spectate.open(&mut game)?;
+    let result = game.play();
+    if let Some(spectators) = spectators {
+        let _ = spectators.join();
+    }
+    Ok(result)
+}
+
+/// Sits down at a server's table and plays until the game is over
+pub fn join(config: &JoinConfig) -> Result<()> {
+    if config.list {
+        for table in lobby::list_tables(&config.address)? {
+            println!("{}", table);
+        }
+        return Ok(());
+    }
+    let plan = config.plan()?;
+    let stream = TcpStream::connect(&config.address)?;
+    let transcript = match (&config.bot, config.tui) {
+        (Some(bot), _) => {
+            let name = config.name.clone().unwrap_or_else(|| bot.name.clone());
+            let seed = rand::random();
+            let visit = plan.map(|plan| Visit::new(plan, &name, true));
+            let talk = config.bot_talk;
+            play_seat(stream, &name, true, visit, |id| {
+                let player = bot_player(&bot.player_type, id, seed)?;
+                Ok(match talk {
+                    Some(talk) => Box::new(TableTalk::new(player, talk)),
+                    None => player,
+                })
+            })?
+        }
+        (None, true) => {
+            let mut prompter = TuiPrompter::new()?;
+            let name = match &config.name {
+                Some(name) => name.clone(),
+                None => prompter.prompt_text("Please enter your name", None)?,
+            };
+            prompter.set_name(name.clone());
+            let visit = plan.map(|plan| Visit::new(plan, &name, false));
+            play_seat(stream, &name, false, visit, |id| Ok(Box::new(HumanPlayer::new(id, prompter)) as Box<dyn Player>))?
+        }
+        (None, false) => {
+            let mut prompter = LocalPrompter::new();
+            let name = match &config.name {
+                Some(name) => name.clone(),
+                None => {
+                    println!("Please enter your name:");
+                    prompter.prompt_player(None)?
+                }
+            };
+            prompter.set_name(name.clone());
+            let visit = plan.map(|plan| Visit::new(plan, &name, true));
+            play_seat(stream, &name, true, visit, |id| Ok(Box::new(HumanPlayer::new(id, prompter)) as Box<dyn Player>))?
+        }
+    };
+    match transcript.events.last() {
+        // Everything else has been printed as it happened, unless the terminal UI was up
+        Some(event @ Event::GameOver { .. }) if config.tui && config.bot.is_none() => {
+            println!("{}", event.describe(&transcript.names))
+        }
+        Some(Event::GameOver { .. }) => {}
+        _ => println!("The server ended the game early"),
+    }
+    Ok(())
+}
+
+pub(crate) fn bot_player(player_type: &PlayerType, id: PlayerID, seed: u64) -> Result<Box<dyn Player>> {
+    match player_type {
+        PlayerType::DumbCPU => Ok(Box::new(DumbPlayer::new(id))),
+        PlayerType::HonestCPU => Ok(Box::new(HonestPlayer::new(id))),
+        PlayerType::RandomCPU(weights) => Ok(Box::new(RandomPlayer::with_weights(id, weights.clone(), seed))),
+        _ => Err(anyhow!("Only random, honest and dumb bots can join a table")),
+    }
+}
+
+/// Names of the players at the table, as learned from the views the server sends
+#[derive(Default)]
+struct Names(HashMap<PlayerID, String>);
+
+impl PlayerNames for Names {
+    fn get_player_name(&self, player_id: &PlayerID) -> String {
+        match self.0.get(player_id) {
+            Some(name) => name.clone(),
+            None => format!("Player {}", player_id.0 + 1),
+        }
+    }
+}
+
+/// Everything a seat was told over a game
+pub(crate) struct Transcript {
+    seat: PlayerID,
+    pub events: Vec<Event>,
+    names: Names,
+}
+
+// Plays whatever seat the server offers, with the player made for it making every decision.
+// Events are printed as they arrive if asked. At a lobby, the visit finds the seat first.
+pub(crate) fn play_seat(
+    stream: TcpStream,
+    name: &str,
+    echo: bool,
+    mut visit: Option<Visit>,
+    make_player: impl FnOnce(PlayerID) -> Result<Box<dyn Player>>,
+) -> Result<Transcript> {
+    stream.set_nodelay(true)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    let mut make_player = Some(make_player);
+    let mut player: Option<Box<dyn Player>> = None;
+    let mut transcript = Transcript {
+        seat: PlayerID(0),
+        events: Vec::new(),
+        names: Names::default(),
+    };
+    if let Some(token) = visit.as_ref().and_then(Visit::rejoining) {
+        let rejoin = Response::Rejoin {
+            protocol_version: PROTOCOL_VERSION,
+            token: token.to_string(),
+        };
+        writeln!(writer, "{}", serde_json::to_string(&rejoin)?)?;
+        writer.flush()?;
+    }
+    let mut line = String::new();
+    loop {
+        line.clear();
+        // The server hangs up once the game is over
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(transcript);
+        }
+        let message: Value = serde_json::from_str(&line)?;
+        let kind = message.get("type").and_then(Value::as_str).unwrap_or_default();
+        if LobbyMessage::TYPES.contains(&kind) {
+            let visit = visit
+                .as_mut()
+                .ok_or_else(|| anyhow!("The server is a lobby. Pick a table with --table, or set one up with --new-table"))?;
+            match visit.answer(serde_json::from_value(message)?)? {
+                Next::Send(request) => writeln!(writer, "{}", serde_json::to_string(&request)?)?,
+                Next::Wait => {}
+                Next::Leave => return Ok(transcript),
+            }
+            writer.flush()?;
+            continue;
+        }
+        let response = match serde_json::from_value(message)? {
+            Message::Hello {
+                protocol_version,
+                seat,
+                token,
+            } => {
+                if protocol_version != PROTOCOL_VERSION {
+                    return Err(anyhow!(
+                        "The server speaks protocol version {}, but only version {} is supported",
+                        protocol_version,
+                        PROTOCOL_VERSION
+                    ));
+                }
+                let make_player = make_player.take().ok_or_else(|| anyhow!("The server said hello twice"))?;
+                player = Some(make_player(seat.clone())?);
+                transcript.names.0.insert(seat.clone(), name.to_string());
+                transcript.seat = seat;
+                if let (true, Some(token)) = (echo, token) {
+                    println!("If your connection drops, get back to your seat with --rejoin {}", token);
+                }
+                Response::Hello {
+                    protocol_version: PROTOCOL_VERSION,
+                    name: Some(name.to_string()),
+                }
+            }
+            Message::Event { event } => {
+                if echo {
+                    println!("{}", event.describe(&transcript.names));
+                }
+                if let Some(player) = &mut player {
+                    player.observe(&event);
+                }
+                transcript.events.push(event);
+                continue;
+            }
+            Message::Decide { id, view, options } => {
+                let player = player.as_mut().ok_or_else(|| anyhow!("Asked to decide before being seated"))?;
+                for info in &view.players {
+                    transcript.names.0.insert(info.id.clone(), info.name.clone());
+                }
+                let choice = encode(seat::decide(player.as_mut(), &view), &view.pending);
+                // Anything said while deciding goes ahead of the choice
+                for message in player.talk() {
+                    writeln!(writer, "{}", serde_json::to_string(&Response::Say { message })?)?;
+                }
+                // Anything the server didn't offer gets its first, safe, option
+                let index = options.iter().position(|option| *option == choice).unwrap_or(0);
+                Response::Choice { id, index }
+            }
+        };
+        writeln!(writer, "{}", serde_json::to_string(&response)?)?;
+        writer.flush()?;
+    }
+}
+
+/// A choice written the way the server writes its options
+pub fn encode(choice: Choice, pending: &PendingDecision) -> Value {
+    let value = match (choice, pending) {
+        // The server offers targets for a forced coup, rather than actions
+        (Choice::Action(Action::Coup(target)), PendingDecision::ForcedCoup) => serde_json::to_value(target),
+        (Choice::Action(action), _) => serde_json::to_value(action),
+        (Choice::Challenge(challenge), _) => serde_json::to_value(challenge),
+        (Choice::Block(block), _) => serde_json::to_value(block),
+        (Choice::ReplaceCard(index), _) => serde_json::to_value(index),
+        (Choice::LoseCard(index), _) => serde_json::to_value(index),
+    };
+    value.unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::player::random_player::RandomWeights;
+
+    // A table on a free port with the given seats and bots, and the listener to serve it on.
+    // Tests change anything else they need before starting it.
+    pub(super) fn test_server(seats: usize, clients: usize, seed: u64, bot_talk: Option<Talk>) -> (TcpListener, ServeConfig) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = ServeConfig {
+            bind: listener.local_addr().unwrap().to_string(),
+            seats,
+            clients,
+            bot: "random".parse().unwrap(),
+            seed: Some(seed),
+            web: false,
+            grace: 60,
+            stand_in: "honest".parse().unwrap(),
+            answer_timeout: 300,
+            bot_talk,
+            spectate: SpectateConfig::default(),
+        };
+        (listener, config)
+    }
+
+    // Plays the game in the background. Returns where to connect, and the result once it's over.
+    pub(super) fn start_server(listener: TcpListener, config: ServeConfig) -> (SocketAddr, Receiver<GameResult>) {
+        let address = listener.local_addr().unwrap();
+        let (done, finished) = mpsc::channel();
+        thread::spawn(move || {
+            let result = serve_on(listener, &config, Rules::default(), LoggerType::Quiet).unwrap();
+            let _ = done.send(result);
+        });
+        (address, finished)
+    }
+
+    #[test]
+    fn clients_play_a_game_on_localhost() {
+        let (listener, config) = test_server(4, 2, 42, None);
+        let (address, finished) = start_server(listener, config);
+        let clients: Vec<_> = (0..2)
+            .map(|client| {
+                thread::spawn(move || {
+                    let stream = TcpStream::connect(address).unwrap();
+                    let bot = PlayerType::RandomCPU(RandomWeights::default());
+                    play_seat(stream, &format!("Client {}", client), false, None, |id| bot_player(&bot, id, client)).unwrap()
+                })
+            })
+            .collect();
+
+        let result = finished.recv().unwrap();
+        for client in clients {
+            let transcript = client.join().unwrap();
+            // Dealt their own cards, and nobody else's
+            assert!(transcript
+                .events
+                .iter()
+                .any(|event| event.private_to() == Some(&transcript.seat)));
+            for event in &transcript.events {
+                if let Some(player) = event.private_to() {
+                    assert_eq!(player, &transcript.seat, "{:?} was sent to the wrong seat", event);
+                }
+            }
+            match transcript.events.last() {
+                Some(Event::GameOver { winner }) => assert_eq!(winner, &result.winner),
+                last => panic!("The game didn't end with a winner, but {:?}", last),
+            }
+        }
+    }
+
+    #[test]
+    fn dropped_players_get_their_seat_back() {
+        let (listener, config) = test_server(3, 1, 5, None);
+        let (address, finished) = start_server(listener, config);
+
+        // Sits down, then drops the connection at the first decision
+        let stream = TcpStream::connect(address).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let (seat, token) = match serde_json::from_str(&line).unwrap() {
+            Message::Hello { seat, token, .. } => (seat, token.unwrap()),
+            _ => panic!("Not greeted with hello: {}", line),
+        };
+        let hello = Response::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            name: Some("Dropped".to_string()),
+        };
+        writeln!(writer, "{}", serde_json::to_string(&hello).unwrap()).unwrap();
+        loop {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+            if let Message::Decide { .. } = serde_json::from_str(&line).unwrap() {
+                break;
+            }
+        }
+        drop(reader);
+        drop(writer);
+
+        let stream = TcpStream::connect(address).unwrap();
+        let visit = Visit::new(Plan::Rejoin(token), "Back", false);
+        let bot = PlayerType::RandomCPU(RandomWeights::default());
+        let transcript = play_seat(stream, "Back", false, Some(visit), |id| bot_player(&bot, id, 1)).unwrap();
+
+        let result = finished.recv().unwrap();
+        assert_eq!(transcript.seat, seat);
+        // Brought up to date with the deal, as well as playing to the end
+        assert!(transcript
+            .events
+            .iter()
+            .any(|event| event.private_to() == Some(&seat)));
+        match transcript.events.last() {
+            Some(Event::GameOver { winner }) => assert_eq!(winner, &result.winner),
+            last => panic!("The game didn't end with a winner, but {:?}", last),
+        }
+    }
+
+    #[test]
+    fn quiet_players_are_taken_to_have_dropped() {
+        let (listener, mut config) = test_server(3, 1, 9, None);
+        config.grace = 0;
+        config.answer_timeout = 1;
+        let (address, finished) = start_server(listener, config);
+
+        // Sits down, then never answers the first decision, with the connection left open
+        let stream = TcpStream::connect(address).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let hello = Response::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            name: Some("Quiet".to_string()),
+        };
+        writeln!(writer, "{}", serde_json::to_string(&hello).unwrap()).unwrap();
+        loop {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+            if let Message::Decide { .. } = serde_json::from_str(&line).unwrap() {
+                break;
+            }
+        }
+
+        // The stand-in plays the seat from then on, so the game still finishes
+        let result = finished.recv_timeout(Duration::from_secs(30)).expect("The table hung on a quiet player");
+        assert!(result.turns > 0);
+        drop(writer);
+    }
+
+    #[test]
+    fn table_talk_reaches_every_seat() {
+        let (listener, config) = test_server(3, 1, 11, Some(Talk::Taunts));
+        let (address, finished) = start_server(listener, config);
+        let stream = TcpStream::connect(address).unwrap();
+        let transcript = play_seat(stream, "Talker", false, None, |id| {
+            let bot = bot_player(&PlayerType::HonestCPU, id, 0)?;
+            Ok(Box::new(TableTalk::new(bot, Talk::Reasoning)) as Box<dyn Player>)
+        })
+        .unwrap();
+        finished.recv().unwrap();
+
+        let speakers: Vec<&PlayerID> = transcript
+            .events
+            .iter()
+            .filter_map(|event| match event {
+                Event::Chat { player, .. } => Some(player),
+                _ => None,
+            })
+            .collect();
+        // The client's reasoning comes back to it, and the bots' taunts reach it
+        assert!(speakers.contains(&&transcript.seat));
+        assert!(speakers.iter().any(|&player| player != &transcript.seat));
+    }
+}
@@ -0,0 +1,367 @@
+//! Playing in a browser.
+//!
+//! With `coup serve --web` the server answers plain HTTP requests with a small page, and takes
+//! the page's WebSocket connections as players. Each WebSocket text message carries one line of
+//! the usual protocol (see `external_player`), so the page is sent its seat's view and the legal
+//! options for every decision, and answers with the index of the one clicked.
+//!
+//! Browser seats have sessions, as TCP seats do. The page keeps the token it's greeted with, and
+//! if its connection drops it opens a WebSocket at `/rejoin/TOKEN` to get back to the seat.
+
+use super::{open_session, Rejoins, ServeConfig};
+use crate::player::remote_player::{Connection, RemotePlayer};
+use crate::PlayerID;
+use anyhow::{anyhow, Result};
+use std::io::{self, BufRead, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tungstenite::protocol::frame::coding::CloseCode;
+use tungstenite::protocol::CloseFrame;
+use tungstenite::{Message, WebSocket};
+
+/// The browser client
+const CLIENT: &str = include_str!("client.html");
+
+/// How long a connection gets to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// WebSockets waiting for a seat, along with where they came from
+pub type Sockets = Receiver<(WebSocket<TcpStream>, SocketAddr)>;
+
+/// Serves the page and accepts WebSockets in the background, for as long as the program runs.
+/// Only the first `clients` WebSockets are handed over, later ones are told the table is full
+/// unless they're rejoining a seat.
+pub fn listen(listener: TcpListener, clients: usize, rejoins: Rejoins, timeout: Duration) -> Sockets {
+    let (sender, receiver) = mpsc::channel();
+    let taken = Arc::new(AtomicUsize::new(0));
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let (stream, sender, taken, rejoins) = match stream {
+                Ok(stream) => (stream, sender.clone(), taken.clone(), rejoins.clone()),
+                Err(e) => {
+                    log::warn!("Unable to accept a connection: {}", e);
+                    continue;
+                }
+            };
+            thread::spawn(move || {
+                let address = stream.peer_addr();
+                match (respond(stream), address) {
+                    (Ok(Some((socket, path))), Ok(address)) => match path.strip_prefix("/rejoin/") {
+                        Some(token) => match rejoin(socket, token, &rejoins, timeout) {
+                            Ok(seat) => println!("Seat {} rejoined from {}", seat.0, address),
+                            Err(e) => log::warn!("Turned away {}: {}", address, e),
+                        },
+                        None if taken.fetch_add(1, Ordering::SeqCst) < clients => {
+                            let _ = sender.send((socket, address));
+                        }
+                        None => turn_away(socket, "The table is full"),
+                    },
+                    (Err(e), _) => log::warn!("Unable to answer a request: {}", e),
+                    _ => {}
+                }
+            });
+        }
+    });
+    receiver
+}
+
+/// Waits for the next WebSocket and greets it as the player at the given seat, with a session
+/// for getting back to the seat. A browser that takes longer than the answer timeout to answer
+/// is taken to have dropped.
+pub fn connect(
+    sockets: &Sockets,
+    config: &ServeConfig,
+    rejoins: &Rejoins,
+    id: PlayerID,
+    name: &str,
+) -> Result<(RemotePlayer, String)> {
+    let (socket, address) = sockets.recv()?;
+    let session = open_session(config, rejoins, &id)?;
+    let connection = lines(socket, Duration::from_secs(config.answer_timeout))?;
+    let (player, name) = RemotePlayer::with_session(id, name, connection, session)?;
+    println!("{} joined from {}", name, address);
+    Ok((player, name))
+}
+
+// Hands a WebSocket over to the seat with the token, and returns the seat
+fn rejoin(socket: WebSocket<TcpStream>, token: &str, rejoins: &Rejoins, timeout: Duration) -> Result<PlayerID> {
+    let seat = rejoins.lock().unwrap().get(token).cloned();
+    let (seat, handover) = match seat {
+        Some(seat) => seat,
+        None => {
+            turn_away(socket, "No seat has that session token");
+            return Err(anyhow!("No seat has that session token"));
+        }
+    };
+    handover
+        .send(lines(socket, timeout)?)
+        .map_err(|_| anyhow!("The game is over"))?;
+    Ok(seat)
+}
+
+// Both ends of a WebSocket, a line of the protocol per message
+fn lines(socket: WebSocket<TcpStream>, timeout: Duration) -> Result<Connection> {
+    socket.get_ref().set_read_timeout(Some(timeout))?;
+    socket.get_ref().set_write_timeout(Some(timeout))?;
+    let socket = Arc::new(Mutex::new(socket));
+    let reader = SocketReader {
+        socket: socket.clone(),
+        buffer: Vec::new(),
+        position: 0,
+    };
+    let writer = SocketWriter {
+        socket,
+        buffer: Vec::new(),
+    };
+    Ok((Box::new(reader), Box::new(writer)))
+}
+
+// Upgrades the connection if it asks for a WebSocket, returning it along with the path it asked
+// for, and otherwise answers with the page
+fn respond(mut stream: TcpStream) -> Result<Option<(WebSocket<TcpStream>, String)>> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let head = peek_head(&stream)?;
+    let upgrade = head.lines().any(|line| {
+        let line = line.to_ascii_lowercase();
+        line.starts_with("upgrade:") && line.contains("websocket")
+    });
+    let path = head.split_whitespace().nth(1).unwrap_or("/").to_string();
+    if upgrade {
+        stream.set_nodelay(true)?;
+        let socket = tungstenite::accept(stream).map_err(|e| anyhow!("WebSocket handshake failed: {}", e))?;
+        socket.get_ref().set_read_timeout(None)?;
+        return Ok(Some((socket, path)));
+    }
+
+    // Only peeked so far, and browsers don't like their requests left unread
+    stream.read_exact(&mut vec![0; head.len()])?;
+    let (status, content_type, body) = match path.as_str() {
+        "/" | "/index.html" => ("200 OK", "text/html; charset=utf-8", CLIENT),
+        _ => ("404 Not Found", "text/plain; charset=utf-8", "Not found"),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+    Ok(None)
+}
+
+// The request line and headers, left in the stream for the WebSocket handshake to read
+fn peek_head(stream: &TcpStream) -> Result<String> {
+    let started = Instant::now();
+    let mut buffer = [0; 8192];
+    loop {
+        let read = stream.peek(&mut buffer)?;
+        if read == 0 {
+            return Err(anyhow!("Connection closed before a request was sent"));
+        }
+        if let Some(end) = buffer[..read].windows(4).position(|window| window == b"\r\n\r\n") {
+            return Ok(String::from_utf8_lossy(&buffer[..end + 4]).into_owned());
+        }
+        if read == buffer.len() {
+            return Err(anyhow!("Request too large"));
+        }
+        if started.elapsed() > REQUEST_TIMEOUT {
+            return Err(anyhow!("Timed out waiting for a request"));
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn turn_away(mut socket: WebSocket<TcpStream>, reason: &str) {
+    let _ = socket.close(Some(CloseFrame {
+        code: CloseCode::Again,
+        reason: reason.to_string().into(),
+    }));
+    let _ = socket.get_ref().set_read_timeout(Some(REQUEST_TIMEOUT));
+    // Wait for the browser to acknowledge
+    while socket.read().is_ok() {}
+}
+
+/// Reads a WebSocket a message at a time, as lines
+struct SocketReader {
+    socket: Arc<Mutex<WebSocket<TcpStream>>>,
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl Read for SocketReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let read = available.len().min(buf.len());
+        buf[..read].copy_from_slice(&available[..read]);
+        self.consume(read);
+        Ok(read)
+    }
+}
+
+impl BufRead for SocketReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.position >= self.buffer.len() {
+            let message = self.socket.lock().unwrap().read();
+            match message {
+                Ok(Message::Text(text)) => {
+                    self.buffer = text.into_bytes();
+                    self.buffer.push(b'\n');
+                    self.position = 0;
+                }
+                // The other end is gone
+                Ok(Message::Close(_))
+                | Err(tungstenite::Error::ConnectionClosed)
+                | Err(tungstenite::Error::AlreadyClosed) => return Ok(&[]),
+                // Pings are answered by tungstenite itself
+                Ok(_) => {}
+                Err(e) => return Err(io::Error::other(e)),
+            }
+        }
+        Ok(&self.buffer[self.position..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.position += amount;
+    }
+}
+
+/// Writes to a WebSocket, sending each line as a message when flushed
+struct SocketWriter {
+    socket: Arc<Mutex<WebSocket<TcpStream>>>,
+    buffer: Vec<u8>,
+}
+
+impl Write for SocketWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let buffer = std::mem::take(&mut self.buffer);
+        let text = String::from_utf8(buffer).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut socket = self.socket.lock().unwrap();
+        for line in text.lines() {
+            socket
+                .send(Message::Text(line.to_string()))
+                .map_err(io::Error::other)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SocketWriter {
+    fn drop(&mut self) {
+        // Lets the page know the game is over, rather than leaving it to notice the socket drop
+        if let Ok(mut socket) = self.socket.lock() {
+            let _ = socket.close(None);
+            let _ = socket.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::Event;
+    use crate::net::tests::{start_server, test_server};
+    use crate::player::link::{Message as Request, Response, PROTOCOL_VERSION};
+
+    #[test]
+    fn browsers_get_the_page_and_play_over_websockets() {
+        let (listener, mut config) = test_server(3, 1, 7, None);
+        config.web = true;
+        let (address, finished) = start_server(listener, config);
+
+        let mut page = TcpStream::connect(address).unwrap();
+        write!(page, "GET / HTTP/1.1\r\nHost: {}\r\n\r\n", address).unwrap();
+        let mut response = String::new();
+        page.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with(CLIENT));
+
+        let (seat, events, _) = play(&format!("ws://{}/", address), false);
+
+        let result = finished.recv().unwrap();
+        for event in &events {
+            if let Some(player) = event.private_to() {
+                assert_eq!(player, &seat, "{:?} was sent to the wrong seat", event);
+            }
+        }
+        match events.last() {
+            Some(Event::GameOver { winner }) => assert_eq!(winner, &result.winner),
+            last => panic!("The game didn't end with a winner, but {:?}", last),
+        }
+    }
+
+    #[test]
+    fn browsers_get_their_seat_back() {
+        let (listener, mut config) = test_server(3, 1, 8, None);
+        config.web = true;
+        let (address, finished) = start_server(listener, config);
+
+        let (seat, _, token) = play(&format!("ws://{}/", address), true);
+        let token = token.expect("Browser seats are given a session token");
+        let (back, events, _) = play(&format!("ws://{}/rejoin/{}", address, token), false);
+
+        let result = finished.recv().unwrap();
+        assert_eq!(back, seat);
+        // Brought up to date with the deal, as well as playing to the end
+        assert!(events.iter().any(|event| event.private_to() == Some(&seat)));
+        match events.last() {
+            Some(Event::GameOver { winner }) => assert_eq!(winner, &result.winner),
+            last => panic!("The game didn't end with a winner, but {:?}", last),
+        }
+    }
+
+    // Plays a seat from a WebSocket until the server closes it, or walks away at the first
+    // decision if asked to. Returns the seat, the events it was sent and its session token.
+    fn play(url: &str, walk_away: bool) -> (PlayerID, Vec<Event>, Option<String>) {
+        let (mut socket, _) = tungstenite::connect(url).unwrap();
+        let mut seat = None;
+        let mut token = None;
+        let mut events = Vec::new();
+        loop {
+            let text = match socket.read() {
+                Ok(Message::Text(text)) => text,
+                Ok(Message::Close(_)) | Err(tungstenite::Error::ConnectionClosed) => break,
+                Ok(_) => continue,
+                Err(e) => panic!("{}", e),
+            };
+            let response = match serde_json::from_str(&text).unwrap() {
+                Request::Hello {
+                    protocol_version,
+                    seat: offered,
+                    token: given,
+                } => {
+                    assert_eq!(protocol_version, PROTOCOL_VERSION);
+                    seat = Some(offered);
+                    token = given;
+                    Response::Hello {
+                        protocol_version: PROTOCOL_VERSION,
+                        name: Some("Browser".to_string()),
+                    }
+                }
+                Request::Event { event } => {
+                    events.push(event);
+                    continue;
+                }
+                Request::Decide { .. } if walk_away => break,
+                Request::Decide { id, view, options } => {
+                    // Asked with the seat's own view, and something to pick from
+                    assert_eq!(Some(&view.me), seat.as_ref());
+                    assert!(!options.is_empty());
+                    Response::Choice { id, index: id as usize % options.len() }
+                }
+            };
+            socket.send(Message::Text(serde_json::to_string(&response).unwrap())).unwrap();
+        }
+        (seat.unwrap(), events, token)
+    }
+}
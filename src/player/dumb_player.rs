@@ -1,6 +1,6 @@
-use crate::{Action, GameState, Identity, PlayerID};
+use crate::view::PlayerView;
+use crate::{Action, Identity, PlayerID};
 use crate::player::traits::Player;
-use anyhow::Result;
 
 pub struct DumbPlayer {
     id: PlayerID,
@@ -15,35 +15,34 @@ impl DumbPlayer {
 }
 
 impl Player for DumbPlayer {
-    fn choose_action(&self, _state: &GameState) -> Action {
+    fn choose_action(&mut self, _view: &PlayerView) -> Action {
         Action::Income
     }
-    fn will_challenge(&self, _state: &GameState, _player_id: &PlayerID, _action: &Action) -> bool {
+    fn will_challenge(&mut self, _view: &PlayerView, _player_id: &PlayerID, _action: &Action) -> bool {
         false
     }
-    fn will_block(&self, _state: &GameState, _player_id: &PlayerID, _action: &Action) -> Option<Action> {
+    fn will_block(&mut self, _view: &PlayerView, _player_id: &PlayerID, _action: &Action) -> Option<Action> {
 	None
     }
     // Index in hand to replace
-    fn choose_card_to_replace(&self, _state: &GameState, _card: &Identity) -> Option<usize> {
+    fn choose_card_to_replace(&mut self, _view: &PlayerView, _card: &Identity) -> Option<usize> {
 	None
     }
 
-    fn choose_card_to_lose(&self, _state: &GameState) -> usize {
+    fn choose_card_to_lose(&mut self, _view: &PlayerView) -> usize {
 	0
     }
 
-    fn choose_forced_coup(&self, state: &GameState) -> PlayerID {
-	for player_id in &state.active_players {
-            if player_id != self.who_am_i() {
-                return player_id.clone();
-            }
-        }
-        panic!("No other players to coup!");
+    fn choose_forced_coup(&mut self, view: &PlayerView) -> PlayerID {
+	// Coup the first player still in, or name ourselves for the game to refuse
+	match self.get_other_active_players(view).first() {
+	    Some(player_id) => player_id.clone(),
+	    None => self.id.clone(),
+	}
     }
     
     fn get_hand(&self) -> Vec<Identity> {
-        self.hand.iter().cloned().collect()
+        self.hand.to_vec()
     }
 
     fn set_hand(&mut self, hand: Vec<Identity>) {
@@ -0,0 +1,226 @@
+//! A player backed by an external program, so bots can be written in any language.
+//!
+//! The engine launches the configured command and talks to it over its stdin and stdout, one
+//! JSON object per line. Anything the program writes to stderr is passed straight through.
+//!
+//! ## Handshake
+//!
+//! The engine opens with
+//!
+//! ```text
+//! {"type":"hello","protocol_version":1,"seat":0,"name":"Porter"}
+//! ```
+//!
+//! and the program must answer with the protocol version it speaks, and optionally a name:
+//!
+//! ```text
+//! {"type":"hello","protocol_version":1,"name":"my-bot"}
+//! ```
+//!
+//! The game refuses to start if the versions differ. Seats played over the network may also be
+//! given a `token` in the hello, for getting back to the seat (see `remote_player`).
+//!
+//! ## Events
+//!
+//! Every event the seat is allowed to see is forwarded as it happens. No reply is expected.
+//!
+//! ```text
+//! {"type":"event","event":{"kind":"action_chosen","actor":1,"action":"Tax"}}
+//! ```
+//!
+//! ## Decisions
+//!
+//! Each decision is a request carrying the seat's view of the game (the `pending` field of the
+//! view says what is being decided, and `rules` holds the costs and amounts in play) and the
+//! list of legal options:
+//!
+//! ```text
+//! {"type":"decide","id":3,"view":{...},"options":["Income","ForeignAid",...]}
+//! ```
+//!
+//! The program answers with the index of the option it picked, echoing the request id:
+//!
+//! ```text
+//! {"type":"choice","id":3,"index":1}
+//! ```
+//!
+//! Options for challenges are `[false, true]`, for blocks `null` followed by the possible
+//! blocking actions, and card choices are indexes into the hand in the view (`null` means keep
+//! the current hand when offered a card during an exchange). The first option is always the
+//! safe one, and is used if the program sends back something the engine can't understand.
+//!
+//! ## Chat
+//!
+//! The program can say something to the table at any time:
+//!
+//! ```text
+//! {"type":"say","message":"I have the Duke, honest."}
+//! ```
+//!
+//! It is heard the next time the seat is asked for a decision, and comes back to every seat as
+//! a `chat` event. Messages are kept to one line of at most 200 characters.
+
+use crate::event::Event;
+use crate::player::link::Link;
+use crate::player::traits::Player;
+use crate::view::PlayerView;
+use crate::{Action, Identity, PlayerID};
+use anyhow::{anyhow, Result};
+use std::io::BufReader;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a bot gets to exit by itself once its stdin is closed
+const EXIT_GRACE: Duration = Duration::from_millis(500);
+
+pub struct ExternalPlayer {
+    id: PlayerID,
+    hand: Vec<Identity>,
+    child: Child,
+    link: Link,
+}
+
+impl ExternalPlayer {
+    /// Launches the command and performs the handshake
+    pub fn new(id: PlayerID, name: &str, command: &str) -> Result<Self> {
+        // Split the way a shell would, so quoted arguments and paths with spaces stay whole
+        let words = shlex::split(command).ok_or_else(|| anyhow!("Unable to parse the command '{}'", command))?;
+        let (program, args) = words
+            .split_first()
+            .ok_or_else(|| anyhow!("No command given for external player {}", name))?;
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| anyhow!("Unable to launch '{}': {}", command, e))?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut link = Link::new(id.clone(), Box::new(stdout), Box::new(stdin));
+        if let Some(bot_name) = link.handshake(name, None)? {
+            log::info!("{} is played by {}", name, bot_name);
+        }
+
+        Ok(ExternalPlayer {
+            id,
+            hand: Vec::new(),
+            child,
+            link,
+        })
+    }
+
+    fn hand_indexes(&self) -> Vec<usize> {
+        (0..self.hand.len()).collect()
+    }
+}
+
+impl Player for ExternalPlayer {
+    fn choose_action(&mut self, view: &PlayerView) -> Action {
+        let options = self.get_available_actions(view);
+        self.link.choose(view, options)
+    }
+
+    fn will_challenge(&mut self, view: &PlayerView, _player_id: &PlayerID, _action: &Action) -> bool {
+        self.link.choose(view, vec![false, true])
+    }
+
+    fn will_block(&mut self, view: &PlayerView, _player_id: &PlayerID, action: &Action) -> Option<Action> {
+        let mut options = vec![None];
+        if let Some(blocks) = action.blockable(self.who_am_i()) {
+            options.extend(blocks.into_iter().map(Some));
+        }
+        self.link.choose(view, options)
+    }
+
+    fn choose_card_to_replace(&mut self, view: &PlayerView, _card: &Identity) -> Option<usize> {
+        let mut options = vec![None];
+        options.extend(self.hand_indexes().into_iter().map(Some));
+        self.link.choose(view, options)
+    }
+
+    fn choose_card_to_lose(&mut self, view: &PlayerView) -> usize {
+        let options = self.hand_indexes();
+        self.link.choose(view, options)
+    }
+
+    fn choose_forced_coup(&mut self, view: &PlayerView) -> PlayerID {
+        let options = self.get_other_active_players(view);
+        self.link.choose(view, options)
+    }
+
+    fn observe(&mut self, event: &Event) {
+        self.link.observe(event);
+    }
+
+    fn talk(&mut self) -> Vec<String> {
+        self.link.take_said()
+    }
+
+    fn get_hand(&self) -> Vec<Identity> {
+        self.hand.to_vec()
+    }
+
+    fn set_hand(&mut self, hand: Vec<Identity>) {
+        self.hand = hand;
+    }
+
+    fn who_am_i(&self) -> &PlayerID {
+        &self.id
+    }
+}
+
+impl Drop for ExternalPlayer {
+    fn drop(&mut self) {
+        // Closing stdin tells a well behaved bot to exit, so it gets a moment to do so
+        self.link.close();
+        let deadline = Instant::now() + EXIT_GRACE;
+        while let Ok(None) = self.child.try_wait() {
+            if Instant::now() >= deadline {
+                let _ = self.child.kill();
+                break;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::Rules;
+    use crate::{Game, LoggerType, PlayerConfig, PlayerType};
+
+    #[test]
+    fn games_are_played_against_the_echo_bot() {
+        if Command::new("python3").arg("--version").output().is_err() {
+            eprintln!("Skipping: python3 isn't installed");
+            return;
+        }
+        let players = vec![
+            PlayerConfig::new(PlayerType::DumbCPU, "Echo".to_string()),
+            PlayerConfig::new(PlayerType::HonestCPU, "Honest".to_string()),
+        ];
+        let mut game = Game::new(Rules::default(), players, LoggerType::Quiet, 4).unwrap();
+        let echo = PlayerID(0);
+        let bot = ExternalPlayer::new(echo.clone(), "Echo", "python3 bots/echo_bot.py").unwrap();
+        game.take_seat(&echo, "Echo".to_string(), Box::new(bot));
+        game.set_turn_limit(200);
+        let result = game.play();
+
+        assert!(result.turns > 0);
+        // The bot took its turns over the protocol
+        let acted = |event: &Event| matches!(event, Event::ActionChosen { actor, .. } if actor == &echo);
+        assert!(game.state.history.iter().any(acted));
+    }
+
+    #[test]
+    fn commands_are_split_like_a_shell_would() {
+        let error = ExternalPlayer::new(PlayerID(0), "Bad", "python3 'bots/echo_bot.py").err().unwrap();
+        assert!(error.to_string().contains("Unable to parse"), "{}", error);
+        if Command::new("python3").arg("--version").output().is_ok() {
+            ExternalPlayer::new(PlayerID(0), "Quoted", "python3 \"bots/echo_bot.py\"").unwrap();
+        }
+    }
+}
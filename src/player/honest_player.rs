@@ -0,0 +1,115 @@
+use crate::player::traits::Player;
+use crate::seat::{self, Choice};
+use crate::view::PlayerView;
+use crate::{Action, Identity, PlayerID};
+use std::cmp::min;
+
+/// Never bluffs and never challenges. Takes the strongest action its hand allows, against the
+/// opponent who looks most dangerous.
+pub struct HonestPlayer {
+    id: PlayerID,
+    hand: Vec<Identity>,
+}
+
+impl HonestPlayer {
+    pub fn new(id: PlayerID) -> Self {
+        let hand = Vec::new();
+        HonestPlayer { id, hand }
+    }
+
+    // Lower is better
+    fn preference(action: &Action) -> u8 {
+        match action {
+            Action::Coup(..) => 0,
+            Action::Assassinate(..) => 1,
+            Action::Tax => 2,
+            Action::Steal(..) => 3,
+            Action::ForeignAid => 4,
+            Action::Exchange => 5,
+            _ => 6,
+        }
+    }
+
+    // The opponent with the most lives, then the most coins
+    fn biggest_threat(view: &PlayerView) -> Option<PlayerID> {
+        view.other_active_players()
+            .into_iter()
+            .max_by_key(|id| {
+                let info = view.player(id);
+                (info.num_lives, info.coins)
+            })
+    }
+
+    fn is_target(action: &Action, target: &Option<PlayerID>) -> bool {
+        match action {
+            Action::Coup(id) | Action::Assassinate(id) | Action::Steal(id) => Some(id) == target.as_ref(),
+            _ => true,
+        }
+    }
+
+    // A card we hold twice, which is the cheapest to give up
+    fn duplicate(&self) -> Option<usize> {
+        (0..self.hand.len()).find(|&index| self.hand[index + 1..].contains(&self.hand[index]))
+    }
+}
+
+impl Player for HonestPlayer {
+    fn choose_action(&mut self, view: &PlayerView) -> Action {
+        let target = HonestPlayer::biggest_threat(view);
+        let hand = &self.hand;
+        let coins = view.player(&self.id).coins;
+        seat::legal_actions(view)
+            .into_iter()
+            .filter(|action| action.can_be_done_with(hand) && HonestPlayer::is_target(action, &target))
+            // Only steal when it sets up a coup, otherwise two captains can take the same
+            // coins back and forth forever
+            .filter(|action| match action {
+                Action::Steal(id) => {
+                    coins + min(view.player(id).coins, view.rules.steal_amount) >= view.rules.coup_cost
+                }
+                _ => true,
+            })
+            .min_by_key(HonestPlayer::preference)
+            .unwrap_or(Action::Income)
+    }
+
+    fn will_challenge(&mut self, _view: &PlayerView, _player_id: &PlayerID, _action: &Action) -> bool {
+        false
+    }
+
+    fn will_block(&mut self, view: &PlayerView, _player_id: &PlayerID, _action: &Action) -> Option<Action> {
+        seat::legal_choices(view).into_iter().find_map(|choice| match choice {
+            Choice::Block(Some(block)) if block.can_be_done_with(&self.hand) => Some(block),
+            _ => None,
+        })
+    }
+
+    // Index in hand to replace
+    fn choose_card_to_replace(&mut self, _view: &PlayerView, card: &Identity) -> Option<usize> {
+        if self.hand.contains(card) {
+            return None;
+        }
+        self.duplicate()
+    }
+
+    fn choose_card_to_lose(&mut self, _view: &PlayerView) -> usize {
+        self.duplicate().unwrap_or(0)
+    }
+
+    fn choose_forced_coup(&mut self, view: &PlayerView) -> PlayerID {
+        // Coup the biggest threat, or name ourselves for the game to refuse
+        HonestPlayer::biggest_threat(view).unwrap_or_else(|| self.id.clone())
+    }
+
+    fn get_hand(&self) -> Vec<Identity> {
+        self.hand.to_vec()
+    }
+
+    fn set_hand(&mut self, hand: Vec<Identity>) {
+        self.hand = hand;
+    }
+
+    fn who_am_i(&self) -> &PlayerID {
+        &self.id
+    }
+}
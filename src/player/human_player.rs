@@ -1,7 +1,7 @@
 use crate::player::traits::Player;
 use crate::prompter::{LocalPrompter, PromptInfo, Prompter};
-use crate::{Action, GameState, Identity, PlayerID};
-use anyhow::{anyhow, Result};
+use crate::view::{PlayerNames, PlayerView};
+use crate::{Action, Identity, PlayerID};
 
 pub struct HumanPlayer<P: Prompter> {
     // Not necessarily two?
@@ -17,69 +17,60 @@ impl<P: Prompter> HumanPlayer<P> {
     }
 }
 
-impl<P: Prompter> Player for HumanPlayer<P> {
-    fn choose_action(&self, state: &GameState) -> Action {
-        let available_actions = self.get_available_actions(state);
+impl<P: Prompter + Send> Player for HumanPlayer<P> {
+    fn choose_action(&mut self, view: &PlayerView) -> Action {
+        let available_actions = self.get_available_actions(view);
         let action = self.prompter.prompt_player_for_action(
             "What will you do?",
             available_actions,
-            PromptInfo {
-                state,
-                player_hand: self.get_hand(),
-            },
+            PromptInfo { view },
         );
         match action {
             Ok(action) => action,
-            Err(e) => {
+            Err(_) => {
                 println!("Hm. I didn't get that...");
-                self.choose_action(state)
+                self.choose_action(view)
             }
         }
     }
 
-    fn will_challenge(&self, state: &GameState, player_id: &PlayerID, action: &Action) -> bool {
+    fn will_challenge(&mut self, view: &PlayerView, player_id: &PlayerID, action: &Action) -> bool {
         let question = &format!(
             "Would you like to challenge {}'s {}?",
-            state.get_player_name(player_id),
+            view.get_player_name(player_id),
             // TODO fix this! I hate that I genericized this but then it doesn't work.
             // This should maybe be on game state?
-            LocalPrompter::display_action(state, action.clone())
+            LocalPrompter::display_action(view, action.clone())
         );
 
         match self.prompter.prompt_player_yes_no(
             question,
-            Some(PromptInfo {
-                state,
-                player_hand: self.get_hand(),
-            }),
+            Some(PromptInfo { view }),
         ) {
             Ok(x) => x,
-            Err(e) => {
+            Err(_) => {
                 // To do --> errors handled in prompter?
                 println!("Hm. I didn't get that.");
-                self.will_challenge(state, player_id, action)
+                self.will_challenge(view, player_id, action)
             }
         }
     }
     fn will_block(
-        &self,
-        state: &GameState,
+        &mut self,
+        view: &PlayerView,
         player_id: &PlayerID,
         action: &Action,
     ) -> Option<Action> {
         let possible_actions = action.blockable(self.who_am_i());
         match possible_actions {
             Some(actions) => {
-                if actions.len() > 0 {
-                    let prompt_info = PromptInfo {
-                        state,
-                        player_hand: self.get_hand(),
-                    };
+                if !actions.is_empty() {
+                    let prompt_info = PromptInfo { view };
                     let human_readable_action =
-                        LocalPrompter::display_action(state, action.clone());
+                        LocalPrompter::display_action(view, action.clone());
                     let question = &format!(
                         "Would you like to block {}'s {}?",
-                        state.get_player_name(player_id),
+                        view.get_player_name(player_id),
                         &human_readable_action,
                     );
 
@@ -87,7 +78,7 @@ impl<P: Prompter> Player for HumanPlayer<P> {
                         let choice = self
                             .prompter
                             .prompt_player_for_action(
-                                &format!("How will you block?"),
+                                "How will you block?",
 				actions,
                                 prompt_info.clone(),
                             )
@@ -103,11 +94,8 @@ impl<P: Prompter> Player for HumanPlayer<P> {
             None => None,
         }
     }
-    fn choose_card_to_replace(&self, state: &GameState, card: &Identity) -> Option<usize> {
-        let prompt_info = Some(PromptInfo {
-            state,
-            player_hand: self.get_hand(),
-        });
+    fn choose_card_to_replace(&mut self, view: &PlayerView, card: &Identity) -> Option<usize> {
+        let prompt_info = Some(PromptInfo { view });
 
         if self
             .prompter
@@ -120,10 +108,7 @@ impl<P: Prompter> Player for HumanPlayer<P> {
             )
             .unwrap()
         {
-            let prompt_info = Some(PromptInfo {
-                state,
-                player_hand: self.get_hand(),
-            });
+            let prompt_info = Some(PromptInfo { view });
             let chosen_idx = self
                 .prompter
                 .prompt_player_choice("Which one?", self.get_hand(), prompt_info)
@@ -133,35 +118,32 @@ impl<P: Prompter> Player for HumanPlayer<P> {
             None
         }
     }
-    fn choose_card_to_lose(&self, state: &GameState) -> usize {
+    fn choose_card_to_lose(&mut self, view: &PlayerView) -> usize {
         // TODO Don't give choice on one card
-        let prompt_info = Some(PromptInfo {
-            state,
-            player_hand: self.get_hand(),
-        });
+        let prompt_info = Some(PromptInfo { view });
         // TODO don't unwrap
-        let chosen_idx = self
+        self
             .prompter
             .prompt_player_choice(
                 "Which identity will you discard?",
                 self.get_hand(),
                 prompt_info,
             )
-            .unwrap();
-        chosen_idx
+            .unwrap()
     }
-    fn choose_forced_coup(&self, state: &GameState) -> PlayerID {
-        let other_players = self.get_other_active_players(state);
-        /*let prompt_info = Some(PromptInfo {
-                state,
-                player_hand: self.get_hand(),
-            });
+    fn choose_forced_coup(&mut self, view: &PlayerView) -> PlayerID {
+        let other_players = self.get_other_active_players(view);
+        /*let prompt_info = Some(PromptInfo { view });
         let chosen_idx = self.prompter.prompt_player_choice("Which identity will you discard?", other_players, prompt_info);*/
-        return other_players[0].clone();
+        other_players[0].clone()
+    }
+
+    fn talk(&mut self) -> Vec<String> {
+        self.prompter.take_said()
     }
 
     fn get_hand(&self) -> Vec<Identity> {
-        self.hand.iter().cloned().collect()
+        self.hand.to_vec()
     }
 
     fn set_hand(&mut self, hand: Vec<Identity>) {
@@ -0,0 +1,224 @@
+//! The line-by-line JSON conversation with a player outside the engine, as described in
+//! `external_player`. External bots hold it over their stdin and stdout, and remote players over
+//! a TCP connection.
+
+use crate::event::Event;
+use crate::view::PlayerView;
+use crate::PlayerID;
+use anyhow::{anyhow, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Write};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// What the engine sends
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Request<'a> {
+    Hello {
+        protocol_version: u32,
+        seat: &'a PlayerID,
+        name: &'a str,
+        /// Lets a remote player get back to the seat if their connection drops
+        #[serde(skip_serializing_if = "Option::is_none")]
+        token: Option<&'a str>,
+    },
+    Event {
+        event: &'a Event,
+    },
+    Decide {
+        id: u64,
+        view: &'a PlayerView<'a>,
+        options: Vec<serde_json::Value>,
+    },
+}
+
+/// What the engine sends, as read at the other end
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Message {
+    Hello {
+        protocol_version: u32,
+        seat: PlayerID,
+        #[serde(default)]
+        token: Option<String>,
+    },
+    Event {
+        event: Event,
+    },
+    Decide {
+        id: u64,
+        view: PlayerView<'static>,
+        options: Vec<serde_json::Value>,
+    },
+}
+
+/// What the other end answers with
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Response {
+    Hello {
+        protocol_version: u32,
+        name: Option<String>,
+    },
+    Choice {
+        id: u64,
+        index: usize,
+    },
+    /// Sent first by a remote player getting back to their seat, instead of waiting to be greeted
+    Rejoin {
+        protocol_version: u32,
+        token: String,
+    },
+    /// Something to say to the table. Can be sent at any time, and is heard with the next choice
+    Say {
+        message: String,
+    },
+}
+
+pub struct Link {
+    id: PlayerID,
+    reader: Box<dyn BufRead + Send>,
+    writer: Option<Box<dyn Write + Send>>,
+    next_request: u64,
+    // Lines the other end has said, until the game hears them
+    said: Vec<String>,
+}
+
+impl Link {
+    pub fn new(id: PlayerID, reader: Box<dyn BufRead + Send>, writer: Box<dyn Write + Send>) -> Self {
+        Link {
+            id,
+            reader,
+            writer: Some(writer),
+            next_request: 0,
+            said: Vec::new(),
+        }
+    }
+
+    /// Greets the other end and makes sure it speaks the same protocol. Returns the name it
+    /// gave, if any.
+    pub fn handshake(&mut self, name: &str, token: Option<&str>) -> Result<Option<String>> {
+        let seat = self.id.clone();
+        self.send(&Request::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            seat: &seat,
+            name,
+            token,
+        })?;
+        match self.receive()? {
+            Response::Hello {
+                protocol_version,
+                name: their_name,
+            } => {
+                if protocol_version != PROTOCOL_VERSION {
+                    return Err(anyhow!(
+                        "{} speaks protocol version {}, but only version {} is supported",
+                        name,
+                        protocol_version,
+                        PROTOCOL_VERSION
+                    ));
+                }
+                Ok(their_name)
+            }
+            _ => Err(anyhow!("{} did not say hello", name)),
+        }
+    }
+
+    fn send(&mut self, request: &Request) -> Result<()> {
+        let writer = self
+            .writer
+            .as_mut()
+            .ok_or_else(|| anyhow!("Player has been shut down"))?;
+        let line = serde_json::to_string(request)?;
+        let sent = writeln!(writer, "{}", line).and_then(|_| writer.flush());
+        if sent.is_err() {
+            self.writer = None;
+        }
+        Ok(sent?)
+    }
+
+    // The next response that isn't chat. Chat is kept until it's taken.
+    fn receive(&mut self) -> Result<Response> {
+        loop {
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => {
+                    self.writer = None;
+                    return Err(anyhow!("Player closed the connection"));
+                }
+                Ok(_) => match serde_json::from_str(&line)? {
+                    Response::Say { message } => self.said.push(message),
+                    response => return Ok(response),
+                },
+                Err(e) => {
+                    self.writer = None;
+                    return Err(e.into());
+                }
+            }
+        }
+    }
+
+    /// Everything the other end has said since the last time
+    pub fn take_said(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.said)
+    }
+
+    /// Whether the other end is still there, as far as is known. A connection that fails is
+    /// never used again.
+    pub fn is_open(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    fn request_choice(&mut self, view: &PlayerView, options: Vec<serde_json::Value>) -> Result<usize> {
+        let id = self.next_request;
+        self.next_request += 1;
+        let num_options = options.len();
+        self.send(&Request::Decide { id, view, options })?;
+        match self.receive()? {
+            Response::Choice { id: reply_id, index } if reply_id == id && index < num_options => Ok(index),
+            Response::Choice { id: reply_id, index } => Err(anyhow!(
+                "Invalid choice {} for request {} ({} options)",
+                index,
+                reply_id,
+                num_options
+            )),
+            _ => Err(anyhow!("Expected a choice for request {}", id)),
+        }
+    }
+
+    /// Asks the other end to pick one of the options, returning the index of its pick
+    pub fn ask<T: Serialize>(&mut self, view: &PlayerView, options: &[T]) -> Result<usize> {
+        let encoded = options
+            .iter()
+            .map(|option| serde_json::to_value(option).unwrap())
+            .collect();
+        self.request_choice(view, encoded)
+    }
+
+    /// Asks the other end to pick one of the options, falling back to the first if it can't
+    pub fn choose<T: Serialize + Clone>(&mut self, view: &PlayerView, options: Vec<T>) -> T {
+        match self.ask(view, &options) {
+            Ok(index) => options[index].clone(),
+            Err(e) => {
+                warn!("Player {:?} failed to decide: {}", self.id, e);
+                options[0].clone()
+            }
+        }
+    }
+
+    pub fn observe(&mut self, event: &Event) {
+        if !self.is_open() {
+            return;
+        }
+        if let Err(e) = self.send(&Request::Event { event }) {
+            warn!("Unable to send event to player {:?}: {}", self.id, e);
+        }
+    }
+
+    /// Stops talking, which tells the other end the game is over
+    pub fn close(&mut self) {
+        self.writer = None;
+    }
+}
@@ -1,4 +1,9 @@
 pub mod dumb_player;
+pub mod external_player;
+pub mod honest_player;
 pub mod traits;
 pub mod human_player;
+pub mod link;
 pub mod random_player;
+pub mod remote_player;
+pub mod table_talk;
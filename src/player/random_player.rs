@@ -1,83 +1,181 @@
-use crate::{Action, GameState, Identity, PlayerID};
+use crate::view::PlayerView;
+use crate::{Action, GameRng, Identity, PlayerID};
 use crate::player::traits::Player;
-use anyhow::Result;
+use crate::seat::{self, Choice};
+use anyhow::{anyhow, Error, Result};
+use rand::distributions::{Distribution, WeightedIndex};
 use rand::seq::SliceRandom;
-use rand::thread_rng;
+use rand::SeedableRng;
+use std::str::FromStr;
 
+/// How likely a RandomPlayer is to pick each kind of choice. Every legal choice is weighted on
+/// its own, so an action with three possible targets is three times as likely as one with a
+/// single target. Card choices are always uniform.
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub struct RandomWeights {
+    pub income: u32,
+    pub foreign_aid: u32,
+    pub tax: u32,
+    pub exchange: u32,
+    pub steal: u32,
+    pub assassinate: u32,
+    pub coup: u32,
+    pub challenge: u32,
+    pub no_challenge: u32,
+    pub block: u32,
+    pub no_block: u32,
+}
+
+impl Default for RandomWeights {
+    fn default() -> Self {
+        RandomWeights {
+            income: 1,
+            foreign_aid: 1,
+            tax: 1,
+            exchange: 1,
+            steal: 1,
+            assassinate: 1,
+            coup: 1,
+            challenge: 1,
+            no_challenge: 1,
+            block: 1,
+            no_block: 1,
+        }
+    }
+}
+
+impl RandomWeights {
+    fn weight(&self, choice: &Choice) -> u32 {
+        match choice {
+            Choice::Action(Action::Income) => self.income,
+            Choice::Action(Action::ForeignAid) => self.foreign_aid,
+            Choice::Action(Action::Tax) => self.tax,
+            Choice::Action(Action::Exchange) => self.exchange,
+            Choice::Action(Action::Steal(_)) => self.steal,
+            Choice::Action(Action::Assassinate(_)) => self.assassinate,
+            Choice::Action(Action::Coup(_)) => self.coup,
+            Choice::Action(_) => 1,
+            Choice::Challenge(true) => self.challenge,
+            Choice::Challenge(false) => self.no_challenge,
+            Choice::Block(Some(_)) => self.block,
+            Choice::Block(None) => self.no_block,
+            Choice::ReplaceCard(_) | Choice::LoseCard(_) => 1,
+        }
+    }
+}
+
+/// Parses a comma separated list of overrides, e.g. "tax=3,challenge=0"
+impl FromStr for RandomWeights {
+    type Err = Error;
+
+    fn from_str(spec: &str) -> Result<Self> {
+        let mut weights = RandomWeights::default();
+        for entry in spec.split(',').filter(|entry| !entry.is_empty()) {
+            let mut parts = entry.splitn(2, '=');
+            let (name, value) = match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) => (name.trim(), value.trim()),
+                _ => return Err(anyhow!("Expected NAME=WEIGHT, got '{}'", entry)),
+            };
+            let value: u32 = value
+                .parse()
+                .map_err(|_| anyhow!("Weight for {} must be a whole number, got '{}'", name, value))?;
+            let weight = match name {
+                "income" => &mut weights.income,
+                "foreign_aid" => &mut weights.foreign_aid,
+                "tax" => &mut weights.tax,
+                "exchange" => &mut weights.exchange,
+                "steal" => &mut weights.steal,
+                "assassinate" => &mut weights.assassinate,
+                "coup" => &mut weights.coup,
+                "challenge" => &mut weights.challenge,
+                "no_challenge" => &mut weights.no_challenge,
+                "block" => &mut weights.block,
+                "no_block" => &mut weights.no_block,
+                _ => return Err(anyhow!("Unknown weight '{}'", name)),
+            };
+            *weight = value;
+        }
+        Ok(weights)
+    }
+}
+
+/// Picks among every legal choice at random. This is the baseline every other bot should beat.
 pub struct RandomPlayer {
-    // Not necessarily two?
     id: PlayerID,
     hand: Vec<Identity>,
+    weights: RandomWeights,
+    rng: GameRng,
 }
 
 impl RandomPlayer {
-    pub fn new(id: PlayerID) -> Self {
+    pub fn new(id: PlayerID, seed: u64) -> Self {
+        RandomPlayer::with_weights(id, RandomWeights::default(), seed)
+    }
+
+    pub fn with_weights(id: PlayerID, weights: RandomWeights, seed: u64) -> Self {
         let hand = Vec::new();
-        RandomPlayer { id, hand }
+        let rng = GameRng::seed_from_u64(seed);
+        RandomPlayer { id, hand, weights, rng }
     }
-    fn choose_random<T: Clone>(options: &mut [T] ) -> T {
-	let mut rng = rand::thread_rng();
-	options.shuffle(&mut rng);
-	return options[0].clone();
+
+    // Picks one of the legal answers to whatever the view is pending on
+    fn choose(&mut self, view: &PlayerView) -> Choice {
+        let choices = seat::legal_choices(view);
+        let weights: Vec<u32> = choices.iter().map(|choice| self.weights.weight(choice)).collect();
+        match WeightedIndex::new(&weights) {
+            Ok(distribution) => choices[distribution.sample(&mut self.rng)].clone(),
+            // Every legal choice was weighted out, so fall back to picking uniformly
+            Err(_) => choices
+                .choose(&mut self.rng)
+                .cloned()
+                .unwrap_or_else(|| seat::safe_default(view)),
+        }
     }
 }
 
 impl Player for RandomPlayer {
-    fn choose_action(&self, state: &GameState) -> Action {
-	// TODO -> Lazy static a rng --> Make a rand module
-	// let mut rng = rand::thread_rng();
-	// TODO -> Choose target randomly -> Then refactor choose_forced_coup
-	let target = self.choose_forced_coup(state);
-	let mut available_actions = vec!(Action::Income, Action::ForeignAid,  Action::Tax, Action::Steal(target.clone()));
-	let num_coins = self.count_coins(state);
-
-	// More constants
-	if num_coins >= 3 {
-	    available_actions.push(Action::Assassinate(target.clone()));
-	}
-
-	if num_coins >= 7 {
-	    available_actions.push(Action::Coup(target.clone()));
-	}
-
-	let num_actions = available_actions.len();
-	let mut options = &mut available_actions[0..num_actions];
-	return RandomPlayer::choose_random(options);
-    }
-    fn will_challenge(&self, _state: &GameState, _player_id: &PlayerID, _action: &Action) -> bool {
-        RandomPlayer::choose_random(&mut[false, true])
-    }
-    fn will_block(&self, _state: &GameState, player_id: &PlayerID, action: &Action) -> Option<Action> {
-	match action.blockable(player_id) {
-	    Some(options) => {
-		let num_actions = options.len();
-		let mut options : Vec<Option<Action>> = options.into_iter().map(|option| Some(option)).collect();
-		options.push(None);
-		RandomPlayer::choose_random(&mut options[0..num_actions + 1])
-	    },
-	    None => None
-	}	
+    fn choose_action(&mut self, view: &PlayerView) -> Action {
+        match self.choose(view) {
+            Choice::Action(action) => action,
+            choice => panic!("Expected an action, got {:?}", choice),
+        }
     }
+
+    fn will_challenge(&mut self, view: &PlayerView, _player_id: &PlayerID, _action: &Action) -> bool {
+        matches!(self.choose(view), Choice::Challenge(true))
+    }
+
+    fn will_block(&mut self, view: &PlayerView, _player_id: &PlayerID, _action: &Action) -> Option<Action> {
+        match self.choose(view) {
+            Choice::Block(block) => block,
+            _ => None,
+        }
+    }
+
     // Index in hand to replace
-    fn choose_card_to_replace(&self, _state: &GameState, _card: &Identity) -> Option<usize> {
-	RandomPlayer::choose_random(&mut [None, Some(0)])
+    fn choose_card_to_replace(&mut self, view: &PlayerView, _card: &Identity) -> Option<usize> {
+        match self.choose(view) {
+            Choice::ReplaceCard(index) => index,
+            _ => None,
+        }
     }
 
-    fn choose_card_to_lose(&self, _state: &GameState) -> usize {
-	0
+    fn choose_card_to_lose(&mut self, view: &PlayerView) -> usize {
+        match self.choose(view) {
+            Choice::LoseCard(index) => index,
+            _ => 0,
+        }
     }
 
-    fn choose_forced_coup(&self, state: &GameState) -> PlayerID {
-	let other_players = self.get_other_active_players(state);
-	if other_players.len() < 1 {
-	    panic!("No other players and I was told I have to coup!")
-	}
-	return other_players[0].clone();
+    fn choose_forced_coup(&mut self, view: &PlayerView) -> PlayerID {
+        match self.choose(view) {
+            Choice::Action(Action::Coup(target)) => target,
+            choice => panic!("Expected a coup, got {:?}", choice),
+        }
     }
 
-    
     fn get_hand(&self) -> Vec<Identity> {
-        self.hand.iter().cloned().collect()
+        self.hand.to_vec()
     }
 
     fn set_hand(&mut self, hand: Vec<Identity>) {
@@ -87,5 +185,73 @@ impl Player for RandomPlayer {
     fn who_am_i(&self) -> &PlayerID {
         &self.id
     }
-    
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::Rules;
+    use crate::view::PendingDecision;
+    use crate::{GameState, PlayerState};
+
+    #[test]
+    fn weights_are_read_from_overrides() {
+        let weights: RandomWeights = " tax = 3,challenge=0,,no_block=7".parse().unwrap();
+        assert_eq!(weights.tax, 3);
+        assert_eq!(weights.challenge, 0);
+        assert_eq!(weights.no_block, 7);
+        assert_eq!(weights.income, 1);
+        assert_eq!("".parse::<RandomWeights>().unwrap(), RandomWeights::default());
+
+        for spec in ["tax", "tax=", "tax=-1", "tax=1.5", "taxes=2", "=2"] {
+            assert!(spec.parse::<RandomWeights>().is_err(), "{} should be refused", spec);
+        }
+    }
+
+    // Seat 0's view of a table of three, where seat 2 is already out
+    fn table(coins: u8) -> GameState {
+        let seats = vec![PlayerID(0), PlayerID(1), PlayerID(2)];
+        let mut state = GameState::new(seats.clone(), Rules::default());
+        for id in seats {
+            state.player_states.insert(id.clone(), PlayerState::new(format!("Player {}", id.0), 2, 2));
+        }
+        state.player_states.get_mut(&PlayerID(0)).unwrap().num_coins = coins;
+        state.player_states.get_mut(&PlayerID(2)).unwrap().num_lives = 0;
+        state
+    }
+
+    #[test]
+    fn only_legal_choices_are_picked() {
+        let everything_off = "income=0,foreign_aid=0,tax=0,exchange=0,steal=0,assassinate=0,coup=0,\
+                              challenge=0,no_challenge=0,block=0,no_block=0";
+        let pending = [
+            PendingDecision::ChooseAction,
+            PendingDecision::ForcedCoup,
+            PendingDecision::Challenge { actor: PlayerID(1), action: Action::Tax },
+            PendingDecision::Block { actor: PlayerID(1), action: Action::Steal(PlayerID(0)) },
+            PendingDecision::LoseCard,
+            PendingDecision::ReplaceCard { card: Identity::Duke },
+        ];
+        for spec in ["", "coup=0,assassinate=9,challenge=5", everything_off] {
+            let mut player = RandomPlayer::with_weights(PlayerID(0), spec.parse().unwrap(), 7);
+            for coins in [0, 3, 7, 10] {
+                let state = table(coins);
+                for pending in &pending {
+                    let hand = vec![Identity::Captain, Identity::Contessa];
+                    let view = PlayerView::new(&state, &PlayerID(0), hand, 9, pending.clone());
+                    let legal = seat::legal_choices(&view);
+                    for _ in 0..50 {
+                        let choice = seat::decide(&mut player, &view);
+                        assert!(
+                            legal.contains(&choice),
+                            "Weights '{}' picked {:?} for {:?}",
+                            spec,
+                            choice,
+                            pending
+                        );
+                    }
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,241 @@
+//! A player at another machine, connected over TCP or a WebSocket.
+//!
+//! The client at the other end speaks the same protocol as external bots (see
+//! `external_player`), and so only ever hears about its own hand and the events its seat is
+//! allowed to see. A client that drops its connection, or goes quiet for longer than its
+//! connection's timeout, gets the safe option for every decision from then on, unless the seat
+//! has a session.
+//!
+//! A seat with a session is greeted with a token the client can use to get back to it over a
+//! new connection, and is sent everything it has seen so far when it does. Decisions wait for
+//! the client for a grace period after its connection drops, and then a stand-in bot makes them
+//! until the client is back.
+
+use crate::event::Event;
+use crate::player::link::Link;
+use crate::player::traits::Player;
+use crate::view::PlayerView;
+use crate::{Action, Identity, PlayerID};
+use anyhow::Result;
+use log::warn;
+use serde::Serialize;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant};
+
+/// Both ends of a connection carrying the protocol a line at a time
+pub type Connection = (Box<dyn BufRead + Send>, Box<dyn Write + Send>);
+
+pub struct RemotePlayer {
+    id: PlayerID,
+    name: String,
+    hand: Vec<Identity>,
+    link: Link,
+    session: Option<Session>,
+}
+
+/// What lets a client get back to its seat
+pub struct Session {
+    token: String,
+    connections: Receiver<Connection>,
+    grace: Duration,
+    stand_in: Box<dyn Player>,
+    // When the client's connection was found to have dropped
+    dropped: Option<Instant>,
+    // Everything the seat has been told, to bring a new connection up to date
+    history: Vec<Event>,
+}
+
+impl Session {
+    /// A session with a new token. Connections sent over the returned sender take over the
+    /// seat. The stand-in makes the decisions once the client has been gone for longer than the
+    /// grace period.
+    pub fn new(grace: Duration, stand_in: Box<dyn Player>) -> (Self, Sender<Connection>) {
+        let (sender, connections) = mpsc::channel();
+        let session = Session {
+            token: format!("{:032x}", rand::random::<u128>()),
+            connections,
+            grace,
+            stand_in,
+            dropped: None,
+            history: Vec::new(),
+        };
+        (session, sender)
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    // The client's next connection, waiting out what's left of the grace period if it's gone
+    fn next_connection(&mut self, open: bool) -> Option<Connection> {
+        if open {
+            return self.connections.try_recv().ok();
+        }
+        let dropped = *self.dropped.get_or_insert_with(Instant::now);
+        match self.grace.checked_sub(dropped.elapsed()) {
+            Some(remaining) => self.connections.recv_timeout(remaining).ok(),
+            None => self.connections.try_recv().ok(),
+        }
+    }
+}
+
+impl RemotePlayer {
+    /// Greets a client that has just connected. Returns the player, and the name the client
+    /// gave or the one offered if it didn't give one. A client that takes longer than the
+    /// timeout to answer is taken to have dropped.
+    pub fn connect(
+        id: PlayerID,
+        name: &str,
+        stream: TcpStream,
+        timeout: Duration,
+        session: Option<Session>,
+    ) -> Result<(Self, String)> {
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        let reader = BufReader::new(stream.try_clone()?);
+        RemotePlayer::greet(id, name, (Box::new(reader), Box::new(stream)), session)
+    }
+
+    /// Greets a client whose seat has a session, handing it the session's token
+    pub fn with_session(id: PlayerID, name: &str, connection: Connection, session: Session) -> Result<(Self, String)> {
+        RemotePlayer::greet(id, name, connection, Some(session))
+    }
+
+    fn greet(id: PlayerID, name: &str, (reader, writer): Connection, session: Option<Session>) -> Result<(Self, String)> {
+        let mut link = Link::new(id.clone(), reader, writer);
+        let token = session.as_ref().map(Session::token);
+        let name = link.handshake(name, token)?.unwrap_or_else(|| name.to_string());
+        let player = RemotePlayer {
+            id,
+            name: name.clone(),
+            hand: Vec::new(),
+            link,
+            session,
+        };
+        Ok((player, name))
+    }
+
+    // The stand-in, if it's the one to decide: the client is gone, and didn't get back within
+    // the grace period. Takes any new connection the client has made first.
+    fn stand_in(&mut self) -> Option<&mut dyn Player> {
+        let session = self.session.as_mut()?;
+        while let Some((reader, writer)) = session.next_connection(self.link.is_open()) {
+            let mut link = Link::new(self.id.clone(), reader, writer);
+            match resync(&mut link, &self.name, session) {
+                Ok(()) => {
+                    log::info!("{} is back", self.name);
+                    self.link = link;
+                    session.dropped = None;
+                }
+                Err(e) => warn!("{} was unable to get back to their seat: {}", self.name, e),
+            }
+        }
+        if self.link.is_open() {
+            return None;
+        }
+        Some(session.stand_in.as_mut())
+    }
+
+    // Asks the client, or the stand-in when it's the one to decide
+    fn decide<T: Serialize + Clone>(
+        &mut self,
+        view: &PlayerView,
+        options: Vec<T>,
+        stand_in: impl Fn(&mut dyn Player) -> T,
+    ) -> T {
+        loop {
+            if let Some(player) = self.stand_in() {
+                return stand_in(player);
+            }
+            match self.link.ask(view, &options) {
+                Ok(index) => return options[index].clone(),
+                // Asked again, of whoever decides now the connection has dropped
+                Err(_) if self.session.is_some() && !self.link.is_open() => continue,
+                Err(e) => {
+                    warn!("Player {:?} failed to decide: {}", self.id, e);
+                    return options[0].clone();
+                }
+            }
+        }
+    }
+
+    fn hand_indexes(&self) -> Vec<usize> {
+        (0..self.hand.len()).collect()
+    }
+}
+
+// Greets a new connection as the seat again, and tells it everything it missed
+fn resync(link: &mut Link, name: &str, session: &Session) -> Result<()> {
+    link.handshake(name, Some(&session.token))?;
+    for event in &session.history {
+        link.observe(event);
+    }
+    Ok(())
+}
+
+impl Player for RemotePlayer {
+    fn choose_action(&mut self, view: &PlayerView) -> Action {
+        let options = self.get_available_actions(view);
+        self.decide(view, options, |player| player.choose_action(view))
+    }
+
+    fn will_challenge(&mut self, view: &PlayerView, player_id: &PlayerID, action: &Action) -> bool {
+        self.decide(view, vec![false, true], |player| {
+            player.will_challenge(view, player_id, action)
+        })
+    }
+
+    fn will_block(&mut self, view: &PlayerView, player_id: &PlayerID, action: &Action) -> Option<Action> {
+        let mut options = vec![None];
+        if let Some(blocks) = action.blockable(self.who_am_i()) {
+            options.extend(blocks.into_iter().map(Some));
+        }
+        self.decide(view, options, |player| player.will_block(view, player_id, action))
+    }
+
+    fn choose_card_to_replace(&mut self, view: &PlayerView, card: &Identity) -> Option<usize> {
+        let mut options = vec![None];
+        options.extend(self.hand_indexes().into_iter().map(Some));
+        self.decide(view, options, |player| player.choose_card_to_replace(view, card))
+    }
+
+    fn choose_card_to_lose(&mut self, view: &PlayerView) -> usize {
+        let options = self.hand_indexes();
+        self.decide(view, options, |player| player.choose_card_to_lose(view))
+    }
+
+    fn choose_forced_coup(&mut self, view: &PlayerView) -> PlayerID {
+        let options = self.get_other_active_players(view);
+        self.decide(view, options, |player| player.choose_forced_coup(view))
+    }
+
+    fn observe(&mut self, event: &Event) {
+        if let Some(session) = &mut self.session {
+            session.history.push(event.clone());
+            session.stand_in.observe(event);
+        }
+        self.link.observe(event);
+    }
+
+    fn talk(&mut self) -> Vec<String> {
+        self.link.take_said()
+    }
+
+    fn get_hand(&self) -> Vec<Identity> {
+        self.hand.to_vec()
+    }
+
+    fn set_hand(&mut self, hand: Vec<Identity>) {
+        if let Some(session) = &mut self.session {
+            session.stand_in.set_hand(hand.clone());
+        }
+        self.hand = hand;
+    }
+
+    fn who_am_i(&self) -> &PlayerID {
+        &self.id
+    }
+}
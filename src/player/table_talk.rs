@@ -0,0 +1,195 @@
+//! Gives a bot something to say. The bot underneath makes every decision as it always would,
+//! and the lines are canned ones picked from what it decided and what happened to it, so a game
+//! with talk plays out just like the same game without.
+
+use crate::event::Event;
+use crate::player::traits::Player;
+use crate::prompter::{LocalPrompter, Prompter};
+use crate::view::{PlayerNames, PlayerView};
+use crate::{Action, Identity, PlayerID};
+use structopt::clap::arg_enum;
+
+arg_enum! {
+/// What a bot says at the table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Talk {
+    // Trash talk about its own moves and how things turned out
+    Taunts,
+    // Why it did what it did, which gives away its hand
+    Reasoning,
+}
+}
+
+const ACTION_TAUNTS: &[&str] = &[
+    "Try and stop me.",
+    "Read it and weep.",
+    "You're all playing for second.",
+    "Too easy.",
+];
+const CHALLENGE_TAUNTS: &[&str] = &["Let's see it, {}.", "Nice try, {}.", "I don't buy it, {}."];
+const BLOCK_TAUNTS: &[&str] = &["Not so fast, {}.", "Not today, {}.", "Hands off, {}."];
+
+pub struct TableTalk {
+    player: Box<dyn Player>,
+    talk: Talk,
+    said: Vec<String>,
+    // How many taunts have been made, so they take turns
+    taunts: usize,
+}
+
+impl TableTalk {
+    pub fn new(player: Box<dyn Player>, talk: Talk) -> Self {
+        TableTalk {
+            player,
+            talk,
+            said: Vec::new(),
+            taunts: 0,
+        }
+    }
+
+    fn say(&mut self, line: String) {
+        self.said.push(line);
+    }
+
+    fn taunt(&mut self, lines: &[&str], name: &str) {
+        let line = lines[self.taunts % lines.len()].replace("{}", name);
+        self.taunts += 1;
+        self.say(line);
+    }
+
+    fn holds(&self, identity: Identity) -> bool {
+        self.player.get_hand().contains(&identity)
+    }
+
+    // Why the bot claimed what it did, or didn't claim anything
+    fn explain_claim(&self, action: &Action, view: &PlayerView) -> String {
+        let shown = LocalPrompter::display_action(view, action.clone());
+        match action.claimed_identity() {
+            Some(identity) if self.holds(identity) => format!("I have the {:?}, so {} it is.", identity, shown),
+            Some(identity) => format!("No {:?} in my hand, but {} is worth the bluff.", identity, shown),
+            None => format!("{} needs no card, so nobody can call it.", shown),
+        }
+    }
+}
+
+impl Player for TableTalk {
+    fn choose_action(&mut self, view: &PlayerView) -> Action {
+        let action = self.player.choose_action(view);
+        match self.talk {
+            Talk::Reasoning => {
+                let line = self.explain_claim(&action, view);
+                self.say(line);
+            }
+            Talk::Taunts => {
+                if let Action::Assassinate(target) | Action::Coup(target) | Action::Steal(target) = &action {
+                    let name = view.get_player_name(target);
+                    self.say(format!("Sorry, {}.", name));
+                } else if action == Action::Tax {
+                    self.taunt(ACTION_TAUNTS, "");
+                }
+            }
+        }
+        action
+    }
+
+    fn will_challenge(&mut self, view: &PlayerView, player_id: &PlayerID, action: &Action) -> bool {
+        let challenge = self.player.will_challenge(view, player_id, action);
+        if challenge {
+            let name = view.get_player_name(player_id);
+            match (self.talk, action.claimed_identity()) {
+                (Talk::Reasoning, Some(identity)) => {
+                    self.say(format!("I don't think {} has the {:?}.", name, identity))
+                }
+                (Talk::Reasoning, None) => {}
+                (Talk::Taunts, _) => self.taunt(CHALLENGE_TAUNTS, &name),
+            }
+        }
+        challenge
+    }
+
+    fn will_block(&mut self, view: &PlayerView, player_id: &PlayerID, action: &Action) -> Option<Action> {
+        let block = self.player.will_block(view, player_id, action);
+        if let Some(block) = &block {
+            match self.talk {
+                Talk::Reasoning => {
+                    let line = self.explain_claim(block, view);
+                    self.say(line);
+                }
+                Talk::Taunts => {
+                    let name = view.get_player_name(player_id);
+                    self.taunt(BLOCK_TAUNTS, &name);
+                }
+            }
+        }
+        block
+    }
+
+    fn choose_card_to_replace(&mut self, view: &PlayerView, card: &Identity) -> Option<usize> {
+        let replace = self.player.choose_card_to_replace(view, card);
+        if let (Talk::Reasoning, Some(index)) = (self.talk, replace) {
+            // The game swaps in the safe choice for an index out of range, so there's nothing to say
+            if let Some(old) = self.player.get_hand().get(index) {
+                self.say(format!("Swapping my {:?} for the {:?}.", old, card));
+            }
+        }
+        replace
+    }
+
+    fn choose_card_to_lose(&mut self, view: &PlayerView) -> usize {
+        let index = self.player.choose_card_to_lose(view);
+        match self.talk {
+            Talk::Reasoning => {
+                if let Some(card) = self.player.get_hand().get(index) {
+                    self.say(format!("The {:?} is the one I can best do without.", card));
+                }
+            }
+            Talk::Taunts if view.player(&view.me).num_lives > 1 => self.say("Just a scratch.".to_string()),
+            Talk::Taunts => self.say("Well played.".to_string()),
+        }
+        index
+    }
+
+    fn choose_forced_coup(&mut self, view: &PlayerView) -> PlayerID {
+        let target = self.player.choose_forced_coup(view);
+        let name = view.get_player_name(&target);
+        match self.talk {
+            Talk::Reasoning => self.say(format!("I have to coup, and {} is the one to stop.", name)),
+            Talk::Taunts => self.say(format!("Nothing personal, {}.", name)),
+        }
+        target
+    }
+
+    fn observe(&mut self, event: &Event) {
+        let me = self.who_am_i().clone();
+        if self.talk == Talk::Taunts {
+            match event {
+                Event::ChallengeResolved { challenger, challenged, challenger_won, .. }
+                    if (challenger == &me && *challenger_won) || (challenged == &me && !*challenger_won) =>
+                {
+                    self.say("Never doubt me.".to_string())
+                }
+                Event::PlayerEliminated { player } if player != &me => self.say("Next.".to_string()),
+                _ => {}
+            }
+        }
+        self.player.observe(event);
+    }
+
+    fn talk(&mut self) -> Vec<String> {
+        let mut said = std::mem::take(&mut self.said);
+        said.extend(self.player.talk());
+        said
+    }
+
+    fn get_hand(&self) -> Vec<Identity> {
+        self.player.get_hand()
+    }
+
+    fn set_hand(&mut self, hand: Vec<Identity>) {
+        self.player.set_hand(hand);
+    }
+
+    fn who_am_i(&self) -> &PlayerID {
+        self.player.who_am_i()
+    }
+}
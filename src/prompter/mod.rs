@@ -1,15 +1,18 @@
+pub mod tui;
+
 use crate::action::Action;
-use crate::{GameState, Identity};
+use crate::view::{PlayerNames, PlayerView};
 use anyhow::{anyhow, Result};
+use std::cell::RefCell;
 use std::fmt::Display;
 use std::io::{stdin, stdout, Write};
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 
 // Defines a temporary struct used to model a players state when prompted
 #[derive(Clone)]
 pub struct PromptInfo<'a> {
-    pub state: &'a GameState,
-    pub player_hand: Vec<Identity>,
+    pub view: &'a PlayerView<'a>,
 }
 
 const YES: &[&str] = &[
@@ -58,27 +61,45 @@ pub trait Prompter {
         prompt_info: PromptInfo,
     ) -> Result<Action>;
     fn prompt_player_yes_no(&self, question: &str, prompt_info: Option<PromptInfo>) -> Result<bool>;
+    /// Everything the player has said to the table since the last time
+    fn take_said(&self) -> Vec<String> {
+        Vec::new()
+    }
     // TODO --> Decide where you want thi
     // Should this just be on game state? Or a utility function?
-    fn display_action(state: &GameState, action: Action) -> String {
+    fn display_action(names: &impl PlayerNames, action: Action) -> String {
         match action.clone() {
             Action::Assassinate(target) | Action::Coup(target) => {
-                format!("{} {}", action.clone(), state.get_player_name(&target))
+                format!("{} {}", action.clone(), names.get_player_name(&target))
             }
-            Action::Steal(target) => format!("{} from {}", action.clone(), state.get_player_name(&target)),
+            Action::Steal(target) => format!("{} from {}", action.clone(), names.get_player_name(&target)),
             _ => format!("{}", action),
         }
     }
 }
 
+// Public events shown to a player when the terminal is handed to them
+const HOT_SEAT_LOG: usize = 20;
+
+/// Who has the terminal, for players taking turns at one. Every local prompter at the terminal
+/// holds a clone.
+#[derive(Clone, Default)]
+pub struct HotSeat(Arc<Mutex<Option<String>>>);
+
 pub struct LocalPrompter {
-    player_name: String
+    player_name: String,
+    // Lines said while being prompted, until the game hears them
+    said: RefCell<Vec<String>>,
+    // Set when the terminal is shared, so each question is only seen by who it's for
+    hot_seat: Option<HotSeat>,
 }
 
 impl LocalPrompter {
     pub fn new() -> Self {
         LocalPrompter {
-	    player_name: "".to_string()
+	    player_name: "".to_string(),
+	    said: RefCell::new(Vec::new()),
+	    hot_seat: None,
 	}
     }
 
@@ -86,6 +107,40 @@ impl LocalPrompter {
 	self.player_name = player_name;
     }
 
+    /// Shares the terminal with the other players holding the same hot seat
+    pub fn set_hot_seat(&mut self, hot_seat: HotSeat) {
+	self.hot_seat = Some(hot_seat);
+    }
+
+    // Makes sure the terminal is in this player's hands before they're asked anything. Whoever
+    // had it before has their questions and hand cleared away, and the player is brought up to
+    // date with the public log and shown their own hand.
+    fn hand_over(&self, view: Option<&PlayerView>) -> Result<()> {
+	let hot_seat = match &self.hot_seat {
+	    Some(hot_seat) => hot_seat,
+	    None => return Ok(()),
+	};
+	let mut holder = hot_seat.0.lock().unwrap();
+	if holder.as_deref() == Some(self.player_name.as_str()) {
+	    return Ok(());
+	}
+	clear_screen();
+	println!("Pass to {}, press enter", self.player_name);
+	self.get_response()?;
+	*holder = Some(self.player_name.clone());
+	clear_screen();
+	if let Some(view) = view {
+	    let skip = view.history.len().saturating_sub(HOT_SEAT_LOG);
+	    for event in &view.history[skip..] {
+		println!("{}", event.describe(view));
+	    }
+	    println!();
+	    let hand: Vec<String> = view.hand.iter().map(|card| format!("{:?}", card)).collect();
+	    println!("{}, your hand is [ {} ]", self.player_name, hand.join(" "));
+	}
+	Ok(())
+    }
+
     fn get_response(&self) -> Result<String> {
         let mut response = String::new();
         let _ = stdout().flush();
@@ -108,18 +163,27 @@ impl Prompter for LocalPrompter {
         // TODO flip this?
         match response {
             Ok(response) => {
-                let response_value = &response.to_lowercase()[0..response.len()];
+                let response_value = response.to_lowercase();
                 match prompt_info {
 		    // TODO --> Make this an enum / generic!
-                    Some(prompt_value) => match response_value {
+                    Some(prompt_value) => match response_value.as_str() {
+			_ if response_value.starts_with("say ") => {
+			    self.said.borrow_mut().push(response[4..].to_string());
+			    self.prompt_player(Some(prompt_value))
+			},
                         "show" => {
-			    let state_value = prompt_value.state;
-                            println!("{}", state_value);
+                            println!("{}", prompt_value.view);
                             self.prompt_player(Some(prompt_value))
                         },
+			"history" => {
+			    for event in prompt_value.view.history.iter() {
+				println!("{}", event.describe(prompt_value.view));
+			    }
+			    self.prompt_player(Some(prompt_value))
+			},
 			"hand" => {
 			    print!("Hand: [ ");
-			    for card in &prompt_value.player_hand {
+			    for card in &prompt_value.view.hand {
 				print!("{:?} ", card);
 			    }
 			    println!("]");
@@ -131,6 +195,8 @@ impl Prompter for LocalPrompter {
 			    println!("You can use the following commands:");
 			    println!("\t'show' => See the state of the game");
 			    println!("\t'hand' => See your hand");
+			    println!("\t'history' => See everything that has happened so far");
+			    println!("\t'say <message>' => Say something to the table");
 			    println!("\t'help' => Show this message");
 			    self.prompt_player(Some(prompt_value))
 			}
@@ -151,6 +217,7 @@ impl Prompter for LocalPrompter {
         possible_choices: Vec<T>,
 	prompt_info: Option<PromptInfo>
     ) -> Result<usize> {
+        self.hand_over(prompt_info.as_ref().map(|info| info.view))?;
         println!("{}", question);
         print!("Choices are: [");
         for (idx, choice) in possible_choices.iter().enumerate() {
@@ -160,7 +227,7 @@ impl Prompter for LocalPrompter {
         match self.prompt_player(prompt_info) {
             Ok(response) => match usize::from_str(&response) {
                 Ok(response) => Ok(response),
-                Err(e) => Err(anyhow!("Unable to convert {} ", response)),
+                Err(_) => Err(anyhow!("Unable to convert {} ", response)),
             },
             Err(e) => Err(e),
         }
@@ -173,17 +240,18 @@ impl Prompter for LocalPrompter {
         possible_choices: Vec<Action>,
 	prompt_info: PromptInfo
     ) -> Result<Action> {
-	let state = prompt_info.state;
+	let view = prompt_info.view;
+	self.hand_over(Some(view))?;
 	println!("{}: {}", self.player_name, question);
         println!("Choices are: [");
         for (idx, choice) in possible_choices.iter().enumerate() {
-            println!("\t{} => {}", idx, LocalPrompter::display_action(state, choice.clone()));
+            println!("\t{} => {}", idx, LocalPrompter::display_action(view, choice.clone()));
         }
         println!(" ]");
         let choice = match self.prompt_player(Some(prompt_info)) {
             Ok(response) => match usize::from_str(&response) {
                 Ok(response) => Ok(response),
-                Err(e) => Err(anyhow!("Unable to convert {} ", response)),
+                Err(_) => Err(anyhow!("Unable to convert {} ", response)),
             },
             Err(e) => Err(e),
         };
@@ -195,20 +263,31 @@ impl Prompter for LocalPrompter {
         }
     }
     fn prompt_player_yes_no(&self, question: &str, prompt_info: Option<PromptInfo>) -> Result<bool> {
+        self.hand_over(prompt_info.as_ref().map(|info| info.view))?;
         println!("{}: {} (y/n)", self.player_name, question);
         let choice = self.prompt_player(prompt_info);
         match choice {
             Ok(choice) => {
-                let choice_value = &choice[0..choice.len()];
-                if YES.to_vec().contains(&choice_value) {
+                let choice_value = choice.as_str();
+                if YES.contains(&choice_value) {
                     Ok(true)
-                } else if NO.to_vec().contains(&choice_value) {
+                } else if NO.contains(&choice_value) {
                     Ok(false)
                 } else {
                     panic!("No response!")
                 }
             }
-            Err(e) => panic!("oh god"),
+            Err(_) => panic!("oh god"),
         }
     }
+
+    fn take_said(&self) -> Vec<String> {
+        self.said.borrow_mut().drain(..).collect()
+    }
+}
+
+// Wipes the terminal and puts the cursor back at the top
+fn clear_screen() {
+    print!("\x1B[2J\x1B[H");
+    let _ = stdout().flush();
 }
@@ -0,0 +1,310 @@
+//! A full-screen terminal prompter.
+//!
+//! The screen shows every seat's coins, influence and revealed cards, your own hand, the event
+//! log and the question being asked. Choices are picked with the arrow keys (or j/k) and Enter,
+//! by their number, or by the first letter of their name. A letter shared by several choices
+//! moves between them instead. `/` says something to the table, PageUp and PageDown scroll the
+//! log, and Ctrl-C leaves the game.
+//!
+//! The screen is only redrawn while a question is being asked, so the log catches up with
+//! everything that happened in between the next time it's your turn to decide something.
+
+use super::{PromptInfo, Prompter};
+use crate::action::Action;
+use crate::view::PlayerView;
+use anyhow::{anyhow, Result};
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::event::{self, Event as TermEvent, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Row, Table};
+use ratatui::{Frame, Terminal};
+use std::cell::{Cell, RefCell};
+use std::fmt::Display;
+use std::io::{stdout, Stdout};
+
+const HELP: &str = "↑/↓ move · Enter choose · 1-9 or first letter pick · / chat · PgUp/PgDn scroll log · Ctrl-C quit";
+
+/// What the prompt at the bottom of the screen is waiting for
+enum Input<'a> {
+    Menu { labels: &'a [String], state: ListState },
+    Text(String),
+}
+
+pub struct TuiPrompter {
+    player_name: String,
+    terminal: RefCell<Terminal<CrosstermBackend<Stdout>>>,
+    // Events scrolled back from the newest
+    scroll: Cell<usize>,
+    // Lines said while choosing, until the game hears them
+    said: RefCell<Vec<String>>,
+}
+
+impl TuiPrompter {
+    /// Takes over the terminal until the prompter is dropped
+    pub fn new() -> Result<Self> {
+        enable_raw_mode()?;
+        execute!(stdout(), EnterAlternateScreen)?;
+        let terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
+        Ok(TuiPrompter {
+            player_name: "".to_string(),
+            terminal: RefCell::new(terminal),
+            scroll: Cell::new(0),
+            said: RefCell::new(Vec::new()),
+        })
+    }
+
+    pub fn set_name(&mut self, player_name: String) {
+        self.player_name = player_name;
+    }
+
+    /// Asks for a line of text
+    pub fn prompt_text(&self, question: &str, view: Option<&PlayerView>) -> Result<String> {
+        loop {
+            if let Some(text) = self.edit(question, view)? {
+                return Ok(text);
+            }
+        }
+    }
+
+    // Reads a line of text, or None if Esc is pressed
+    fn edit(&self, question: &str, view: Option<&PlayerView>) -> Result<Option<String>> {
+        let mut input = Input::Text(String::new());
+        loop {
+            self.draw(question, &input, view)?;
+            let key = match self.next_key()? {
+                Some(key) => key,
+                None => continue,
+            };
+            if let Input::Text(text) = &mut input {
+                match key.code {
+                    KeyCode::Enter if !text.trim().is_empty() => return Ok(Some(text.trim().to_string())),
+                    KeyCode::Esc => return Ok(None),
+                    KeyCode::Backspace => {
+                        text.pop();
+                    }
+                    KeyCode::Char(c) => text.push(c),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    // Shows the choices and waits until one is picked, returning its index
+    fn select(&self, question: &str, labels: &[String], view: Option<&PlayerView>) -> Result<usize> {
+        if labels.is_empty() {
+            return Err(anyhow!("Nothing to choose from"));
+        }
+        let mut state = ListState::default();
+        state.select(Some(0));
+        let mut input = Input::Menu { labels, state };
+        loop {
+            self.draw(question, &input, view)?;
+            let key = match self.next_key()? {
+                Some(key) => key,
+                None => continue,
+            };
+            let state = match &mut input {
+                Input::Menu { state, .. } => state,
+                Input::Text(_) => unreachable!(),
+            };
+            let selected = state.selected().unwrap_or(0);
+            match key.code {
+                KeyCode::Up | KeyCode::Char('k') => state.select(Some((selected + labels.len() - 1) % labels.len())),
+                KeyCode::Down | KeyCode::Char('j') => state.select(Some((selected + 1) % labels.len())),
+                KeyCode::Enter | KeyCode::Char(' ') => return Ok(selected),
+                KeyCode::Char('/') => {
+                    if let Some(message) = self.edit("Say something (Esc to cancel)", view)? {
+                        self.said.borrow_mut().push(message);
+                    }
+                }
+                KeyCode::Char(c) if c.is_ascii_digit() => match c.to_digit(10) {
+                    Some(number) if number >= 1 && number as usize <= labels.len() => return Ok(number as usize - 1),
+                    _ => {}
+                },
+                KeyCode::Char(c) => {
+                    let matches: Vec<usize> = (0..labels.len()).filter(|&i| hotkey(&labels[i]) == Some(c)).collect();
+                    match matches.as_slice() {
+                        [] => {}
+                        [only] => return Ok(*only),
+                        _ => {
+                            let next = matches.iter().find(|&&i| i > selected).unwrap_or(&matches[0]);
+                            state.select(Some(*next));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Waits for a key press, dealing with scrolling and quitting itself. None means the screen
+    // just needs drawing again.
+    fn next_key(&self) -> Result<Option<KeyEvent>> {
+        let key = match event::read()? {
+            TermEvent::Key(key) if key.kind == KeyEventKind::Press => key,
+            _ => return Ok(None),
+        };
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.restore();
+                std::process::exit(130);
+            }
+            KeyCode::PageUp => self.scroll.set(self.scroll.get() + 5),
+            KeyCode::PageDown => self.scroll.set(self.scroll.get().saturating_sub(5)),
+            _ => return Ok(Some(key)),
+        }
+        Ok(None)
+    }
+
+    fn draw(&self, question: &str, input: &Input, view: Option<&PlayerView>) -> Result<()> {
+        let mut terminal = self.terminal.borrow_mut();
+        let scroll = &self.scroll;
+        terminal.draw(|frame| {
+            let prompt_height = match input {
+                Input::Menu { labels, .. } => labels.len() as u16 + 2,
+                Input::Text(_) => 3,
+            };
+            let [board, prompt, help] = Layout::vertical([
+                Constraint::Min(0),
+                Constraint::Length(prompt_height),
+                Constraint::Length(1),
+            ])
+            .areas(frame.area());
+            if let Some(view) = view {
+                draw_board(frame, board, view, scroll);
+            }
+            draw_prompt(frame, prompt, question, input);
+            frame.render_widget(Paragraph::new(HELP).style(Style::default().add_modifier(Modifier::DIM)), help);
+        })?;
+        Ok(())
+    }
+
+    fn restore(&self) {
+        let _ = disable_raw_mode();
+        let _ = execute!(stdout(), LeaveAlternateScreen);
+        let _ = self.terminal.borrow_mut().show_cursor();
+    }
+}
+
+impl Drop for TuiPrompter {
+    fn drop(&mut self) {
+        self.restore();
+    }
+}
+
+// The letter that picks a choice: the first letter of its name
+fn hotkey(label: &str) -> Option<char> {
+    label.chars().next().map(|c| c.to_ascii_lowercase())
+}
+
+fn draw_board(frame: &mut Frame, area: Rect, view: &PlayerView, scroll: &Cell<usize>) {
+    let [seats, rest] =
+        Layout::vertical([Constraint::Length(view.players.len() as u16 + 3), Constraint::Min(0)]).areas(area);
+    let [hand, log] = Layout::horizontal([Constraint::Length(20), Constraint::Min(0)]).areas(rest);
+
+    let rows = view.players.iter().map(|player| {
+        let mut style = Style::default();
+        if player.id == view.me {
+            style = style.add_modifier(Modifier::BOLD);
+        }
+        if !player.is_alive() {
+            style = style.add_modifier(Modifier::DIM | Modifier::CROSSED_OUT);
+        }
+        let revealed: Vec<String> = player.revealed.iter().map(|identity| format!("{:?}", identity)).collect();
+        Row::new(vec![
+            player.name.clone(),
+            player.coins.to_string(),
+            player.num_lives.to_string(),
+            revealed.join(", "),
+        ])
+        .style(style)
+    });
+    let widths = [
+        Constraint::Length(16),
+        Constraint::Length(6),
+        Constraint::Length(10),
+        Constraint::Min(0),
+    ];
+    let table = Table::new(rows, widths)
+        .header(Row::new(vec!["Player", "Coins", "Influence", "Revealed"]).style(Style::default().add_modifier(Modifier::UNDERLINED)))
+        .block(Block::bordered().title(format!(" Turn {} · {} cards in the deck ", view.turn, view.deck_size)));
+    frame.render_widget(table, seats);
+
+    let cards: Vec<ListItem> = view.hand.iter().map(|identity| ListItem::new(format!("{:?}", identity))).collect();
+    frame.render_widget(List::new(cards).block(Block::bordered().title(" Your hand ")), hand);
+
+    // Newest events at the bottom, scrolled back no further than the first
+    let events: Vec<String> = view.history.iter().map(|event| event.describe(view)).collect();
+    let height = log.height.saturating_sub(2) as usize;
+    let back = scroll.get().min(events.len().saturating_sub(height));
+    scroll.set(back);
+    let end = events.len() - back;
+    let lines: Vec<Line> = events[end.saturating_sub(height)..end].iter().map(|event| Line::from(event.as_str())).collect();
+    let title = if back > 0 { format!(" Log ({} back) ", back) } else { " Log ".to_string() };
+    frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(title)), log);
+}
+
+fn draw_prompt(frame: &mut Frame, area: Rect, question: &str, input: &Input) {
+    let block = Block::bordered().title(format!(" {} ", question));
+    match input {
+        Input::Menu { labels, state } => {
+            let items: Vec<ListItem> = labels
+                .iter()
+                .enumerate()
+                .map(|(i, label)| ListItem::new(format!("{}. {}", i + 1, label)))
+                .collect();
+            let list = List::new(items)
+                .block(block)
+                .highlight_symbol("> ")
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+            frame.render_stateful_widget(list, area, &mut state.clone());
+        }
+        Input::Text(text) => frame.render_widget(Paragraph::new(format!("{}█", text)).block(block), area),
+    }
+}
+
+impl Prompter for TuiPrompter {
+    fn prompt_player(&self, prompt_info: Option<PromptInfo>) -> Result<String> {
+        self.prompt_text("Your answer", prompt_info.map(|info| info.view))
+    }
+
+    fn prompt_player_choice<T: Display + Clone>(
+        &self,
+        question: &str,
+        possible_choices: Vec<T>,
+        prompt_info: Option<PromptInfo>,
+    ) -> Result<usize> {
+        let labels: Vec<String> = possible_choices.iter().map(|choice| choice.to_string()).collect();
+        self.select(&format!("{}: {}", self.player_name, question), &labels, prompt_info.map(|info| info.view))
+    }
+
+    fn prompt_player_for_action(
+        &self,
+        question: &str,
+        possible_actions: Vec<Action>,
+        prompt_info: PromptInfo,
+    ) -> Result<Action> {
+        let view = prompt_info.view;
+        let labels: Vec<String> = possible_actions
+            .iter()
+            .map(|action| TuiPrompter::display_action(view, action.clone()))
+            .collect();
+        let index = self.select(&format!("{}: {}", self.player_name, question), &labels, Some(view))?;
+        Ok(possible_actions[index].clone())
+    }
+
+    fn prompt_player_yes_no(&self, question: &str, prompt_info: Option<PromptInfo>) -> Result<bool> {
+        let labels = ["Yes".to_string(), "No".to_string()];
+        let index = self.select(&format!("{}: {}", self.player_name, question), &labels, prompt_info.map(|info| info.view))?;
+        Ok(index == 0)
+    }
+
+    fn take_said(&self) -> Vec<String> {
+        self.said.borrow_mut().drain(..).collect()
+    }
+}
+
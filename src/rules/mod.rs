@@ -0,0 +1,204 @@
+//! The numbers behind a game of Coup, so house rules can be played and compared.
+
+use crate::compact::MAX_SEATS;
+use crate::Identity;
+use anyhow::{anyhow, Result};
+use enumset::EnumSet;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// Everything about the rules that can be changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Rules {
+    /// The identities shuffled into the deck
+    #[serde(serialize_with = "identity_list", deserialize_with = "identity_set")]
+    pub identities: EnumSet<Identity>,
+    /// Copies of each identity in the deck. None picks 3, or 4 for games of five or more
+    pub cards_per_identity: Option<u8>,
+    pub starting_cards: u8,
+    pub starting_lives: u8,
+    pub starting_coins: u8,
+    /// Players with this many coins must coup
+    pub required_coup_coins: u8,
+    pub coup_cost: u8,
+    pub assassinate_cost: u8,
+    /// The most coins a steal can take
+    pub steal_amount: u8,
+    pub income: u8,
+    pub foreign_aid: u8,
+    pub tax: u8,
+    /// Cards drawn from the deck during an exchange
+    pub exchange_cards: u8,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            identities: EnumSet::all(),
+            cards_per_identity: None,
+            starting_cards: 2,
+            starting_lives: 2,
+            starting_coins: 2,
+            required_coup_coins: 10,
+            coup_cost: 7,
+            assassinate_cost: 3,
+            steal_amount: 2,
+            income: 1,
+            foreign_aid: 2,
+            tax: 3,
+            exchange_cards: 2,
+        }
+    }
+}
+
+impl Rules {
+    /// How many copies of each identity go into the deck
+    pub fn cards_per_identity(&self, num_players: usize) -> u8 {
+        match (self.cards_per_identity, num_players) {
+            (Some(cards), _) => cards,
+            (None, 1..=4) => 3,
+            (None, _) => 4,
+        }
+    }
+
+    /// These rules with some of them changed, given by field name as they are written in JSON
+    pub fn with_changes(&self, changes: &Map<String, Value>) -> Result<Rules> {
+        let mut rules = serde_json::to_value(self)?;
+        for (name, value) in changes {
+            match rules.get_mut(name) {
+                Some(rule) => *rule = value.clone(),
+                None => return Err(anyhow!("There is no rule called {}", name)),
+            }
+        }
+        Ok(serde_json::from_value(rules)?)
+    }
+
+    /// Makes sure a game for this many players can be played to the end under these rules
+    pub fn check(&self, num_players: usize) -> Result<()> {
+        if num_players < 2 {
+            return Err(anyhow!("A game needs at least two players, got {}", num_players));
+        }
+        // Seats are numbered with a u8, and compact states only have room for so many
+        if num_players > MAX_SEATS {
+            return Err(anyhow!("A game takes at most {} players, got {}", MAX_SEATS, num_players));
+        }
+        if self.identities.is_empty() {
+            return Err(anyhow!("The deck needs at least one identity"));
+        }
+        if self.starting_lives == 0 || self.starting_lives > self.starting_cards {
+            return Err(anyhow!(
+                "Players need between 1 and {} lives (one per card), got {}",
+                self.starting_cards,
+                self.starting_lives
+            ));
+        }
+        if self.coup_cost > self.required_coup_coins {
+            return Err(anyhow!(
+                "A coup can't cost more ({}) than the coins that force one ({})",
+                self.coup_cost,
+                self.required_coup_coins
+            ));
+        }
+        // Only the player to move gains coins, and they have fewer than the coins that force a
+        // coup when they do
+        let most_gained = [self.income, self.foreign_aid, self.tax, self.steal_amount].iter().cloned().max();
+        let most_coins = u16::from(self.required_coup_coins.saturating_sub(1)) + u16::from(most_gained.unwrap_or(0));
+        if most_coins.max(u16::from(self.starting_coins)) > u16::from(u8::MAX) {
+            return Err(anyhow!("Players could end up with more than {} coins", u8::MAX));
+        }
+        // Every seat is dealt in, with enough left over for an exchange
+        let deck = self.identities.len() * self.cards_per_identity(num_players) as usize;
+        let needed = num_players * self.starting_cards as usize + self.exchange_cards as usize;
+        if deck < needed {
+            return Err(anyhow!(
+                "{} players need {} cards, but the deck only has {}",
+                num_players,
+                needed,
+                deck
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn identity_list<S: Serializer>(identities: &EnumSet<Identity>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(identities.iter())
+}
+
+fn identity_set<'de, D: Deserializer<'de>>(deserializer: D) -> Result<EnumSet<Identity>, D::Error> {
+    let identities: Vec<Identity> = Vec::deserialize(deserializer)?;
+    Ok(identities.into_iter().collect())
+}
+
+impl fmt::Display for Rules {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let identities: Vec<String> = self.identities.iter().map(|identity| format!("{:?}", identity)).collect();
+        write!(f, "identities {}", identities.join("/"))?;
+        if let Some(cards) = self.cards_per_identity {
+            write!(f, " x{}", cards)?;
+        }
+        write!(
+            f,
+            ", {} coins to start, coup {} (forced at {}), assassinate {}, steal {}",
+            self.starting_coins, self.coup_cost, self.required_coup_coins, self.assassinate_cost, self.steal_amount
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn changed(changes: Value) -> Result<Rules> {
+        Rules::default().with_changes(changes.as_object().unwrap())
+    }
+
+    #[test]
+    fn rules_are_changed_by_field_name() {
+        let rules = changed(json!({"starting_coins": 4, "identities": ["Duke", "Captain", "Duke"]})).unwrap();
+        assert_eq!(rules.starting_coins, 4);
+        assert_eq!(rules.identities, Identity::Duke | Identity::Captain);
+        assert_eq!(rules.coup_cost, Rules::default().coup_cost);
+        assert_eq!(changed(json!({})).unwrap().starting_coins, Rules::default().starting_coins);
+
+        let unknown = changed(json!({"starting_gold": 4})).unwrap_err();
+        assert!(unknown.to_string().contains("starting_gold"), "{}", unknown);
+        assert!(changed(json!({"identities": ["Duke", "Jester"]})).is_err());
+        assert!(changed(json!({"identities": "Duke"})).is_err());
+        assert!(changed(json!({"starting_coins": -1})).is_err());
+        assert!(changed(json!({"starting_coins": "lots"})).is_err());
+    }
+
+    #[test]
+    fn rules_that_cant_be_played_out_are_refused() {
+        let rules = Rules::default();
+        assert!(rules.check(2).is_ok() && rules.check(6).is_ok());
+        assert!(rules.check(1).is_err());
+        assert!(rules.check(MAX_SEATS).is_ok());
+        // Even with a deck big enough to deal them all in
+        let huge_deck = changed(json!({"cards_per_identity": 255})).unwrap();
+        assert!(huge_deck.check(MAX_SEATS + 1).is_err() && huge_deck.check(300).is_err());
+
+        // Three Dukes and three Captains can deal two players in, with two for an exchange
+        let small = changed(json!({"identities": ["Duke", "Captain"]})).unwrap();
+        assert!(small.check(2).is_ok());
+        let too_small = small.check(3).unwrap_err();
+        assert!(too_small.to_string().contains("3 players need 8 cards"), "{}", too_small);
+        assert!(changed(json!({"identities": ["Duke"], "cards_per_identity": 20})).unwrap().check(6).is_ok());
+
+        for broken in [
+            json!({"identities": []}),
+            json!({"starting_lives": 0}),
+            json!({"starting_lives": 3}),
+            json!({"coup_cost": 11}),
+            // Players could end up with more coins than fit in a u8
+            json!({"required_coup_coins": 255, "tax": 3}),
+            json!({"tax": 255}),
+        ] {
+            let rules = changed(broken.clone()).unwrap();
+            assert!(rules.check(3).is_err(), "{} should be refused", broken);
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use crate::action::Action;
+use crate::event::Event;
+use crate::player::traits::Player;
+use crate::view::{PendingDecision, PlayerView};
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use structopt::clap::arg_enum;
+
+/// The answer to a PendingDecision
+#[derive(Debug, Clone, PartialEq)]
+pub enum Choice {
+    Action(Action),
+    Challenge(bool),
+    Block(Option<Action>),
+    ReplaceCard(Option<usize>),
+    LoseCard(usize),
+}
+
+arg_enum! {
+/// What happens to a seat that runs out of time on a decision
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TimeoutPolicy {
+    // Income, no challenge, no block, lose the first card
+    SafeDefault,
+    // The player is knocked out of the game
+    Forfeit,
+    // A RandomPlayer takes over the seat
+    Random,
+}
+}
+
+/// Asks a player for whatever decision the view is pending on
+pub fn decide(player: &mut dyn Player, view: &PlayerView) -> Choice {
+    player.set_hand(view.hand.clone());
+    match &view.pending {
+        PendingDecision::ChooseAction => Choice::Action(player.choose_action(view)),
+        PendingDecision::ForcedCoup => Choice::Action(Action::Coup(player.choose_forced_coup(view))),
+        PendingDecision::Challenge { actor, action } => {
+            Choice::Challenge(player.will_challenge(view, actor, action))
+        }
+        PendingDecision::Block { actor, action } => Choice::Block(player.will_block(view, actor, action)),
+        PendingDecision::ReplaceCard { card } => {
+            Choice::ReplaceCard(player.choose_card_to_replace(view, card))
+        }
+        PendingDecision::LoseCard => Choice::LoseCard(player.choose_card_to_lose(view)),
+    }
+}
+
+/// The choice that can never get a player into trouble: Income, no challenge, no block, keep
+/// the current hand and lose the first card. The game falls back on it whenever a player's
+/// choice isn't among the legal ones, such as a forced coup with nobody else left to target.
+pub fn safe_default(view: &PlayerView) -> Choice {
+    match &view.pending {
+        PendingDecision::ChooseAction => Choice::Action(Action::Income),
+        // With nobody left to coup the game is already over, and Income does no harm
+        PendingDecision::ForcedCoup => match view.other_active_players().into_iter().next() {
+            Some(target) => Choice::Action(Action::Coup(target)),
+            None => Choice::Action(Action::Income),
+        },
+        PendingDecision::Challenge { .. } => Choice::Challenge(false),
+        PendingDecision::Block { .. } => Choice::Block(None),
+        PendingDecision::ReplaceCard { .. } => Choice::ReplaceCard(None),
+        PendingDecision::LoseCard => Choice::LoseCard(0),
+    }
+}
+
+/// Every action the player can take on their turn, once for each possible target
+pub fn legal_actions(view: &PlayerView) -> Vec<Action> {
+    let coins = view.player(&view.me).coins;
+    let mut actions = vec![Action::Income, Action::ForeignAid, Action::Tax, Action::Exchange];
+    for target in view.other_active_players() {
+        actions.push(Action::Steal(target.clone()));
+        if coins >= view.rules.assassinate_cost {
+            actions.push(Action::Assassinate(target.clone()));
+        }
+        if coins >= view.rules.coup_cost {
+            actions.push(Action::Coup(target));
+        }
+    }
+    actions
+}
+
+/// Every answer the player could legally give to the decision the view is pending on. The
+/// safe default is always among them.
+pub fn legal_choices(view: &PlayerView) -> Vec<Choice> {
+    let hand_indexes = 0..view.hand.len();
+    match &view.pending {
+        PendingDecision::ChooseAction => legal_actions(view).into_iter().map(Choice::Action).collect(),
+        PendingDecision::ForcedCoup => view
+            .other_active_players()
+            .into_iter()
+            .map(|target| Choice::Action(Action::Coup(target)))
+            .collect(),
+        PendingDecision::Challenge { .. } => vec![Choice::Challenge(false), Choice::Challenge(true)],
+        PendingDecision::Block { action, .. } => {
+            let mut choices = vec![Choice::Block(None)];
+            if let Some(blocks) = action.blockable(&view.me) {
+                choices.extend(blocks.into_iter().map(|block| Choice::Block(Some(block))));
+            }
+            choices
+        }
+        PendingDecision::ReplaceCard { .. } => {
+            let mut choices = vec![Choice::ReplaceCard(None)];
+            choices.extend(hand_indexes.map(|index| Choice::ReplaceCard(Some(index))));
+            choices
+        }
+        PendingDecision::LoseCard => hand_indexes.map(Choice::LoseCard).collect(),
+    }
+}
+
+/// A player couldn't decide within its time budget
+#[derive(Debug)]
+pub struct TimedOut;
+
+enum WorkerMessage {
+    Decide(u64, PlayerView<'static>),
+    Observe(Event),
+}
+
+/// A player running on its own thread, so the game can stop waiting on it
+struct Worker {
+    requests: Sender<WorkerMessage>,
+    replies: Receiver<(u64, Choice)>,
+    // What the player has said, as they say it
+    said: Receiver<String>,
+    next_request: u64,
+}
+
+impl Worker {
+    fn spawn(mut player: Box<dyn Player>) -> Self {
+        let (requests, incoming) = channel();
+        let (outgoing, replies) = channel();
+        let (talk, said) = channel();
+        thread::spawn(move || {
+            for message in incoming {
+                // Lines are said before the choice they go with is sent back
+                match message {
+                    WorkerMessage::Decide(id, view) => {
+                        let choice = decide(player.as_mut(), &view);
+                        for line in player.talk() {
+                            let _ = talk.send(line);
+                        }
+                        if outgoing.send((id, choice)).is_err() {
+                            break;
+                        }
+                    }
+                    WorkerMessage::Observe(event) => {
+                        player.observe(&event);
+                        for line in player.talk() {
+                            let _ = talk.send(line);
+                        }
+                    }
+                }
+            }
+        });
+        Worker {
+            requests,
+            replies,
+            said,
+            next_request: 0,
+        }
+    }
+
+    fn decide(&mut self, view: &PlayerView, budget: Option<Duration>) -> Result<Choice, TimedOut> {
+        let id = self.next_request;
+        self.next_request += 1;
+        let deadline = budget.map(|budget| Instant::now() + budget);
+        self.requests
+            .send(WorkerMessage::Decide(id, view.clone().into_owned()))
+            .map_err(|_| TimedOut)?;
+        loop {
+            let reply = match deadline {
+                Some(deadline) => self
+                    .replies
+                    .recv_timeout(deadline.saturating_duration_since(Instant::now())),
+                None => self.replies.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            match reply {
+                Ok((reply_id, choice)) if reply_id == id => return Ok(choice),
+                // A late answer to a decision we already gave up on
+                Ok(_) => continue,
+                // The worker is gone if the player panicked
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => {
+                    return Err(TimedOut)
+                }
+            }
+        }
+    }
+}
+
+enum Occupant {
+    Local(Box<dyn Player>),
+    Threaded(Worker),
+}
+
+/// Holds whoever is playing a seat, and makes sure they answer in time
+pub struct Seat {
+    occupant: Occupant,
+    // Humans are never put on a clock
+    cpu: bool,
+}
+
+impl Seat {
+    pub fn new(player: Box<dyn Player>, cpu: bool) -> Self {
+        Seat {
+            occupant: Occupant::Local(player),
+            cpu,
+        }
+    }
+
+    /// Moves a cpu player onto its own thread, so its decisions can be given a time budget
+    pub fn start_clock(self) -> Self {
+        match self.occupant {
+            Occupant::Local(player) if self.cpu => Seat {
+                occupant: Occupant::Threaded(Worker::spawn(player)),
+                cpu: true,
+            },
+            occupant => Seat { occupant, cpu: self.cpu },
+        }
+    }
+
+    /// Wraps a cpu player that hasn't been moved onto its own thread yet, e.g. to give it
+    /// something to say
+    pub fn wrap(self, wrap: impl FnOnce(Box<dyn Player>) -> Box<dyn Player>) -> Self {
+        match self.occupant {
+            Occupant::Local(player) if self.cpu => Seat {
+                occupant: Occupant::Local(wrap(player)),
+                cpu: true,
+            },
+            occupant => Seat { occupant, cpu: self.cpu },
+        }
+    }
+
+    /// Hands the seat to a new player. Anything still running for the old one is abandoned.
+    pub fn replace(&mut self, player: Box<dyn Player>) {
+        self.occupant = Occupant::Local(player);
+    }
+
+    pub fn decide(&mut self, view: &PlayerView, budget: Option<Duration>) -> Result<Choice, TimedOut> {
+        match &mut self.occupant {
+            Occupant::Local(player) => Ok(decide(player.as_mut(), view)),
+            Occupant::Threaded(worker) => worker.decide(view, budget),
+        }
+    }
+
+    pub fn observe(&mut self, event: &Event) {
+        match &mut self.occupant {
+            Occupant::Local(player) => player.observe(event),
+            Occupant::Threaded(worker) => {
+                let _ = worker.requests.send(WorkerMessage::Observe(event.clone()));
+            }
+        }
+    }
+
+    /// Whatever the player has said since the last time
+    pub fn talk(&mut self) -> Vec<String> {
+        match &mut self.occupant {
+            Occupant::Local(player) => player.talk(),
+            Occupant::Threaded(worker) => worker.said.try_iter().collect(),
+        }
+    }
+}
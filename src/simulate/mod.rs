@@ -0,0 +1,467 @@
+//! Headless bot-only games, played across every core with nothing printed until the end.
+//!
+//! Every game gets its own seed, worked out from the run's seed and the game's number, so a
+//! run gives the same totals whatever the number of threads.
+
+use crate::event::Subscriber;
+use crate::player::random_player::RandomWeights;
+use crate::stats::{PlayerStats, StatsCollector, StatsFormat, StatsWriter};
+use crate::rules::Rules;
+use crate::seat::TimeoutPolicy;
+use crate::{Game, GameResult, LoggerType, PlayerConfig, PlayerID, PlayerType};
+use anyhow::{anyhow, Error, Result};
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::Duration;
+use structopt::StructOpt;
+
+// Games handed to the thread pool at a time
+const BATCH_SIZE: u64 = 1024;
+
+/// z score for a 95% confidence interval
+pub const Z_95: f64 = 1.96;
+
+#[derive(StructOpt, Debug)]
+pub struct SimulateConfig {
+    /// The number of games to play
+    #[structopt(long, default_value = "1000")]
+    games: u32,
+    /// The bots at the table, as TYPE:COUNT. Types are random, honest and dumb
+    #[structopt(long, value_delimiter = ",", default_value = "random:3,honest:2")]
+    cpus: Vec<CpuSpec>,
+    /// Games still going after this many turns are called off without a winner
+    #[structopt(long, default_value = "1000")]
+    max_turns: u32,
+    /// Games that come back to the same position this many times are called off without a
+    /// winner. Positions aren't tracked if not given
+    #[structopt(long)]
+    max_repeats: Option<u32>,
+    /// Seed for the whole run. A random one is picked (and printed) if not given
+    #[structopt(long)]
+    seed: Option<u64>,
+    /// Threads to play on. Defaults to one per core
+    #[structopt(long)]
+    threads: Option<usize>,
+    /// Write per game, per player stats to this file
+    #[structopt(long, parse(from_os_str))]
+    stats: Option<PathBuf>,
+    /// The format of the stats file
+    #[structopt(long, possible_values = &StatsFormat::variants(), case_insensitive = true, default_value = "Csv")]
+    stats_format: StatsFormat,
+    /// How long bots get for each decision, in milliseconds. Unlimited if not given
+    #[structopt(long)]
+    decision_timeout_ms: Option<u64>,
+    /// What happens to a bot that runs out of time
+    #[structopt(long, possible_values = &TimeoutPolicy::variants(), case_insensitive = true, default_value = "SafeDefault")]
+    timeout_policy: TimeoutPolicy,
+}
+
+impl SimulateConfig {
+    fn limits(&self) -> Limits {
+        Limits {
+            max_turns: self.max_turns,
+            max_repeats: self.max_repeats,
+            decision_clock: self
+                .decision_timeout_ms
+                .map(|timeout| (Duration::from_millis(timeout), self.timeout_policy)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum BotKind {
+    Random,
+    Honest,
+    Dumb,
+}
+
+impl BotKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            BotKind::Random => "random",
+            BotKind::Honest => "honest",
+            BotKind::Dumb => "dumb",
+        }
+    }
+
+    pub fn player_type(self, weights: &RandomWeights) -> PlayerType {
+        match self {
+            BotKind::Random => PlayerType::RandomCPU(weights.clone()),
+            BotKind::Honest => PlayerType::HonestCPU,
+            BotKind::Dumb => PlayerType::DumbCPU,
+        }
+    }
+}
+
+impl FromStr for BotKind {
+    type Err = Error;
+
+    fn from_str(kind: &str) -> Result<Self> {
+        match kind.trim() {
+            "random" => Ok(BotKind::Random),
+            "honest" => Ok(BotKind::Honest),
+            "dumb" => Ok(BotKind::Dumb),
+            _ => Err(anyhow!("Unknown bot type '{}'", kind)),
+        }
+    }
+}
+
+/// A number of seats played by the same kind of bot, e.g. "random:3"
+#[derive(Debug, Clone)]
+pub struct CpuSpec {
+    kind: BotKind,
+    count: u8,
+}
+
+impl FromStr for CpuSpec {
+    type Err = Error;
+
+    fn from_str(spec: &str) -> Result<Self> {
+        let mut parts = spec.splitn(2, ':');
+        let kind = parts.next().unwrap_or_default().parse()?;
+        let count = match parts.next() {
+            Some(count) => count
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("Expected a number of bots in '{}'", spec))?,
+            None => 1,
+        };
+        Ok(CpuSpec { kind, count })
+    }
+}
+
+/// Wins for one seat, or for every seat of one kind of bot
+#[derive(Debug, Default)]
+struct Tally {
+    seats: u32,
+    wins: u64,
+    timeouts: u64,
+}
+
+/// Running totals over the games played so far
+#[derive(Debug, Clone, PartialEq)]
+struct Totals {
+    games: u64,
+    draws: u64,
+    seat_wins: Vec<u64>,
+    seat_timeouts: Vec<u64>,
+    turns: u64,
+    turns_squared: u64,
+}
+
+impl Totals {
+    fn new(num_seats: usize) -> Self {
+        Totals {
+            games: 0,
+            draws: 0,
+            seat_wins: vec![0; num_seats],
+            seat_timeouts: vec![0; num_seats],
+            turns: 0,
+            turns_squared: 0,
+        }
+    }
+
+    fn add(mut self, result: GameResult) -> Self {
+        self.games += 1;
+        match result.winner {
+            Some(PlayerID(seat)) => self.seat_wins[seat as usize] += 1,
+            None => self.draws += 1,
+        }
+        for (PlayerID(seat), timeouts) in result.timeouts {
+            self.seat_timeouts[seat as usize] += u64::from(timeouts);
+        }
+        let turns = u64::from(result.turns);
+        self.turns += turns;
+        self.turns_squared += turns * turns;
+        self
+    }
+}
+
+/// Plays the configured number of games and prints how each bot did
+pub fn run(config: &SimulateConfig, rules: Rules, weights: &RandomWeights) -> Result<()> {
+    let seats = seat_kinds(&config.cpus);
+    rules.check(seats.len())?;
+    if config.games == 0 {
+        return Err(anyhow!("Nothing to simulate"));
+    }
+    let seed = config.seed.unwrap_or_else(rand::random);
+
+    let mut stats = match &config.stats {
+        Some(path) => Some(StatsWriter::create(path, config.stats_format)?),
+        None => None,
+    };
+
+    let totals = play_games(config, &seats, rules, weights, seed, &mut stats)?;
+    if let Some(stats) = stats {
+        stats.finish()?;
+    }
+
+    println!("Seed: {}", seed);
+    present(&seats, &totals);
+    Ok(())
+}
+
+// Plays every game, writing stats for each as it goes, and adds up the results
+fn play_games(
+    config: &SimulateConfig,
+    seats: &[BotKind],
+    rules: Rules,
+    weights: &RandomWeights,
+    seed: u64,
+    stats: &mut Option<StatsWriter>,
+) -> Result<Totals> {
+    let mut pool = ThreadPoolBuilder::new();
+    if let Some(threads) = config.threads {
+        pool = pool.num_threads(threads);
+    }
+    let pool = pool.build()?;
+    let mut totals = Totals::new(seats.len());
+    let games = u64::from(config.games);
+    // Games are played a batch at a time, so stats can be written out in order as we go
+    for start in (0..games).step_by(BATCH_SIZE as usize) {
+        let batch = start..(start + BATCH_SIZE).min(games);
+        let results: Vec<Result<(GameResult, Vec<PlayerStats>)>> = pool.install(|| {
+            batch
+                .into_par_iter()
+                .map(|game| {
+                    let players = seat_players(seats, weights);
+                    let seed = game_seed(seed, game);
+                    if stats.is_none() {
+                        let result = play_game(players, rules, config.limits(), seed, None)?;
+                        return Ok((result, Vec::new()));
+                    }
+                    let collector = Rc::new(RefCell::new(StatsCollector::new(game)));
+                    let subscriber = Box::new(collector.clone());
+                    let result = play_game(players, rules, config.limits(), seed, Some(subscriber))?;
+                    let rows = collector.borrow().rows();
+                    Ok((result, rows))
+                })
+                .collect()
+        });
+        for result in results {
+            let (result, rows) = result?;
+            totals = totals.add(result);
+            if let Some(stats) = stats {
+                stats.write(&rows)?;
+            }
+        }
+    }
+    Ok(totals)
+}
+
+/// The kind of bot in each seat, in order
+pub fn seat_kinds(cpus: &[CpuSpec]) -> Vec<BotKind> {
+    cpus.iter()
+        .flat_map(|spec| std::iter::repeat_n(spec.kind, spec.count as usize))
+        .collect()
+}
+
+/// Names each bot after its kind and how many of that kind sat down before it
+pub fn seat_players(seats: &[BotKind], weights: &RandomWeights) -> Vec<PlayerConfig> {
+    let mut counts: HashMap<BotKind, u32> = HashMap::new();
+    seats
+        .iter()
+        .map(|kind| {
+            let count = counts.entry(*kind).or_insert(0);
+            *count += 1;
+            let name = format!("{} {}", kind.name(), count);
+            PlayerConfig::new(kind.player_type(weights), name)
+        })
+        .collect()
+}
+
+/// When a headless game is called off without a winner
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub max_turns: u32,
+    // The times a position can come round before the game is called off
+    pub max_repeats: Option<u32>,
+    // How long each bot gets for a decision, and what happens when it runs out
+    pub decision_clock: Option<(Duration, TimeoutPolicy)>,
+}
+
+/// Plays one quiet game to the end. Seats are numbered in the order the players are given.
+pub fn play_game(
+    players: Vec<PlayerConfig>,
+    rules: Rules,
+    limits: Limits,
+    seed: u64,
+    subscriber: Option<Box<dyn Subscriber>>,
+) -> Result<GameResult> {
+    let mut game = Game::new(rules, players, LoggerType::Quiet, seed)?;
+    game.set_turn_limit(limits.max_turns);
+    if let Some(max_repeats) = limits.max_repeats {
+        game.set_repetition_limit(max_repeats);
+    }
+    if let Some((budget, policy)) = limits.decision_clock {
+        game.set_decision_clock(budget, policy);
+    }
+    if let Some(subscriber) = subscriber {
+        game.subscribe(subscriber);
+    }
+    Ok(game.play())
+}
+
+/// Spreads a run's seed out into one seed per game (splitmix64)
+pub fn game_seed(seed: u64, game: u64) -> u64 {
+    let mut z = seed.wrapping_add(game.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+fn present(seats: &[BotKind], totals: &Totals) {
+    let games = totals.games;
+    println!("{} games, {} without a winner", games, totals.draws);
+    let (mean, margin) = mean_with_margin(games, totals.turns, totals.turns_squared);
+    println!("Average game length: {:.1} ± {:.1} turns", mean, margin);
+    println!();
+
+    // Kinds in the order they first sit down
+    let mut kinds: Vec<BotKind> = Vec::new();
+    let mut by_kind: HashMap<BotKind, Tally> = HashMap::new();
+    for (seat, kind) in seats.iter().enumerate() {
+        if !kinds.contains(kind) {
+            kinds.push(*kind);
+        }
+        let tally = by_kind.entry(*kind).or_default();
+        tally.seats += 1;
+        tally.wins += totals.seat_wins[seat];
+        tally.timeouts += totals.seat_timeouts[seat];
+    }
+
+    println!(
+        "{:<10} {:>5} {:>7} {:>8} {:>17} {:>10} {:>9}",
+        "Bot", "Seats", "Wins", "Win %", "95% CI", "Fair %", "Timeouts"
+    );
+    for kind in kinds {
+        let tally = &by_kind[&kind];
+        let fair = 100.0 * f64::from(tally.seats) / seats.len() as f64;
+        println!(
+            "{:<10} {:>5} {} {:>10.1} {:>9}",
+            kind.name(),
+            tally.seats,
+            win_rate(tally.wins, games),
+            fair,
+            tally.timeouts
+        );
+    }
+    println!();
+
+    println!("{:<10} {:>5} {:>7} {:>8} {:>17} {:>9}", "Seat", "Bot", "Wins", "Win %", "95% CI", "Timeouts");
+    for (seat, kind) in seats.iter().enumerate() {
+        println!(
+            "{:<10} {:>5} {} {:>9}",
+            seat,
+            kind.name(),
+            win_rate(totals.seat_wins[seat], games),
+            totals.seat_timeouts[seat]
+        );
+    }
+}
+
+/// The 95% Wilson score interval for a win rate
+pub fn wilson_interval(wins: u64, games: u64) -> (f64, f64) {
+    let n = games as f64;
+    let p = wins as f64 / n;
+    let z2 = Z_95 * Z_95;
+    let centre = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+    let spread = Z_95 * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt() / (1.0 + z2 / n);
+    ((centre - spread).max(0.0), (centre + spread).min(1.0))
+}
+
+// Wins, win percentage and its Wilson score interval, formatted for the tables above
+fn win_rate(wins: u64, games: u64) -> String {
+    let p = wins as f64 / games as f64;
+    let (low, high) = wilson_interval(wins, games);
+    format!(
+        "{:>7} {:>8.1} {:>17}",
+        wins,
+        100.0 * p,
+        format!("[{:.1}, {:.1}]", 100.0 * low, 100.0 * high)
+    )
+}
+
+/// The sample mean, and the half width of its 95% confidence interval, from the count, sum and
+/// sum of squares of the samples
+pub fn mean_with_margin(n: u64, sum: u64, sum_of_squares: u64) -> (f64, f64) {
+    let mean = sum as f64 / n as f64;
+    if n < 2 {
+        return (mean, 0.0);
+    }
+    let n = n as f64;
+    let variance = ((sum_of_squares as f64 - n * mean * mean) / (n - 1.0)).max(0.0);
+    (mean, Z_95 * (variance / n).sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bots_are_read_as_type_and_count() {
+        let spec: CpuSpec = "honest:3".parse().unwrap();
+        assert_eq!((spec.kind, spec.count), (BotKind::Honest, 3));
+        let spec: CpuSpec = " random ".parse().unwrap();
+        assert_eq!((spec.kind, spec.count), (BotKind::Random, 1));
+        for spec in ["clever:2", "random:", "random:-1", "random:two", "dumb:300", ""] {
+            assert!(spec.parse::<CpuSpec>().is_err(), "{} should be refused", spec);
+        }
+        let cpus = ["random:2".parse().unwrap(), "dumb".parse().unwrap()];
+        assert_eq!(seat_kinds(&cpus), vec![BotKind::Random, BotKind::Random, BotKind::Dumb]);
+    }
+
+    #[test]
+    fn win_rates_come_with_a_sensible_interval() {
+        let (low, high) = wilson_interval(50, 100);
+        assert!((low - 0.404).abs() < 1e-3 && (high - 0.596).abs() < 1e-3, "[{}, {}]", low, high);
+        // Never outside [0, 1], even with no wins or nothing but wins
+        let (low, high) = wilson_interval(0, 10);
+        assert!(low == 0.0 && high > 0.0 && high < 0.5);
+        let (low, high) = wilson_interval(10, 10);
+        assert!(high == 1.0 && low > 0.5 && low < 1.0);
+        // More games, a tighter interval
+        let (low, high) = wilson_interval(500, 1000);
+        assert!(high - low < 0.07);
+    }
+
+    #[test]
+    fn means_come_with_a_margin() {
+        // 2, 4, 4, 4, 5, 5, 7, 9
+        let (mean, margin) = mean_with_margin(8, 40, 232);
+        assert_eq!(mean, 5.0);
+        let standard_deviation = (32.0f64 / 7.0).sqrt();
+        assert!((margin - Z_95 * standard_deviation / 8f64.sqrt()).abs() < 1e-9);
+        assert_eq!(mean_with_margin(1, 7, 49), (7.0, 0.0));
+        assert_eq!(mean_with_margin(3, 12, 48), (4.0, 0.0));
+    }
+
+    #[test]
+    fn every_game_gets_its_own_seed() {
+        let seeds: Vec<u64> = (0..1000).map(|game| game_seed(42, game)).collect();
+        let mut distinct = seeds.clone();
+        distinct.sort();
+        distinct.dedup();
+        assert_eq!(distinct.len(), seeds.len());
+        assert_eq!(game_seed(42, 7), seeds[7]);
+        assert_ne!(game_seed(43, 7), seeds[7]);
+    }
+
+    #[test]
+    fn the_number_of_threads_makes_no_difference() {
+        let totals = |threads: &str| {
+            let args = ["simulate", "--games", "300", "--cpus", "random:2,honest,dumb", "--threads", threads];
+            let config = SimulateConfig::from_iter_safe(args).unwrap();
+            let seats = seat_kinds(&config.cpus);
+            play_games(&config, &seats, Rules::default(), &RandomWeights::default(), 5, &mut None).unwrap()
+        };
+        let one = totals("1");
+        assert_eq!(one.games, 300);
+        assert_eq!(totals("4"), one);
+    }
+}
@@ -0,0 +1,362 @@
+//! Watching a game without playing in it.
+//!
+//! Spectators are shown every event they may see, along with the public state of the table as
+//! it was at the time. A public view leaves out private events and hands, so spectators know no
+//! more than someone looking over the table. An omniscient view adds the private events, every
+//! hand and the deck. Spectators can be kept behind the game, so nobody watching an omniscient
+//! view can tip off a player in time for it to matter.
+//!
+//! `--watch` shows a game on the terminal it runs on, and `--spectators ADDR` lets `coup watch
+//! ADDR` follow it from elsewhere. Over the network each frame is one JSON line, and anyone
+//! connecting late is sent the frames they missed first.
+
+use crate::event::{Event, Subscriber};
+use crate::view::{PlayerNames, PublicPlayerInfo};
+use crate::{Game, GameState, Identity, PlayerID};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use structopt::clap::arg_enum;
+use structopt::StructOpt;
+
+// How long a frame waits on a spectator who isn't reading. Frames are sent with the crowd
+// locked, so one spectator who stops reading mustn't hold up the game or the rest of them.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+arg_enum! {
+/// How much of the game spectators are shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Visibility {
+    // Only what every player sees
+    Public,
+    // Every event, every hand and the deck
+    Omniscient,
+}
+}
+
+#[derive(StructOpt, Debug)]
+pub struct SpectateConfig {
+    /// Show the game on this terminal the way a spectator sees it, instead of the usual log
+    #[structopt(long)]
+    watch: bool,
+    /// Let spectators follow the game from elsewhere, with `watch` pointed at this address
+    #[structopt(long)]
+    spectators: Option<String>,
+    /// What spectators are shown: the public table, or every hand and the deck as well
+    #[structopt(long, possible_values = &Visibility::variants(), case_insensitive = true, default_value = "Public")]
+    spectator_view: Visibility,
+    /// How far behind the game spectators are kept, in milliseconds
+    #[structopt(long, default_value = "0")]
+    spectator_delay_ms: u64,
+}
+
+/// No spectators
+impl Default for SpectateConfig {
+    fn default() -> Self {
+        SpectateConfig {
+            watch: false,
+            spectators: None,
+            spectator_view: Visibility::Public,
+            spectator_delay_ms: 0,
+        }
+    }
+}
+
+impl SpectateConfig {
+    /// Whether the game is shown on this terminal, in place of its log
+    pub fn watching(&self) -> bool {
+        self.watch
+    }
+
+    /// Starts showing the game to its spectators, if it has any. The handle finishes once
+    /// they've been shown the end of the game, which can be a while after it's over.
+    pub fn open(&self, game: &mut Game) -> Result<Option<JoinHandle<()>>> {
+        let mut audiences: Vec<Box<dyn Audience>> = Vec::new();
+        if self.watch {
+            audiences.push(Box::new(Terminal));
+        }
+        if let Some(address) = &self.spectators {
+            let listener = TcpListener::bind(address)?;
+            println!("Spectators can watch on {}", listener.local_addr()?);
+            audiences.push(Box::new(Stands::open(listener)));
+        }
+        if audiences.is_empty() {
+            return Ok(None);
+        }
+        let delay = Duration::from_millis(self.spectator_delay_ms);
+        let (broadcast, relay) = Broadcast::start(self.spectator_view, delay, audiences);
+        game.subscribe(Box::new(broadcast));
+        Ok(Some(relay))
+    }
+}
+
+/// What a spectator is shown of an event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Frame {
+    pub event: Event,
+    /// The public state of every seat, in turn order
+    pub players: Vec<PublicPlayerInfo>,
+    /// Every hand, in an omniscient view
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hands: Option<BTreeMap<PlayerID, Vec<Identity>>>,
+    /// The deck from the top, in an omniscient view
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deck: Option<Vec<Identity>>,
+}
+
+impl Frame {
+    /// The frame for the event, if spectators with this view may see it
+    pub fn new(
+        visibility: Visibility,
+        event: &Event,
+        state: &GameState,
+        hands: &HashMap<PlayerID, Vec<Identity>>,
+        deck: &[Identity],
+    ) -> Option<Self> {
+        let omniscient = visibility == Visibility::Omniscient;
+        if event.private_to().is_some() && !omniscient {
+            return None;
+        }
+        Some(Frame {
+            event: event.clone(),
+            players: PublicPlayerInfo::for_table(state),
+            hands: if omniscient {
+                Some(hands.iter().map(|(id, hand)| (id.clone(), hand.clone())).collect())
+            } else {
+                None
+            },
+            deck: if omniscient { Some(deck.to_vec()) } else { None },
+        })
+    }
+
+    /// The event, followed by the hands when they're shown
+    pub fn describe(&self) -> String {
+        let mut description = self.event.describe(self);
+        if let Some(hands) = &self.hands {
+            for info in &self.players {
+                let hand = hands.get(&info.id).map(Vec::as_slice).unwrap_or_default();
+                description += &format!("\n    {} ({} coins): {:?}", info.name, info.coins, hand);
+            }
+        }
+        description
+    }
+}
+
+impl PlayerNames for Frame {
+    fn get_player_name(&self, player_id: &PlayerID) -> String {
+        match self.players.iter().find(|info| &info.id == player_id) {
+            Some(info) => info.name.clone(),
+            None => format!("Player {}", player_id.0 + 1),
+        }
+    }
+}
+
+/// Somewhere frames are shown
+trait Audience: Send {
+    fn show(&mut self, frame: &Frame);
+    /// Called after the last frame of the game
+    fn close(&mut self) {}
+}
+
+struct Terminal;
+
+impl Audience for Terminal {
+    fn show(&mut self, frame: &Frame) {
+        println!("{}", frame.describe());
+    }
+}
+
+/// Spectators connected over the network
+struct Stands(Arc<Mutex<Crowd>>);
+
+#[derive(Default)]
+struct Crowd {
+    watchers: Vec<TcpStream>,
+    // Every line sent so far, for whoever connects next
+    shown: Vec<String>,
+    over: bool,
+}
+
+impl Stands {
+    // Lets spectators in for as long as the program runs
+    fn open(listener: TcpListener) -> Self {
+        let crowd = Arc::new(Mutex::new(Crowd::default()));
+        let stands = crowd.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = stream.and_then(|stream| stream.set_write_timeout(Some(WRITE_TIMEOUT)).map(|_| stream));
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        log::warn!("Unable to accept a spectator: {}", e);
+                        continue;
+                    }
+                };
+                let mut crowd = stands.lock().unwrap();
+                let caught_up = crowd.shown.iter().try_for_each(|line| writeln!(stream, "{}", line));
+                if caught_up.is_ok() && !crowd.over {
+                    crowd.watchers.push(stream);
+                }
+            }
+        });
+        Stands(crowd)
+    }
+}
+
+impl Audience for Stands {
+    fn show(&mut self, frame: &Frame) {
+        let line = serde_json::to_string(frame).unwrap();
+        let mut crowd = self.0.lock().unwrap();
+        // Anyone who has gone, or has stopped reading, stops being sent frames
+        crowd.watchers.retain_mut(|watcher| writeln!(watcher, "{}", line).is_ok());
+        crowd.shown.push(line);
+    }
+
+    fn close(&mut self) {
+        let mut crowd = self.0.lock().unwrap();
+        crowd.over = true;
+        // Hanging up tells them the game is over
+        crowd.watchers.clear();
+    }
+}
+
+/// Shows a game to its spectators, `delay` after each event happens
+pub struct Broadcast {
+    visibility: Visibility,
+    delay: Duration,
+    frames: Sender<(Instant, Frame)>,
+}
+
+impl Broadcast {
+    /// Starts the thread that shows the frames when they're due. It finishes after showing the
+    /// end of the game.
+    fn start(visibility: Visibility, delay: Duration, audiences: Vec<Box<dyn Audience>>) -> (Self, JoinHandle<()>) {
+        let (frames, due) = mpsc::channel();
+        let relay = thread::spawn(move || relay(due, audiences));
+        let broadcast = Broadcast {
+            visibility,
+            delay,
+            frames,
+        };
+        (broadcast, relay)
+    }
+}
+
+impl Subscriber for Broadcast {
+    fn on_event(&mut self, event: &Event, state: &GameState, hands: &HashMap<PlayerID, Vec<Identity>>, deck: &[Identity]) {
+        if let Some(frame) = Frame::new(self.visibility, event, state, hands, deck) {
+            let _ = self.frames.send((Instant::now() + self.delay, frame));
+        }
+    }
+}
+
+fn relay(frames: Receiver<(Instant, Frame)>, mut audiences: Vec<Box<dyn Audience>>) {
+    for (due, frame) in frames {
+        if let Some(wait) = due.checked_duration_since(Instant::now()) {
+            thread::sleep(wait);
+        }
+        for audience in &mut audiences {
+            audience.show(&frame);
+        }
+        if let Event::GameOver { .. } = frame.event {
+            break;
+        }
+    }
+    for audience in &mut audiences {
+        audience.close();
+    }
+}
+
+#[derive(StructOpt, Debug)]
+pub struct WatchConfig {
+    /// Where the game lets spectators in, as HOST:PORT
+    address: String,
+}
+
+/// Follows a game as a spectator until it's over
+pub fn watch(config: &WatchConfig) -> Result<()> {
+    let stream = TcpStream::connect(&config.address)?;
+    let mut over = false;
+    for line in BufReader::new(stream).lines() {
+        let frame: Frame = serde_json::from_str(&line?)?;
+        println!("{}", frame.describe());
+        over = matches!(frame.event, Event::GameOver { .. });
+    }
+    if !over {
+        return Err(anyhow!("The game stopped being shown before it was over"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::Rules;
+    use crate::{LoggerType, PlayerConfig, PlayerType};
+
+    // Everything the spectator at the address is sent
+    fn spectate(address: String) -> JoinHandle<Vec<Frame>> {
+        thread::spawn(move || {
+            let stream = TcpStream::connect(address).unwrap();
+            BufReader::new(stream)
+                .lines()
+                .map(|line| serde_json::from_str(&line.unwrap()).unwrap())
+                .collect()
+        })
+    }
+
+    #[test]
+    fn spectators_see_as_much_as_their_view_allows() {
+        let players = vec![
+            PlayerConfig::new(PlayerType::HonestCPU, "Hal".to_string()),
+            PlayerConfig::new(PlayerType::DumbCPU, "Don".to_string()),
+            PlayerConfig::new(PlayerType::HonestCPU, "Hil".to_string()),
+        ];
+        let mut game = Game::new(Rules::default(), players, LoggerType::Quiet, 11).unwrap();
+        let delay = Duration::from_millis(200);
+        let mut relays = Vec::new();
+        let mut spectators = Vec::new();
+        for (visibility, delay) in [(Visibility::Public, Duration::ZERO), (Visibility::Omniscient, delay)] {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            spectators.push(spectate(listener.local_addr().unwrap().to_string()));
+            let audiences: Vec<Box<dyn Audience>> = vec![Box::new(Stands::open(listener))];
+            let (broadcast, relay) = Broadcast::start(visibility, delay, audiences);
+            game.subscribe(Box::new(broadcast));
+            relays.push(relay);
+        }
+
+        let started = Instant::now();
+        let result = game.play();
+        for relay in relays {
+            relay.join().unwrap();
+        }
+        // The omniscient view was held back
+        assert!(started.elapsed() >= delay);
+
+        let omniscient = spectators.pop().unwrap().join().unwrap();
+        let public = spectators.pop().unwrap().join().unwrap();
+        for frames in [&public, &omniscient] {
+            match frames.last().map(|frame| &frame.event) {
+                Some(Event::GameOver { winner }) => assert_eq!(winner, &result.winner),
+                last => panic!("The game didn't end with a winner, but {:?}", last),
+            }
+        }
+        assert!(public
+            .iter()
+            .all(|frame| frame.event.private_to().is_none() && frame.hands.is_none() && frame.deck.is_none()));
+        assert!(omniscient.iter().any(|frame| frame.event.private_to().is_some()));
+        assert!(omniscient.len() > public.len());
+        // Every card is somewhere: in a hand, in the deck or face up
+        let start = &omniscient[0];
+        let hands = start.hands.as_ref().unwrap();
+        assert_eq!(hands.len(), 3);
+        let cards = hands.values().map(Vec::len).sum::<usize>() + start.deck.as_ref().unwrap().len();
+        assert_eq!(cards, 15);
+    }
+}
@@ -0,0 +1,303 @@
+//! Per game, per player statistics, collected by watching a game's events and exported as CSV
+//! or JSON for analysis elsewhere.
+
+use crate::action::Action;
+use crate::event::{Event, Subscriber};
+use crate::{GameState, Identity, PlayerID};
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use structopt::clap::arg_enum;
+
+arg_enum! {
+/// How stats are written out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatsFormat {
+    Csv,
+    // One JSON array of rows
+    Json,
+}
+}
+
+/// Everything one player did in one game
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PlayerStats {
+    pub game: u64,
+    pub seat: u8,
+    pub name: String,
+    pub won: bool,
+    // None if they were still in when the game ended
+    pub eliminated_on_turn: Option<u32>,
+    pub income: u32,
+    pub foreign_aid: u32,
+    pub tax: u32,
+    pub steal: u32,
+    pub assassinate: u32,
+    pub coup: u32,
+    pub exchange: u32,
+    // Actions that claimed an identity, and how many of those they didn't hold
+    pub claims: u32,
+    pub bluffs: u32,
+    pub challenges: u32,
+    pub challenges_won: u32,
+    pub blocks: u32,
+    pub block_bluffs: u32,
+    pub coins_earned: u32,
+    pub coins_spent: u32,
+    // Taken by someone else's steal
+    pub coins_stolen: u32,
+}
+
+/// Builds up a PlayerStats for every seat as the game goes
+#[derive(Debug, Default)]
+pub struct StatsCollector {
+    game: u64,
+    players: HashMap<PlayerID, PlayerStats>,
+    // Everyone's coins when the action being played out was chosen
+    coins_before: HashMap<PlayerID, u8>,
+}
+
+impl StatsCollector {
+    pub fn new(game: u64) -> Self {
+        StatsCollector {
+            game,
+            ..Default::default()
+        }
+    }
+
+    /// One row per player, in seat order
+    pub fn rows(&self) -> Vec<PlayerStats> {
+        let mut rows: Vec<PlayerStats> = self.players.values().cloned().collect();
+        rows.sort_by_key(|row| row.seat);
+        rows
+    }
+
+    fn player(&mut self, id: &PlayerID) -> &mut PlayerStats {
+        self.players.get_mut(id).expect("Stats for a player who isn't seated")
+    }
+
+    fn claimed_without_card(action: &Action, hands: &HashMap<PlayerID, Vec<Identity>>, id: &PlayerID) -> bool {
+        !action.can_be_done_with(&hands[id])
+    }
+}
+
+impl Subscriber for StatsCollector {
+    fn on_event(&mut self, event: &Event, state: &GameState, hands: &HashMap<PlayerID, Vec<Identity>>, _deck: &[Identity]) {
+        match event {
+            Event::GameStarted { turn_order } => {
+                for id in turn_order {
+                    let stats = PlayerStats {
+                        game: self.game,
+                        seat: id.0,
+                        name: state.player_states[id].get_name(),
+                        ..Default::default()
+                    };
+                    self.players.insert(id.clone(), stats);
+                }
+            }
+            Event::ActionChosen { actor, action } => {
+                self.coins_before = state
+                    .player_states
+                    .iter()
+                    .map(|(id, player)| (id.clone(), player.num_coins))
+                    .collect();
+                let bluff = Self::claimed_without_card(action, hands, actor);
+                let stats = self.player(actor);
+                match action {
+                    Action::Income => stats.income += 1,
+                    Action::ForeignAid => stats.foreign_aid += 1,
+                    Action::Tax => stats.tax += 1,
+                    Action::Steal(..) => stats.steal += 1,
+                    Action::Assassinate(..) => stats.assassinate += 1,
+                    Action::Coup(..) => stats.coup += 1,
+                    Action::Exchange => stats.exchange += 1,
+                    _ => {}
+                }
+                if action.claimed_identity().is_some() {
+                    stats.claims += 1;
+                    if bluff {
+                        stats.bluffs += 1;
+                    }
+                }
+            }
+            Event::BlockDeclared { blocker, block, .. } => {
+                let bluff = Self::claimed_without_card(block, hands, blocker);
+                let stats = self.player(blocker);
+                stats.blocks += 1;
+                if bluff {
+                    stats.block_bluffs += 1;
+                }
+            }
+            Event::ChallengeResolved { challenger, challenger_won, .. } => {
+                let stats = self.player(challenger);
+                stats.challenges += 1;
+                if *challenger_won {
+                    stats.challenges_won += 1;
+                }
+            }
+            Event::ActionResolved { actor, .. } => {
+                for (id, player) in &state.player_states {
+                    let before = i32::from(self.coins_before.get(id).cloned().unwrap_or(player.num_coins));
+                    let change = i32::from(player.num_coins) - before;
+                    let stats = self.player(id);
+                    if id == actor && change > 0 {
+                        stats.coins_earned += change as u32;
+                    } else if id == actor {
+                        stats.coins_spent += (-change) as u32;
+                    } else if change < 0 {
+                        stats.coins_stolen += (-change) as u32;
+                    }
+                }
+            }
+            Event::PlayerEliminated { player } => {
+                let turn = state.turn;
+                self.player(player).eliminated_on_turn = Some(turn);
+            }
+            Event::GameOver { winner: Some(winner) } => self.player(winner).won = true,
+            _ => {}
+        }
+    }
+}
+
+enum Output {
+    Csv(Box<csv::Writer<BufWriter<File>>>),
+    Json(BufWriter<File>),
+}
+
+/// Streams rows out to a file as games finish
+pub struct StatsWriter {
+    output: Output,
+    rows_written: u64,
+}
+
+impl StatsWriter {
+    pub fn create(path: &Path, format: StatsFormat) -> Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        let output = match format {
+            StatsFormat::Csv => Output::Csv(Box::new(csv::Writer::from_writer(file))),
+            StatsFormat::Json => {
+                write!(file, "[")?;
+                Output::Json(file)
+            }
+        };
+        Ok(StatsWriter {
+            output,
+            rows_written: 0,
+        })
+    }
+
+    pub fn write(&mut self, rows: &[PlayerStats]) -> Result<()> {
+        for row in rows {
+            match &mut self.output {
+                Output::Csv(csv) => csv.serialize(row)?,
+                Output::Json(json) => {
+                    if self.rows_written > 0 {
+                        write!(json, ",")?;
+                    }
+                    write!(json, "\n  {}", serde_json::to_string(row)?)?;
+                }
+            }
+            self.rows_written += 1;
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<()> {
+        match self.output {
+            Output::Csv(mut csv) => csv.flush()?,
+            Output::Json(mut json) => {
+                writeln!(json, "\n]")?;
+                json.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::Rules;
+    use crate::simulate::{play_game, seat_players, BotKind, Limits};
+    use crate::player::random_player::RandomWeights;
+    use serde_json::Value;
+    use std::cell::RefCell;
+    use std::fs;
+    use std::rc::Rc;
+
+    #[test]
+    fn stats_agree_with_the_result_and_survive_being_written_out() {
+        let seats = [BotKind::Random, BotKind::Honest, BotKind::Random, BotKind::Dumb];
+        let limits = Limits {
+            max_turns: 1000,
+            max_repeats: None,
+            decision_clock: None,
+        };
+        let collector = Rc::new(RefCell::new(StatsCollector::new(3)));
+        let players = seat_players(&seats, &RandomWeights::default());
+        let result = play_game(players, Rules::default(), limits, 21, Some(Box::new(collector.clone()))).unwrap();
+        let rows = collector.borrow().rows();
+
+        assert_eq!(rows.len(), seats.len());
+        assert!(result.winner.is_some());
+        for (seat, row) in rows.iter().enumerate() {
+            let id = PlayerID(seat as u8);
+            assert_eq!((row.game, row.seat), (3, seat as u8));
+            assert_eq!(row.won, result.winner.as_ref() == Some(&id));
+            assert_eq!(row.eliminated_on_turn.is_some(), result.eliminated.contains(&id));
+            assert!(row.eliminated_on_turn.is_none_or(|turn| turn <= result.turns));
+            assert!(row.bluffs <= row.claims && row.block_bluffs <= row.blocks);
+            assert!(row.challenges_won <= row.challenges);
+        }
+        // Every turn is one action, bar the turn that was under way when the game ended
+        let actions: u32 = rows
+            .iter()
+            .map(|row| row.income + row.foreign_aid + row.tax + row.steal + row.assassinate + row.coup + row.exchange)
+            .sum();
+        assert!(actions > 0 && actions.abs_diff(result.turns) <= 1, "{} actions in {} turns", actions, result.turns);
+        // Coins taken by a steal went to whoever stole them
+        let stolen: u32 = rows.iter().map(|row| row.coins_stolen).sum();
+        assert!(stolen <= rows.iter().map(|row| row.coins_earned).sum());
+
+        let path = |format: &str| std::env::temp_dir().join(format!("coup-stats-{}.{}", std::process::id(), format));
+        for format in [StatsFormat::Csv, StatsFormat::Json] {
+            let file = path(&format.to_string());
+            let mut writer = StatsWriter::create(&file, format).unwrap();
+            writer.write(&rows[..1]).unwrap();
+            writer.write(&rows[1..]).unwrap();
+            writer.finish().unwrap();
+            let written = fs::read_to_string(&file).unwrap();
+            fs::remove_file(&file).unwrap();
+
+            let read: Vec<Value> = match format {
+                StatsFormat::Json => serde_json::from_str(&written).unwrap(),
+                StatsFormat::Csv => {
+                    let mut reader = csv::Reader::from_reader(written.as_bytes());
+                    let headers = reader.headers().unwrap().clone();
+                    reader
+                        .records()
+                        .map(|record| {
+                            let record = record.unwrap();
+                            headers.iter().zip(record.iter()).map(|(name, value)| (name, Value::from(value))).collect()
+                        })
+                        .collect()
+                }
+            };
+            assert_eq!(read.len(), rows.len(), "{}", format);
+            for (row, read) in rows.iter().zip(&read) {
+                let winner = match format {
+                    StatsFormat::Json => Value::from(row.won),
+                    StatsFormat::Csv => Value::from(row.won.to_string()),
+                };
+                assert_eq!(read["won"], winner, "{}", format);
+                assert_eq!(read["name"], Value::from(row.name.clone()), "{}", format);
+            }
+            if format == StatsFormat::Json {
+                assert_eq!(Value::Array(read), serde_json::to_value(&rows).unwrap());
+            }
+        }
+    }
+}
@@ -0,0 +1,597 @@
+//! Playing a game one decision at a time.
+//!
+//! `next_decision` runs the game forward until a seat has something to decide, and says which
+//! seat, what about, and which choices the rules allow. `submit` makes that decision and runs
+//! the game on to the next one. `Game::play` is this loop with every decision put to the player
+//! in the seat, but anything that would rather hold the decisions itself, like a server, a UI or
+//! a training environment, can drive the game directly.
+
+use crate::action::Action;
+use crate::event::Event;
+use crate::seat::{self, Choice};
+use crate::view::{PendingDecision, PlayerView};
+use crate::{Game, GameResult, Identity, PlayerID};
+use anyhow::{anyhow, Result};
+use std::cmp::min;
+
+/// A decision the game is waiting on
+#[derive(Debug, Clone)]
+pub struct Decision {
+    pub seat: PlayerID,
+    pub pending: PendingDecision,
+    /// Every choice the rules allow, the safe default first
+    pub options: Vec<Choice>,
+}
+
+/// How far a game has got, so it can stop at a decision and pick up again once it's made
+#[derive(Default)]
+pub struct Progress {
+    phase: Phase,
+    waiting: Option<Decision>,
+    // Everyone still in when the round started, in turn order
+    round: Vec<PlayerID>,
+    next_in_round: usize,
+    result: Option<GameResult>,
+}
+
+#[derive(Default)]
+enum Phase {
+    // The cards haven't been dealt
+    #[default]
+    Start,
+    NextTurn,
+    // The player whose turn it is picks an action
+    Acting { actor: PlayerID },
+    // Going round the table for anyone who'll block, from the given place in the round
+    Blocking { actor: PlayerID, action: Action, next: usize },
+    // Going round the table for anyone who'll challenge the claim, from the given challenger
+    Challenging {
+        actor: PlayerID,
+        action: Action,
+        claim: Claim,
+        challengers: Vec<PlayerID>,
+        next: usize,
+    },
+    // A player picks the card they lose
+    Losing { player: PlayerID, then: AfterLoss },
+    // The action goes through, unless it was stopped
+    Resolving { actor: PlayerID, action: Action, blocked: bool },
+    // Cards are drawn one at a time during an exchange, and each can be swapped into the hand
+    Exchanging {
+        actor: PlayerID,
+        drawn: Vec<Identity>,
+        returned: Vec<Identity>,
+        left: u8,
+    },
+    EndingTurn,
+    Finishing,
+    Over,
+}
+
+/// What is being challenged: the action, or a block of it
+enum Claim {
+    Action,
+    Block { blocker: PlayerID, block: Action },
+}
+
+/// What happens once a card has been lost
+enum AfterLoss {
+    // Someone lost a challenge, which settles whether the action is stopped
+    Challenge {
+        actor: PlayerID,
+        action: Action,
+        claim: Claim,
+        challenger_won: bool,
+    },
+    // The target of an assassination or coup lost their card, which is the end of the action
+    Action { actor: PlayerID, action: Action },
+}
+
+impl Game {
+    /// Runs the game until a seat has a decision to make, and returns it. None once the game
+    /// is over.
+    pub fn next_decision(&mut self) -> Option<Decision> {
+        if self.progress.waiting.is_none() {
+            self.advance(None);
+        }
+        self.progress.waiting.clone()
+    }
+
+    /// Makes the decision the game is waiting on, which must be one of its options, and runs
+    /// the game on to the next one
+    pub fn submit(&mut self, choice: Choice) -> Result<()> {
+        let decision = self
+            .progress
+            .waiting
+            .as_ref()
+            .ok_or_else(|| anyhow!("The game isn't waiting on a decision"))?;
+        if !decision.options.contains(&choice) {
+            return Err(anyhow!(
+                "{} can't choose {:?}",
+                self.get_player_name(&decision.seat),
+                choice
+            ));
+        }
+        self.progress.waiting = None;
+        self.advance(Some(choice));
+        Ok(())
+    }
+
+    /// How the game turned out, once it's over
+    pub fn result(&self) -> Option<&GameResult> {
+        self.progress.result.as_ref()
+    }
+
+    /// Everything the seat is allowed to see while making the given decision
+    pub fn view(&self, seat: &PlayerID, pending: PendingDecision) -> PlayerView<'_> {
+        PlayerView::new(
+            &self.state,
+            seat,
+            self.driver.hands.get(seat).unwrap().clone(),
+            self.driver.field.deck.len(),
+            pending,
+        )
+    }
+
+    // Steps through the game until it needs a decision or ends. The choice, if any, answers
+    // the decision the game stopped at.
+    fn advance(&mut self, mut choice: Option<Choice>) {
+        while self.progress.waiting.is_none() {
+            let phase = std::mem::replace(&mut self.progress.phase, Phase::Over);
+            if let Phase::Over = phase {
+                return;
+            }
+            self.progress.phase = self.step(phase, choice.take());
+        }
+    }
+
+    // Stops the game until the seat decides
+    fn request(&mut self, seat: &PlayerID, pending: PendingDecision) {
+        let options = seat::legal_choices(&self.view(seat, pending.clone()));
+        self.progress.waiting = Some(Decision {
+            seat: seat.clone(),
+            pending,
+            options,
+        });
+    }
+
+    // Carries out a phase, and returns the one after it. A phase that needs a decision asks
+    // for it and returns itself, to be stepped again with the answer.
+    fn step(&mut self, phase: Phase, choice: Option<Choice>) -> Phase {
+        match phase {
+            Phase::Start => {
+                self.setup();
+                Phase::NextTurn
+            }
+            Phase::NextTurn => self.next_turn(),
+            Phase::Acting { actor } => match choice {
+                None => {
+                    // Enforce Required Coup
+                    let num_coins = self.state.player_states.get(&actor).unwrap().num_coins;
+                    let pending = if num_coins < self.state.rules.required_coup_coins {
+                        PendingDecision::ChooseAction
+                    } else {
+                        PendingDecision::ForcedCoup
+                    };
+                    self.request(&actor, pending);
+                    Phase::Acting { actor }
+                }
+                Some(Choice::Action(action)) => {
+                    // The player may have forfeited instead of choosing
+                    if !self.is_player_alive(&actor) {
+                        return Phase::EndingTurn;
+                    }
+                    self.emit(Event::ActionChosen {
+                        actor: actor.clone(),
+                        action: action.clone(),
+                    });
+                    Phase::Blocking { actor, action, next: 0 }
+                }
+                Some(choice) => panic!("Expected an action, got {:?}", choice),
+            },
+            Phase::Blocking { actor, action, next } => match choice {
+                None => {
+                    // Don't block yourself, and the dead can't block
+                    let blocker = self.progress.round[next..].iter().position(|blocker| {
+                        blocker != &actor && self.is_player_alive(blocker) && action.blockable(blocker).is_some()
+                    });
+                    match blocker {
+                        Some(offset) => {
+                            let blocker = self.progress.round[next + offset].clone();
+                            let pending = PendingDecision::Block {
+                                actor: actor.clone(),
+                                action: action.clone(),
+                            };
+                            self.request(&blocker, pending);
+                            Phase::Blocking {
+                                actor,
+                                action,
+                                next: next + offset,
+                            }
+                        }
+                        None if action.challengable() => self.open_challenges(actor, action, Claim::Action),
+                        None => Phase::Resolving {
+                            actor,
+                            action,
+                            blocked: false,
+                        },
+                    }
+                }
+                Some(Choice::Block(None)) => Phase::Blocking {
+                    actor,
+                    action,
+                    next: next + 1,
+                },
+                Some(Choice::Block(Some(block))) => {
+                    let blocker = self.progress.round[next].clone();
+                    self.wait_if_interactive();
+                    self.emit(Event::BlockDeclared {
+                        blocker: blocker.clone(),
+                        actor: actor.clone(),
+                        action: action.clone(),
+                        block: block.clone(),
+                    });
+                    // Once blocked, only the block can be challenged
+                    self.open_challenges(actor, action, Claim::Block { blocker, block })
+                }
+                Some(choice) => panic!("Expected a block, got {:?}", choice),
+            },
+            Phase::Challenging {
+                actor,
+                action,
+                claim,
+                challengers,
+                next,
+            } => {
+                let (claimant, claimed) = match &claim {
+                    Claim::Action => (actor.clone(), action.clone()),
+                    Claim::Block { blocker, block } => (blocker.clone(), block.clone()),
+                };
+                match choice {
+                    // Can't challenge yourself
+                    None => match challengers[next..].iter().position(|challenger| challenger != &claimant) {
+                        Some(offset) => {
+                            let challenger = challengers[next + offset].clone();
+                            let pending = PendingDecision::Challenge {
+                                actor: claimant,
+                                action: claimed,
+                            };
+                            self.request(&challenger, pending);
+                            Phase::Challenging {
+                                actor,
+                                action,
+                                claim,
+                                challengers,
+                                next: next + offset,
+                            }
+                        }
+                        // An unchallenged block stops the action
+                        None => {
+                            let blocked = matches!(claim, Claim::Block { .. });
+                            Phase::Resolving { actor, action, blocked }
+                        }
+                    },
+                    Some(Choice::Challenge(false)) => Phase::Challenging {
+                        actor,
+                        action,
+                        claim,
+                        challengers,
+                        next: next + 1,
+                    },
+                    Some(Choice::Challenge(true)) => {
+                        let challenger = challengers[next].clone();
+                        let challenger_won = self.settle_challenge(&claimant, &challenger, &claimed);
+                        let loser = if challenger_won { claimant } else { challenger };
+                        Phase::Losing {
+                            player: loser,
+                            then: AfterLoss::Challenge {
+                                actor,
+                                action,
+                                claim,
+                                challenger_won,
+                            },
+                        }
+                    }
+                    Some(choice) => panic!("Expected a challenge, got {:?}", choice),
+                }
+            }
+            Phase::Losing { player, then } => match choice {
+                None => {
+                    let num_lives_left = self.state.player_states.get(&player).unwrap().num_lives;
+                    if num_lives_left == 0 {
+                        self.logger.log(format!(
+                            "Tried to kill {} but they have no lives left!",
+                            self.get_player_name(&player)
+                        ));
+                        return self.after_loss(then);
+                    }
+                    self.request(&player, PendingDecision::LoseCard);
+                    Phase::Losing { player, then }
+                }
+                Some(Choice::LoseCard(index)) => {
+                    // A player who has already forfeited has nothing left to lose
+                    if self.is_player_alive(&player) {
+                        self.reveal_card(&player, index);
+                    }
+                    self.after_loss(then)
+                }
+                Some(choice) => panic!("Expected a card to lose, got {:?}", choice),
+            },
+            Phase::Resolving { actor, action, blocked } => {
+                if blocked || !self.is_player_alive(&actor) {
+                    return Phase::EndingTurn;
+                }
+                self.process_action(actor, action)
+            }
+            Phase::Exchanging {
+                actor,
+                mut drawn,
+                mut returned,
+                left,
+            } => match choice {
+                None => {
+                    if left == 0 || self.driver.field.deck.is_empty() {
+                        self.driver.field.deck.extend(returned.iter().cloned());
+                        self.shuffle();
+                        self.emit(Event::CardsExchanged {
+                            player: actor.clone(),
+                            drawn,
+                            returned,
+                        });
+                        self.emit(Event::ActionResolved {
+                            actor,
+                            action: Action::Exchange,
+                        });
+                        return Phase::EndingTurn;
+                    }
+                    let card = self.driver.field.deck.remove(0);
+                    drawn.push(card);
+                    self.request(&actor, PendingDecision::ReplaceCard { card });
+                    Phase::Exchanging {
+                        actor,
+                        drawn,
+                        returned,
+                        left: left - 1,
+                    }
+                }
+                Some(Choice::ReplaceCard(to_replace)) => {
+                    let card = *drawn.last().unwrap();
+                    let hand = self.driver.hands.get_mut(&actor).unwrap();
+                    match to_replace {
+                        Some(index) if index < hand.len() => {
+                            returned.push(std::mem::replace(&mut hand[index], card));
+                        }
+                        _ => returned.push(card),
+                    }
+                    Phase::Exchanging {
+                        actor,
+                        drawn,
+                        returned,
+                        left,
+                    }
+                }
+                Some(choice) => panic!("Expected a card to replace, got {:?}", choice),
+            },
+            Phase::EndingTurn => {
+                let turn_order = self.state.turn_order.clone();
+                self.update_active_players(&turn_order);
+                if self.game_over(&turn_order) {
+                    Phase::Finishing
+                } else {
+                    Phase::NextTurn
+                }
+            }
+            Phase::Finishing => {
+                self.wait_if_interactive();
+                self.progress.result = Some(self.present_game_results());
+                Phase::Over
+            }
+            Phase::Over => Phase::Over,
+        }
+    }
+
+    // Starts the next player's turn, starting a new round if need be
+    fn next_turn(&mut self) -> Phase {
+        loop {
+            if self.progress.next_in_round >= self.progress.round.len() {
+                let turn_order = self.state.turn_order.clone();
+                if self.game_over(&turn_order) || self.out_of_turns() {
+                    return Phase::Finishing;
+                }
+                self.progress.round = self.active_players(&turn_order);
+                self.progress.next_in_round = 0;
+            }
+            let actor = self.progress.round[self.progress.next_in_round].clone();
+            self.progress.next_in_round += 1;
+            // Players knocked out earlier in the round don't get a turn
+            if !self.is_player_alive(&actor) {
+                continue;
+            }
+            self.record_position(&actor);
+            if self.out_of_turns() {
+                return Phase::Finishing;
+            }
+            self.state.turn += 1;
+            self.emit(Event::TurnStarted { player: actor.clone() });
+            self.wait_if_interactive();
+            return Phase::Acting { actor };
+        }
+    }
+
+    // Everyone still in gets the chance to challenge, in turn order
+    fn open_challenges(&self, actor: PlayerID, action: Action, claim: Claim) -> Phase {
+        Phase::Challenging {
+            actor,
+            action,
+            claim,
+            challengers: self.active_players(&self.state.turn_order),
+            next: 0,
+        }
+    }
+
+    // Reveals whether the claimant could do what they claimed. Returns whether the challenger
+    // was right.
+    fn settle_challenge(&mut self, claimant: &PlayerID, challenger: &PlayerID, claimed: &Action) -> bool {
+        self.wait_if_interactive();
+        self.emit(Event::ChallengeDeclared {
+            challenger: challenger.clone(),
+            challenged: claimant.clone(),
+            action: claimed.clone(),
+        });
+        let challenger_won = !claimed.can_be_done_with(self.driver.hands.get(claimant).unwrap());
+
+        self.wait_if_interactive();
+        self.emit(Event::ChallengeResolved {
+            challenger: challenger.clone(),
+            challenged: claimant.clone(),
+            action: claimed.clone(),
+            challenger_won,
+        });
+        self.wait_if_interactive();
+        // TODO - Give winner a card from the deck
+        challenger_won
+    }
+
+    fn after_loss(&mut self, then: AfterLoss) -> Phase {
+        match then {
+            // A successful challenge calls the action off, and a failed one calls off the block
+            AfterLoss::Challenge {
+                actor,
+                action,
+                claim: Claim::Action,
+                challenger_won,
+            } => Phase::Resolving {
+                actor,
+                action,
+                blocked: challenger_won,
+            },
+            AfterLoss::Challenge {
+                actor,
+                action,
+                claim: Claim::Block { .. },
+                challenger_won,
+            } => Phase::Resolving {
+                actor,
+                action,
+                blocked: !challenger_won,
+            },
+            AfterLoss::Action { actor, action } => {
+                self.emit(Event::ActionResolved { actor, action });
+                Phase::EndingTurn
+            }
+        }
+    }
+
+    fn process_action(&mut self, actor: PlayerID, action: Action) -> Phase {
+        let rules = self.state.rules;
+        match &action {
+            Action::Income => {
+                let player = self.state.player_states.get_mut(&actor).unwrap();
+                player.num_coins += rules.income;
+            }
+            Action::ForeignAid => {
+                let player = self.state.player_states.get_mut(&actor).unwrap();
+                player.num_coins += rules.foreign_aid;
+            }
+            Action::Tax => {
+                let player = self.state.player_states.get_mut(&actor).unwrap();
+                player.num_coins += rules.tax;
+            }
+            Action::Steal(target) => {
+                let target = self.state.player_states.get_mut(target).unwrap();
+                let coins_to_steal = min(target.num_coins, rules.steal_amount);
+                target.num_coins -= coins_to_steal;
+                let player = self.state.player_states.get_mut(&actor).unwrap();
+                player.num_coins += coins_to_steal;
+            }
+            // TODO Trying a blocked assassination should still result in side effect
+            Action::Assassinate(target) => {
+                let player = self.state.player_states.get_mut(&actor).unwrap();
+                player.num_coins = player.num_coins.saturating_sub(rules.assassinate_cost);
+                let player = target.clone();
+                return Phase::Losing {
+                    player,
+                    then: AfterLoss::Action { actor, action },
+                };
+            }
+            Action::Coup(target) => {
+                let player = self.state.player_states.get_mut(&actor).unwrap();
+                player.num_coins = player.num_coins.saturating_sub(rules.coup_cost);
+                let player = target.clone();
+                return Phase::Losing {
+                    player,
+                    then: AfterLoss::Action { actor, action },
+                };
+            }
+            Action::Exchange => {
+                return Phase::Exchanging {
+                    actor,
+                    drawn: Vec::new(),
+                    returned: Vec::new(),
+                    left: rules.exchange_cards,
+                };
+            }
+            _ => {
+                self.logger
+                    .log(format!("Unknown action... Moving on {:?}", action));
+                return Phase::EndingTurn;
+            }
+        }
+        self.emit(Event::ActionResolved { actor, action });
+        Phase::EndingTurn
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::Rules;
+    use crate::{LoggerType, PlayerConfig, PlayerType};
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    fn game(seed: u64) -> Game {
+        let players = ["Ana", "Bea", "Cy"]
+            .iter()
+            .map(|name| PlayerConfig::new(PlayerType::DumbCPU, name.to_string()))
+            .collect();
+        Game::new(Rules::default(), players, LoggerType::Quiet, seed).unwrap()
+    }
+
+    #[test]
+    fn games_can_be_driven_a_decision_at_a_time() {
+        let mut game = game(5);
+        let mut rng = ChaCha8Rng::seed_from_u64(5);
+        let mut decisions = 0;
+        while let Some(decision) = game.next_decision() {
+            // Asking again doesn't move the game on
+            assert_eq!(game.next_decision().unwrap().seat, decision.seat);
+            assert_eq!(game.view(&decision.seat, decision.pending.clone()).me, decision.seat);
+
+            let choice = decision.options[rng.gen_range(0, decision.options.len())].clone();
+            // Nobody ever holds ten cards
+            assert!(game.submit(Choice::LoseCard(9)).is_err());
+            game.submit(choice).unwrap();
+            decisions += 1;
+            assert!(decisions < 10_000, "The game never ended");
+        }
+        assert!(game.result().is_some());
+        assert!(game.submit(Choice::Challenge(false)).is_err());
+        assert!(game.next_decision().is_none());
+    }
+
+    #[test]
+    fn held_seats_are_handed_back_and_the_rest_played_as_before() {
+        let held = PlayerID(1);
+        let mut stepped = game(8);
+        while let Some(decision) = stepped.play_until(|seat| *seat == held) {
+            assert_eq!(decision.seat, held);
+            // Answered as the seat's own player would have, so the game is the one play plays
+            let choice = stepped.ask(&decision);
+            stepped.submit(choice).unwrap();
+        }
+        let played = game(8).play();
+        let result = stepped.result().unwrap();
+        assert_eq!((&result.winner, &result.eliminated, result.turns), (&played.winner, &played.eliminated, played.turns));
+    }
+}
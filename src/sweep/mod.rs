@@ -0,0 +1,337 @@
+//! Grid sweeps over the rules, to see how house rules change the balance of the game.
+//!
+//! Every combination of the listed values is played for the same number of games, with the
+//! seats rotated one place each game so every bot sits in every position equally often. Each
+//! configuration is played with the same per-game seeds, so differences between rows come from
+//! the rules rather than from the luck of the deal.
+
+use crate::player::random_player::RandomWeights;
+use crate::rules::Rules;
+use crate::simulate::{game_seed, mean_with_margin, play_game, seat_kinds, seat_players, BotKind, CpuSpec, Limits};
+use crate::{GameResult, Identity, PlayerID};
+use anyhow::{anyhow, Result};
+use enumset::EnumSet;
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct SweepConfig {
+    /// Games played under each configuration
+    #[structopt(long, default_value = "500")]
+    games: u32,
+    /// The bots at the table, as TYPE:COUNT. Types are random, honest and dumb
+    #[structopt(long, value_delimiter = ",", default_value = "random:2,honest:2")]
+    cpus: Vec<CpuSpec>,
+    /// Starting coins to try. Values not swept come from the game's own options
+    #[structopt(long, value_delimiter = ",")]
+    starting_coins: Vec<u8>,
+    /// Coin counts that force a coup to try
+    #[structopt(long, value_delimiter = ",")]
+    required_coup_coins: Vec<u8>,
+    /// Coup costs to try
+    #[structopt(long, value_delimiter = ",")]
+    coup_cost: Vec<u8>,
+    /// Assassination costs to try
+    #[structopt(long, value_delimiter = ",")]
+    assassinate_cost: Vec<u8>,
+    /// Steal amounts to try
+    #[structopt(long, value_delimiter = ",")]
+    steal_amount: Vec<u8>,
+    /// Copies of each identity in the deck to try
+    #[structopt(long, value_delimiter = ",")]
+    cards_per_identity: Vec<u8>,
+    /// A set of identities to play with, e.g. "Duke,Captain,Contessa". Can be given more than once
+    #[structopt(long, number_of_values = 1, parse(try_from_str = parse_identities))]
+    identities: Vec<EnumSet<Identity>>,
+    /// Games still going after this many turns are called off without a winner
+    #[structopt(long, default_value = "1000")]
+    max_turns: u32,
+    /// Games that come back to the same position this many times are called off without a
+    /// winner. Positions aren't tracked if not given
+    #[structopt(long)]
+    max_repeats: Option<u32>,
+    /// Seed for the whole sweep. A random one is picked (and printed) if not given
+    #[structopt(long)]
+    seed: Option<u64>,
+    /// Threads to play on. Defaults to one per core
+    #[structopt(long)]
+    threads: Option<usize>,
+}
+
+impl SweepConfig {
+    fn limits(&self) -> Limits {
+        Limits {
+            max_turns: self.max_turns,
+            max_repeats: self.max_repeats,
+            decision_clock: None,
+        }
+    }
+}
+
+fn parse_identities(list: &str) -> Result<EnumSet<Identity>> {
+    list.split(',')
+        .map(|identity| identity.trim().parse::<Identity>().map_err(|e| anyhow!(e)))
+        .collect()
+}
+
+/// How the games under one configuration went
+#[derive(Debug)]
+struct Outcome {
+    games: u64,
+    draws: u64,
+    // Wins by position at the table, first to act first
+    position_wins: Vec<u64>,
+    // Wins by seat as configured, whichever position it was rotated to
+    bot_wins: Vec<u64>,
+    turns: u64,
+    turns_squared: u64,
+}
+
+impl Outcome {
+    fn new(num_seats: usize) -> Self {
+        Outcome {
+            games: 0,
+            draws: 0,
+            position_wins: vec![0; num_seats],
+            bot_wins: vec![0; num_seats],
+            turns: 0,
+            turns_squared: 0,
+        }
+    }
+
+    fn add(&mut self, rotation: usize, result: GameResult) {
+        self.games += 1;
+        match result.winner {
+            Some(PlayerID(position)) => {
+                let position = position as usize;
+                let num_seats = self.bot_wins.len();
+                self.position_wins[position] += 1;
+                self.bot_wins[(position + rotation) % num_seats] += 1;
+            }
+            None => self.draws += 1,
+        }
+        let turns = u64::from(result.turns);
+        self.turns += turns;
+        self.turns_squared += turns * turns;
+    }
+
+    // Percentage points between the luckiest and unluckiest position at the table
+    fn seat_spread(&self) -> f64 {
+        spread(self.position_wins.iter().map(|&wins| self.win_rate(wins, 1)))
+    }
+
+    // Percentage points between the best and worst kind of bot, per seat played
+    fn bot_spread(&self, seats: &[BotKind]) -> f64 {
+        let mut kinds: Vec<BotKind> = Vec::new();
+        for kind in seats {
+            if !kinds.contains(kind) {
+                kinds.push(*kind);
+            }
+        }
+        spread(kinds.iter().map(|kind| {
+            let (count, wins) = seats
+                .iter()
+                .zip(&self.bot_wins)
+                .filter(|(seat, _)| *seat == kind)
+                .fold((0, 0), |(count, total), (_, wins)| (count + 1, total + wins));
+            self.win_rate(wins, count)
+        }))
+    }
+
+    fn win_rate(&self, wins: u64, seats: u64) -> f64 {
+        100.0 * wins as f64 / (self.games * seats) as f64
+    }
+}
+
+fn spread(rates: impl Iterator<Item = f64>) -> f64 {
+    let (low, high) = rates.fold((f64::MAX, f64::MIN), |(low, high), rate| (low.min(rate), high.max(rate)));
+    high - low
+}
+
+/// Plays every configuration in the grid and prints a row for each
+pub fn run(config: &SweepConfig, base: Rules, weights: &RandomWeights) -> Result<()> {
+    let seats = seat_kinds(&config.cpus);
+    if seats.len() < 2 {
+        return Err(anyhow!("A sweep needs at least two bots at the table"));
+    }
+    if config.games == 0 {
+        return Err(anyhow!("Nothing to sweep"));
+    }
+    let grid = grid(config, base);
+    let seed = config.seed.unwrap_or_else(rand::random);
+    let mut pool = ThreadPoolBuilder::new();
+    if let Some(threads) = config.threads {
+        pool = pool.num_threads(threads);
+    }
+    let pool = pool.build()?;
+
+    println!("Seed: {}", seed);
+    println!("{} configurations of {} games each", grid.len(), config.games);
+    println!();
+    println!(
+        "{:>5} {:>6} {:>5} {:>6} {:>5} {:>5} {:>6} {:>13} {:>6} {:>6}  Identities",
+        "Coins", "Forced", "Coup", "Assass", "Steal", "Cards", "Draws", "Length", "Seat±", "Bot±"
+    );
+    for rules in grid {
+        let cards = rules
+            .cards_per_identity
+            .map_or_else(|| "auto".to_string(), |cards| cards.to_string());
+        let identities: Vec<String> = rules.identities.iter().map(|identity| format!("{:?}", identity)).collect();
+        print!(
+            "{:>5} {:>6} {:>5} {:>6} {:>5} {:>5} ",
+            rules.starting_coins,
+            rules.required_coup_coins,
+            rules.coup_cost,
+            rules.assassinate_cost,
+            rules.steal_amount,
+            cards
+        );
+        if let Err(e) = rules.check(seats.len()) {
+            println!("{:<35}  {}", format!("skipped: {}", e), identities.join(","));
+            continue;
+        }
+
+        let games = u64::from(config.games);
+        let results: Vec<Result<GameResult>> = pool.install(|| {
+            (0..games)
+                .into_par_iter()
+                .map(|game| {
+                    let mut players = seat_players(&seats, weights);
+                    players.rotate_left(game as usize % seats.len());
+                    play_game(players, rules, config.limits(), game_seed(seed, game), None)
+                })
+                .collect()
+        });
+        let mut outcome = Outcome::new(seats.len());
+        for (game, result) in results.into_iter().enumerate() {
+            outcome.add(game % seats.len(), result?);
+        }
+
+        let (length, margin) = mean_with_margin(outcome.games, outcome.turns, outcome.turns_squared);
+        println!(
+            "{:>6} {:>13} {:>6.1} {:>6.1}  {}",
+            outcome.draws,
+            format!("{:.1} ± {:.1}", length, margin),
+            outcome.seat_spread(),
+            outcome.bot_spread(&seats),
+            identities.join(",")
+        );
+    }
+    Ok(())
+}
+
+// Every combination of the swept values, on top of the base rules
+fn grid(config: &SweepConfig, base: Rules) -> Vec<Rules> {
+    let or_base = |values: &[u8], base: u8| if values.is_empty() { vec![base] } else { values.to_vec() };
+    let cards: Vec<Option<u8>> = if config.cards_per_identity.is_empty() {
+        vec![base.cards_per_identity]
+    } else {
+        config.cards_per_identity.iter().cloned().map(Some).collect()
+    };
+    let identities = if config.identities.is_empty() {
+        vec![base.identities]
+    } else {
+        config.identities.clone()
+    };
+
+    let mut grid = Vec::new();
+    for &identities in &identities {
+        for &cards_per_identity in &cards {
+            for starting_coins in or_base(&config.starting_coins, base.starting_coins) {
+                for required_coup_coins in or_base(&config.required_coup_coins, base.required_coup_coins) {
+                    for coup_cost in or_base(&config.coup_cost, base.coup_cost) {
+                        for assassinate_cost in or_base(&config.assassinate_cost, base.assassinate_cost) {
+                            for steal_amount in or_base(&config.steal_amount, base.steal_amount) {
+                                grid.push(Rules {
+                                    identities,
+                                    cards_per_identity,
+                                    starting_coins,
+                                    required_coup_coins,
+                                    coup_cost,
+                                    assassinate_cost,
+                                    steal_amount,
+                                    ..base
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    grid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn won_by(position: Option<u8>, turns: u32) -> GameResult {
+        GameResult {
+            winner: position.map(PlayerID),
+            eliminated: Vec::new(),
+            turns,
+            timeouts: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn the_grid_is_every_combination_on_top_of_the_base_rules() {
+        let args = ["sweep", "--starting-coins", "1,3", "--coup-cost", "5,7", "--identities", "Duke,Captain"];
+        let config = SweepConfig::from_iter_safe(&args).unwrap();
+        let base = Rules {
+            steal_amount: 3,
+            ..Rules::default()
+        };
+        let rule_sets = grid(&config, base);
+        assert_eq!(rule_sets.len(), 4);
+        for &(starting_coins, coup_cost) in &[(1, 5), (1, 7), (3, 5), (3, 7)] {
+            assert!(rule_sets
+                .iter()
+                .any(|rules| (rules.starting_coins, rules.coup_cost) == (starting_coins, coup_cost)));
+        }
+        for rules in &rule_sets {
+            assert_eq!(rules.identities, Identity::Duke | Identity::Captain);
+            assert_eq!(rules.steal_amount, 3);
+            assert_eq!(rules.cards_per_identity, base.cards_per_identity);
+        }
+
+        // Sweeping nothing plays the base rules alone
+        let config = SweepConfig::from_iter_safe(&["sweep"]).unwrap();
+        assert_eq!(grid(&config, base), vec![base]);
+    }
+
+    #[test]
+    fn wins_are_credited_to_the_bot_rotated_into_the_position() {
+        let mut outcome = Outcome::new(3);
+        // Rotated one place, the second bot acts first
+        outcome.add(1, won_by(Some(0), 10));
+        // Rotated two places, the second bot acts last
+        outcome.add(2, won_by(Some(2), 20));
+        outcome.add(0, won_by(None, 30));
+        assert_eq!((outcome.games, outcome.draws), (3, 1));
+        assert_eq!(outcome.position_wins, vec![1, 0, 1]);
+        assert_eq!(outcome.bot_wins, vec![0, 2, 0]);
+        assert_eq!((outcome.turns, outcome.turns_squared), (60, 1400));
+    }
+
+    #[test]
+    fn spreads_run_from_the_worst_win_rate_to_the_best() {
+        let mut outcome = Outcome::new(4);
+        let games = [(0, 0), (0, 0), (0, 0), (0, 0), (1, 0), (0, 1), (0, 2), (3, 2), (0, 3), (1, 3)];
+        for &(rotation, position) in &games {
+            outcome.add(rotation, won_by(Some(position), 10));
+        }
+        assert_eq!(outcome.position_wins, vec![5, 1, 2, 2]);
+        assert_eq!(outcome.bot_wins, vec![5, 3, 1, 1]);
+        // The first position won half the games, the second a tenth
+        assert!((outcome.seat_spread() - 40.0).abs() < 1e-9);
+        // The random bots won 8 of the 20 seats they played, the honest ones 2
+        let seats = [BotKind::Random, BotKind::Random, BotKind::Honest, BotKind::Honest];
+        assert!((outcome.bot_spread(&seats) - 30.0).abs() < 1e-9);
+        // With one kind of bot at the table there's nothing between them
+        assert_eq!(outcome.bot_spread(&[BotKind::Dumb; 4]), 0.0);
+    }
+}
@@ -0,0 +1,402 @@
+//! Ranks a roster of bots by playing them against each other at tables of mixed sizes.
+//!
+//! Every table is played once in each rotation of its seats, so no bot gets the best seat more
+//! often than the others. Games within a round run in parallel, but ratings are always updated
+//! in the same order, so a seed gives the same leaderboard whatever the number of threads.
+
+pub mod rating;
+
+use crate::player::random_player::RandomWeights;
+use crate::simulate::{game_seed, play_game, BotKind, Limits};
+use crate::rules::Rules;
+use crate::seat::TimeoutPolicy;
+use crate::{GameResult, PlayerConfig, PlayerID, PlayerType};
+use anyhow::{anyhow, Error, Result};
+use rating::Rating;
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use std::str::FromStr;
+use std::time::Duration;
+use structopt::clap::arg_enum;
+use structopt::StructOpt;
+
+arg_enum! {
+/// How bots are seated together each round
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pairing {
+    // Every combination of bots, at every table size
+    RoundRobin,
+    // Bots with similar ratings play each other
+    Swiss,
+}
+}
+
+#[derive(StructOpt, Debug)]
+pub struct TournamentConfig {
+    /// A bot in the tournament, as TYPE, or "random WEIGHTS" for a weighted random bot (e.g.
+    /// "random tax=3,no_challenge=9"). Can be given more than once. Defaults to random, honest
+    /// and dumb
+    #[structopt(long = "bot", number_of_values = 1)]
+    bots: Vec<BotEntry>,
+    /// How bots are seated together each round
+    #[structopt(long, possible_values = &Pairing::variants(), case_insensitive = true, default_value = "RoundRobin")]
+    pairing: Pairing,
+    /// The table sizes to play at
+    #[structopt(long, value_delimiter = ",", default_value = "2,3")]
+    table_sizes: Vec<usize>,
+    /// Games played at each seating of a table
+    #[structopt(long, default_value = "10")]
+    games: u32,
+    /// The number of rounds. A round robin round plays every table once
+    #[structopt(long, default_value = "1")]
+    rounds: u32,
+    /// Games still going after this many turns are called off without a winner
+    #[structopt(long, default_value = "1000")]
+    max_turns: u32,
+    /// Games that come back to the same position this many times are called off without a
+    /// winner. Positions aren't tracked if not given
+    #[structopt(long)]
+    max_repeats: Option<u32>,
+    /// Seed for the whole tournament. A random one is picked (and printed) if not given
+    #[structopt(long)]
+    seed: Option<u64>,
+    /// Threads to play on. Defaults to one per core
+    #[structopt(long)]
+    threads: Option<usize>,
+    /// How long bots get for each decision, in milliseconds. Unlimited if not given
+    #[structopt(long)]
+    decision_timeout_ms: Option<u64>,
+    /// What happens to a bot that runs out of time
+    #[structopt(long, possible_values = &TimeoutPolicy::variants(), case_insensitive = true, default_value = "SafeDefault")]
+    timeout_policy: TimeoutPolicy,
+}
+
+impl TournamentConfig {
+    fn limits(&self) -> Limits {
+        Limits {
+            max_turns: self.max_turns,
+            max_repeats: self.max_repeats,
+            decision_clock: self
+                .decision_timeout_ms
+                .map(|timeout| (Duration::from_millis(timeout), self.timeout_policy)),
+        }
+    }
+}
+
+/// A bot on the roster
+#[derive(Debug, Clone)]
+pub struct BotEntry {
+    pub name: String,
+    pub player_type: PlayerType,
+}
+
+impl FromStr for BotEntry {
+    type Err = Error;
+
+    fn from_str(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+        let mut parts = spec.splitn(2, char::is_whitespace);
+        let kind: BotKind = parts.next().unwrap_or_default().parse()?;
+        let weights = match (kind, parts.next()) {
+            (BotKind::Random, Some(weights)) => weights.trim().parse()?,
+            (_, Some(_)) => return Err(anyhow!("Only random bots take weights, got '{}'", spec)),
+            (_, None) => RandomWeights::default(),
+        };
+        Ok(BotEntry {
+            name: spec.to_string(),
+            player_type: kind.player_type(&weights),
+        })
+    }
+}
+
+/// How one bot has done so far
+#[derive(Debug, Default, Clone)]
+struct Standing {
+    games: u32,
+    wins: u32,
+    timeouts: u32,
+    rating: Rating,
+}
+
+/// Plays the tournament and prints the leaderboard
+pub fn run(config: &TournamentConfig, rules: Rules) -> Result<()> {
+    let bots = if config.bots.is_empty() {
+        vec!["random".parse()?, "honest".parse()?, "dumb".parse()?]
+    } else {
+        config.bots.clone()
+    };
+    for (index, bot) in bots.iter().enumerate() {
+        if bots[..index].iter().any(|other| other.name == bot.name) {
+            return Err(anyhow!("{} is on the roster twice", bot.name));
+        }
+    }
+    for &size in &config.table_sizes {
+        if size > bots.len() {
+            return Err(anyhow!("Can't fill a table of {} with {} bots", size, bots.len()));
+        }
+        rules.check(size)?;
+        // Swiss sits a bot that would be left on its own at the last table, which has to fit too
+        if config.pairing == Pairing::Swiss && bots.len() > size && bots.len() % size == 1 {
+            rules.check(size + 1)?;
+        }
+    }
+    if config.table_sizes.is_empty() {
+        return Err(anyhow!("No table sizes to play at"));
+    }
+
+    let seed = config.seed.unwrap_or_else(rand::random);
+    let mut pool = ThreadPoolBuilder::new();
+    if let Some(threads) = config.threads {
+        pool = pool.num_threads(threads);
+    }
+    let pool = pool.build()?;
+
+    let mut standings = vec![Standing::default(); bots.len()];
+    let mut games_played = 0;
+    for round in 0..config.rounds {
+        let tables = match config.pairing {
+            Pairing::RoundRobin => round_robin(bots.len(), &config.table_sizes),
+            Pairing::Swiss => swiss(&standings, config.table_sizes[round as usize % config.table_sizes.len()]),
+        };
+        let seatings = seatings(&tables, config.games);
+        let results: Vec<Result<GameResult>> = pool.install(|| {
+            seatings
+                .par_iter()
+                .enumerate()
+                .map(|(index, seating)| {
+                    let players = seating
+                        .iter()
+                        .map(|&bot| PlayerConfig::new(bots[bot].player_type.clone(), bots[bot].name.clone()))
+                        .collect();
+                    let seed = game_seed(seed, games_played + index as u64);
+                    play_game(players, rules, config.limits(), seed, None)
+                })
+                .collect()
+        });
+        games_played += seatings.len() as u64;
+
+        for (seating, result) in seatings.iter().zip(results) {
+            let result = result?;
+            for &bot in seating {
+                standings[bot].games += 1;
+            }
+            if let Some(PlayerID(seat)) = result.winner {
+                standings[seating[seat as usize]].wins += 1;
+            }
+            for (PlayerID(seat), timeouts) in &result.timeouts {
+                standings[seating[*seat as usize]].timeouts += timeouts;
+            }
+            let mut ratings: Vec<Rating> = standings.iter().map(|standing| standing.rating).collect();
+            rating::update(&mut ratings, &finishing_order(seating, &result));
+            for (standing, rating) in standings.iter_mut().zip(ratings) {
+                standing.rating = rating;
+            }
+        }
+    }
+
+    println!("Seed: {}", seed);
+    println!("{} games over {} round(s)", games_played, config.rounds);
+    present(&bots, &standings);
+    Ok(())
+}
+
+// Every combination of bots, at every table size
+fn round_robin(num_bots: usize, table_sizes: &[usize]) -> Vec<Vec<usize>> {
+    let mut tables = Vec::new();
+    for &size in table_sizes {
+        let mut table: Vec<usize> = (0..size).collect();
+        loop {
+            tables.push(table.clone());
+            // Move on to the next combination, in lexicographic order
+            let position = (0..size).rev().find(|&i| table[i] < num_bots - size + i);
+            match position {
+                Some(i) => {
+                    table[i] += 1;
+                    for j in i + 1..size {
+                        table[j] = table[j - 1] + 1;
+                    }
+                }
+                None => break,
+            }
+        }
+    }
+    tables
+}
+
+// Sits bots down with the bots rated closest to them. A bot that would be left on its own
+// joins the last table instead.
+fn swiss(standings: &[Standing], size: usize) -> Vec<Vec<usize>> {
+    let mut by_rating: Vec<usize> = (0..standings.len()).collect();
+    by_rating.sort_by(|&a, &b| {
+        standings[b]
+            .rating
+            .rating
+            .partial_cmp(&standings[a].rating.rating)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(a.cmp(&b))
+    });
+    let mut tables: Vec<Vec<usize>> = by_rating.chunks(size).map(|table| table.to_vec()).collect();
+    if tables.len() > 1 && tables.last().is_some_and(|table| table.len() < 2) {
+        let leftover = tables.pop().unwrap();
+        tables.last_mut().unwrap().extend(leftover);
+    }
+    tables
+}
+
+// Every rotation of every table, each played the given number of times
+fn seatings(tables: &[Vec<usize>], games: u32) -> Vec<Vec<usize>> {
+    let mut seatings = Vec::new();
+    for table in tables {
+        for rotation in 0..table.len() {
+            let mut seating = table.clone();
+            seating.rotate_left(rotation);
+            for _ in 0..games {
+                seatings.push(seating.clone());
+            }
+        }
+    }
+    seatings
+}
+
+// Groups the bots at a table by how they finished, best first. Everyone still standing when
+// the game ended shares first place, then the rest go in reverse order of elimination.
+fn finishing_order(seating: &[usize], result: &GameResult) -> Vec<Vec<usize>> {
+    let bot = |PlayerID(seat): &PlayerID| seating[*seat as usize];
+    let mut survivors: Vec<usize> = seating.to_vec();
+    survivors.retain(|seat_bot| !result.eliminated.iter().any(|id| bot(id) == *seat_bot));
+    let mut order = vec![survivors];
+    order.extend(result.eliminated.iter().rev().map(|id| vec![bot(id)]));
+    order.retain(|group| !group.is_empty());
+    order
+}
+
+fn present(bots: &[BotEntry], standings: &[Standing]) {
+    let mut leaderboard: Vec<usize> = (0..bots.len()).collect();
+    leaderboard.sort_by(|&a, &b| {
+        standings[b]
+            .rating
+            .rating
+            .partial_cmp(&standings[a].rating.rating)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    let width = bots.iter().map(|bot| bot.name.len()).max().unwrap_or(0).max(3);
+    println!();
+    println!(
+        "{:>4}  {:<width$} {:>7} {:>7} {:>7} {:>8} {:>6} {:>9}",
+        "Rank",
+        "Bot",
+        "Games",
+        "Wins",
+        "Win %",
+        "Rating",
+        "±",
+        "Timeouts",
+        width = width
+    );
+    for (rank, &bot) in leaderboard.iter().enumerate() {
+        let standing = &standings[bot];
+        let win_rate = if standing.games == 0 {
+            0.0
+        } else {
+            100.0 * f64::from(standing.wins) / f64::from(standing.games)
+        };
+        println!(
+            "{:>4}  {:<width$} {:>7} {:>7} {:>7.1} {:>8.0} {:>6.0} {:>9}",
+            rank + 1,
+            bots[bot].name,
+            standing.games,
+            standing.wins,
+            win_rate,
+            standing.rating.rating,
+            // Roughly a 95% interval
+            2.0 * standing.rating.deviation,
+            standing.timeouts,
+            width = width
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn round_robin_seats_every_combination_once() {
+        let tables = round_robin(4, &[2, 3]);
+        assert_eq!(tables.len(), 6 + 4);
+        for (index, table) in tables.iter().enumerate() {
+            assert!(table.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", table);
+            assert!(table.iter().all(|&bot| bot < 4));
+            assert!(!tables[..index].contains(table), "{:?} is seated twice", table);
+        }
+        assert_eq!(round_robin(3, &[3]), vec![vec![0, 1, 2]]);
+    }
+
+    #[test]
+    fn swiss_seats_bots_by_rating_and_never_alone() {
+        let mut standings = vec![Standing::default(); 5];
+        for (bot, rating) in [1400.0, 1600.0, 1500.0, 1700.0, 1300.0].iter().enumerate() {
+            standings[bot].rating.rating = *rating;
+        }
+        assert_eq!(swiss(&standings, 2), vec![vec![3, 1], vec![2, 0, 4]]);
+        assert_eq!(swiss(&standings, 3), vec![vec![3, 1, 2], vec![0, 4]]);
+        assert_eq!(swiss(&standings[..1], 2), vec![vec![0]]);
+    }
+
+    #[test]
+    fn swiss_refuses_rules_its_merged_table_breaks() {
+        let rules = Rules::default()
+            .with_changes(json!({"identities": ["Duke", "Captain"]}).as_object().unwrap())
+            .unwrap();
+        assert!(rules.check(2).is_ok() && rules.check(3).is_err());
+        let config = |bots: &[&str]| {
+            let mut args = vec!["tournament", "--pairing", "swiss", "--table-sizes", "2", "--games", "1"];
+            for bot in bots {
+                args.extend(["--bot", bot]);
+            }
+            TournamentConfig::from_iter_safe(args).unwrap()
+        };
+        assert!(run(&config(&["random", "honest", "dumb"]), rules).is_err());
+        assert!(run(&config(&["random", "honest", "dumb", "random no_block=2"]), rules).is_ok());
+    }
+
+    #[test]
+    fn survivors_share_first_place_and_the_first_out_comes_last() {
+        let result = GameResult {
+            winner: None,
+            eliminated: vec![PlayerID(2), PlayerID(0)],
+            turns: 40,
+            timeouts: BTreeMap::new(),
+        };
+        let order = finishing_order(&[7, 5, 9, 4], &result);
+        assert_eq!(order, vec![vec![5, 4], vec![7], vec![9]]);
+    }
+
+    #[test]
+    fn ratings_move_towards_results() {
+        let mut ratings = vec![Rating::default(); 4];
+        rating::update(&mut ratings, &[vec![0], vec![1, 2]]);
+        assert!(ratings[0].rating > 1500.0);
+        assert!(ratings[1].rating < 1500.0);
+        assert_eq!(ratings[1].rating, ratings[2].rating);
+        // Only the players at the table are rated, and everyone there is now more certain
+        assert_eq!(ratings[3].rating, 1500.0);
+        assert_eq!(ratings[3].deviation, Rating::default().deviation);
+        assert!(ratings[..3].iter().all(|rating| rating.deviation < Rating::default().deviation));
+
+        // Beating a much weaker player teaches less than beating an equal one
+        let mut lopsided = vec![Rating::default(), Rating { rating: 1000.0, ..Rating::default() }];
+        rating::update(&mut lopsided, &[vec![0], vec![1]]);
+        let mut even = vec![Rating::default(); 2];
+        rating::update(&mut even, &[vec![0], vec![1]]);
+        assert!(lopsided[0].rating - 1500.0 < even[0].rating - 1500.0);
+
+        // A draw between equals changes nobody's rating
+        let mut drawn = vec![Rating::default(); 2];
+        rating::update(&mut drawn, &[vec![0, 1]]);
+        assert!((drawn[0].rating - 1500.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,73 @@
+//! Glicko ratings, extended to multiplayer games by scoring every pair of players at the table
+//! as a match of its own.
+
+use std::cmp::Ordering;
+use std::f64::consts::{LN_10, PI};
+
+const INITIAL_RATING: f64 = 1500.0;
+const INITIAL_DEVIATION: f64 = 350.0;
+// Keeps ratings moving even after many games, so they can follow a bot that keeps improving
+const MIN_DEVIATION: f64 = 30.0;
+const Q: f64 = LN_10 / 400.0;
+
+/// A skill estimate and how unsure we are of it. The true rating is within about two
+/// deviations of the estimate.
+#[derive(Debug, Clone, Copy)]
+pub struct Rating {
+    pub rating: f64,
+    pub deviation: f64,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Rating {
+            rating: INITIAL_RATING,
+            deviation: INITIAL_DEVIATION,
+        }
+    }
+}
+
+// Scales down what is learnt from an opponent we are unsure about
+fn g(deviation: f64) -> f64 {
+    1.0 / (1.0 + 3.0 * Q * Q * deviation * deviation / (PI * PI)).sqrt()
+}
+
+// How likely a player is to finish ahead of an opponent
+fn expected_score(player: &Rating, opponent: &Rating) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-g(opponent.deviation) * (player.rating - opponent.rating) / 400.0))
+}
+
+/// Updates the ratings of everyone at a table after a game. `standings` groups the players (as
+/// indexes into `ratings`) by how they finished, best first, with players who finished level in
+/// the same group. Everyone is rated against where the others stood before the game.
+pub fn update(ratings: &mut [Rating], standings: &[Vec<usize>]) {
+    let place = |player: usize| standings.iter().position(|group| group.contains(&player));
+    let players: Vec<usize> = standings.iter().flatten().cloned().collect();
+    let before: Vec<Rating> = ratings.to_vec();
+    for &player in &players {
+        let current = before[player];
+        let mut surprise = 0.0;
+        let mut information = 0.0;
+        for &opponent in players.iter().filter(|&&other| other != player) {
+            let score = match place(player).cmp(&place(opponent)) {
+                Ordering::Less => 1.0,
+                Ordering::Equal => 0.5,
+                Ordering::Greater => 0.0,
+            };
+            let opponent = before[opponent];
+            let expected = expected_score(&current, &opponent);
+            let weight = g(opponent.deviation);
+            surprise += weight * (score - expected);
+            information += weight * weight * expected * (1.0 - expected);
+        }
+        if information == 0.0 {
+            continue;
+        }
+        let d_squared = 1.0 / (Q * Q * information);
+        let precision = 1.0 / (current.deviation * current.deviation) + 1.0 / d_squared;
+        ratings[player] = Rating {
+            rating: current.rating + Q / precision * surprise,
+            deviation: (1.0 / precision).sqrt().max(MIN_DEVIATION),
+        };
+    }
+}
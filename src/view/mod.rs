@@ -0,0 +1,185 @@
+use crate::action::Action;
+use crate::event::Event;
+use crate::prompter::{LocalPrompter, Prompter};
+use crate::rules::Rules;
+use crate::{GameState, Identity, PlayerID};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt;
+
+/// Anything that can turn a PlayerID into a name for display
+pub trait PlayerNames {
+    fn get_player_name(&self, player_id: &PlayerID) -> String;
+}
+
+/// What a player is currently being asked to decide
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PendingDecision {
+    ChooseAction,
+    ForcedCoup,
+    Challenge { actor: PlayerID, action: Action },
+    Block { actor: PlayerID, action: Action },
+    LoseCard,
+    ReplaceCard { card: Identity },
+}
+
+impl PendingDecision {
+    pub fn describe(&self, names: &impl PlayerNames) -> String {
+        match self {
+            PendingDecision::ChooseAction => "Choosing an action".to_string(),
+            PendingDecision::ForcedCoup => "Choosing who to coup".to_string(),
+            PendingDecision::Challenge { actor, action } => format!(
+                "Deciding whether to challenge {}'s {}",
+                names.get_player_name(actor),
+                LocalPrompter::display_action(names, action.clone())
+            ),
+            PendingDecision::Block { actor, action } => format!(
+                "Deciding whether to block {}'s {}",
+                names.get_player_name(actor),
+                LocalPrompter::display_action(names, action.clone())
+            ),
+            PendingDecision::LoseCard => "Choosing a card to lose".to_string(),
+            PendingDecision::ReplaceCard { card } => format!("Deciding whether to keep a {:?}", card),
+        }
+    }
+}
+
+/// The public information about a single seat
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublicPlayerInfo {
+    pub id: PlayerID,
+    pub name: String,
+    pub coins: u8,
+    pub num_lives: u8,
+    pub revealed: Vec<Identity>,
+}
+
+impl PublicPlayerInfo {
+    /// Public info for every seat, in turn order
+    pub fn for_table(state: &GameState) -> Vec<Self> {
+        state
+            .turn_order
+            .iter()
+            .map(|id| {
+                let player_state = state.player_states.get(id).unwrap();
+                PublicPlayerInfo {
+                    id: id.clone(),
+                    name: player_state.get_name(),
+                    coins: player_state.num_coins,
+                    num_lives: player_state.num_lives,
+                    revealed: player_state.lost_lives.clone(),
+                }
+            })
+            .collect()
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.num_lives > 0
+    }
+}
+
+/// Everything a single seat is allowed to know when making a decision. This is built fresh by
+/// the game for every decision, and is the only view of the game players get.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerView<'a> {
+    pub me: PlayerID,
+    pub hand: Vec<Identity>,
+    /// Public info for every seat, in turn order
+    pub players: Vec<PublicPlayerInfo>,
+    pub deck_size: usize,
+    pub turn: u32,
+    /// Every public event so far, oldest first
+    pub history: Cow<'a, [Event]>,
+    pub pending: PendingDecision,
+    pub rules: Rules,
+}
+
+impl<'a> PlayerView<'a> {
+    pub fn new(
+        state: &'a GameState,
+        me: &PlayerID,
+        hand: Vec<Identity>,
+        deck_size: usize,
+        pending: PendingDecision,
+    ) -> Self {
+        Self {
+            me: me.clone(),
+            hand,
+            players: PublicPlayerInfo::for_table(state),
+            deck_size,
+            turn: state.turn,
+            history: Cow::Borrowed(&state.history),
+            pending,
+            rules: state.rules,
+        }
+    }
+
+    /// Copies the borrowed parts of the view, so it can be sent to another thread
+    pub fn into_owned(self) -> PlayerView<'static> {
+        PlayerView {
+            me: self.me,
+            hand: self.hand,
+            players: self.players,
+            deck_size: self.deck_size,
+            turn: self.turn,
+            history: Cow::Owned(self.history.into_owned()),
+            pending: self.pending,
+            rules: self.rules,
+        }
+    }
+
+    pub fn player(&self, player_id: &PlayerID) -> &PublicPlayerInfo {
+        self.players
+            .iter()
+            .find(|info| &info.id == player_id)
+            .expect("Player is not at this table")
+    }
+
+    pub fn active_players(&self) -> Vec<PlayerID> {
+        self.players
+            .iter()
+            .filter(|info| info.is_alive())
+            .map(|info| info.id.clone())
+            .collect()
+    }
+
+    pub fn other_active_players(&self) -> Vec<PlayerID> {
+        self.active_players()
+            .into_iter()
+            .filter(|id| id != &self.me)
+            .collect()
+    }
+}
+
+impl PlayerNames for PlayerView<'_> {
+    fn get_player_name(&self, player_id: &PlayerID) -> String {
+        self.player(player_id).name.clone()
+    }
+}
+
+impl fmt::Display for PublicPlayerInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}:", self.name)?;
+        writeln!(f, "\tLives: {}", self.num_lives)?;
+        writeln!(f, "\tCoins: {}", self.coins)?;
+        if !self.revealed.is_empty() {
+            write!(f, "\tLost Identities: ")?;
+            for identity in &self.revealed {
+                write!(f, "{:?} ", identity)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for PlayerView<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Turn {} - {} cards left in the deck", self.turn, self.deck_size)?;
+        for info in &self.players {
+            writeln!(f, "{}", info)?;
+        }
+        writeln!(f, "{}", self.pending.describe(self))
+    }
+}